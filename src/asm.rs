@@ -0,0 +1,878 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::chip_8::{MEMORY_SIZE, PROGRAM_START};
+
+const MAX_INCLUDE_DEPTH: usize = 16;
+const MAX_MACRO_DEPTH: usize = 16;
+
+// Assembles source written in the mnemonics from Cowgod's reference (the
+// disassembler's default syntax) into a ROM that loads at 0x200.
+//
+//     ; comments run to the end of the line
+//     SPEED = 3                 ; constants can be any expression
+//     include "sprites.asm"     ; relative to the including file
+//
+//     macro move x, y           ; parameters are replaced wherever they appear
+//         LD V0, x
+//         LD V1, y
+//     endm
+//
+//     main:
+//         move 10, SPEED * 2
+//         LD I, ship
+//         DRW V0, V1, ship_end - ship
+//     loop: JP loop
+//     ship: db 0x18, 0x3C, 0b01111110
+//     ship_end:
+//
+// Expressions have numbers (decimal, 0x hex, 0b binary, 'c' characters),
+// labels, constants, parentheses and the C operators + - * / % & | ^ ~ << >>.
+// db takes bytes and "strings", dw takes 16 bit big-endian words.
+// I, DT, ST, K, F, HF, B, R and V0 to VF are register names, not symbols
+pub fn assemble(source: &str) -> Result<Assembly, AsmError> {
+    assemble_lines(load("<source>", source, 0)?)
+}
+
+// Same as assemble, reading the source from `path`
+pub fn assemble_file(path: &str) -> Result<Assembly, AsmError> {
+    let source = fs::read_to_string(path).map_err(|error| AsmError {
+        file: path.to_string(),
+        line: 0,
+        column: 0,
+        message: format!("could not read the file: {}", error),
+    })?;
+    assemble_lines(load(path, &source, 0)?)
+}
+
+// The assembled ROM and where its labels ended up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assembly {
+    pub rom: Vec<u8>,
+    pub symbols: Symbols,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.file, self.message)
+        } else {
+            write!(f, "{}:{}:{}: {}", self.file, self.line, self.column, self.message)
+        }
+    }
+}
+
+impl Error for AsmError {}
+
+// Label addresses, written next to the ROM so the debugger can show names.
+// The file has one "address name" line per label, the address in hex:
+//
+//     0200 main
+//     0212 loop
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Symbols {
+    addresses: BTreeMap<String, u16>,
+}
+
+impl Symbols {
+    pub fn new() -> Symbols {
+        Symbols::default()
+    }
+
+    pub fn insert(&mut self, name: &str, address: u16) {
+        self.addresses.insert(name.to_string(), address);
+    }
+
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.addresses.get(name).copied()
+    }
+
+    // The name for an address. With several to choose from, the first alphabetically wins
+    pub fn name(&self, address: u16) -> Option<&str> {
+        self.addresses.iter().find(|(_, a)| **a == address).map(|(name, _)| name.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u16)> {
+        self.addresses.iter().map(|(name, address)| (name.as_str(), *address))
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut symbols: Vec<(u16, &str)> = self.iter().map(|(name, address)| (address, name)).collect();
+        symbols.sort();
+        symbols.iter().map(|(address, name)| format!("{:04X} {}\n", address, name)).collect()
+    }
+
+    // Blank lines and lines starting with # are skipped
+    pub fn from_text(text: &str) -> Result<Symbols, String> {
+        let mut symbols = Symbols::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (address, name) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| format!("line {}: expected 'address name'", i + 1))?;
+            let address = u16::from_str_radix(address, 16)
+                .map_err(|_| format!("line {}: bad address '{}'", i + 1, address))?;
+            symbols.insert(name.trim(), address);
+        }
+
+        Ok(symbols)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_text()).map_err(|error| format!("Could not write {}: {}", path, error))
+    }
+
+    pub fn load(path: &str) -> Result<Symbols, String> {
+        let text = fs::read_to_string(path).map_err(|error| format!("Could not read {}: {}", path, error))?;
+        Symbols::from_text(&text).map_err(|error| format!("{}: {}", path, error))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    Ident(String),
+    Number(i64),
+    Str(Vec<u8>),
+    Punct(&'static str),
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Kind::Ident(name) => write!(f, "{}", name),
+            Kind::Number(value) => write!(f, "{}", value),
+            Kind::Str(_) => write!(f, "string"),
+            Kind::Punct(punct) => write!(f, "{}", punct),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: Kind,
+    column: usize,
+}
+
+impl Token {
+    fn ident(&self) -> Option<&str> {
+        match &self.kind {
+            Kind::Ident(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    fn is(&self, punct: &str) -> bool {
+        matches!(self.kind, Kind::Punct(p) if p == punct)
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        self.ident().is_some_and(|name| name.eq_ignore_ascii_case(keyword))
+    }
+}
+
+// A line of source after tokenizing, remembering where it came from
+#[derive(Debug, Clone)]
+struct Line {
+    file: Rc<str>,
+    number: usize,
+    tokens: Vec<Token>,
+}
+
+impl Line {
+    fn error(&self, column: usize, message: impl Into<String>) -> AsmError {
+        AsmError { file: self.file.to_string(), line: self.number, column, message: message.into() }
+    }
+
+    // Just past the last token, for complaints about something missing
+    fn end_column(&self) -> usize {
+        self.tokens.last().map_or(1, |token| token.column + 1)
+    }
+
+    // The label at the start of the line, if any, and the rest of it
+    fn split_label(&self) -> (Option<&Token>, &[Token]) {
+        match self.tokens.as_slice() {
+            [label, colon, rest @ ..] if label.ident().is_some() && colon.is(":") => (Some(label), rest),
+            tokens => (None, tokens),
+        }
+    }
+}
+
+fn tokenize(text: &str, file: &Rc<str>, number: usize) -> Result<Line, AsmError> {
+    let mut line = Line { file: file.clone(), number, tokens: Vec::new() };
+    let chars: Vec<char> = text.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c == ';' {
+            break;
+        }
+
+        let kind = if c.is_ascii_alphabetic() || c == '_' || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '.') {
+                i += 1;
+            }
+            Kind::Ident(chars[start..i].iter().collect())
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let digits: String = chars[start..i].iter().filter(|c| **c != '_').collect();
+            let parsed = match digits.get(..2) {
+                Some("0x") | Some("0X") => i64::from_str_radix(&digits[2..], 16),
+                Some("0b") | Some("0B") => i64::from_str_radix(&digits[2..], 2),
+                _ => digits.parse(),
+            };
+            Kind::Number(parsed.map_err(|_| line.error(column, format!("bad number '{}'", digits)))?)
+        } else if c == '"' {
+            i += 1;
+            let mut bytes = Vec::new();
+            loop {
+                match chars.get(i) {
+                    None => return Err(line.error(column, "unterminated string")),
+                    Some('"') => break,
+                    Some('\\') => {
+                        let escaped = match chars.get(i + 1) {
+                            Some('n') => '\n',
+                            Some('0') => '\0',
+                            Some(c @ ('\\' | '"')) => *c,
+                            _ => return Err(line.error(i + 1, "bad escape in string")),
+                        };
+                        bytes.push(escaped as u8);
+                        i += 2;
+                    },
+                    Some(c) if c.is_ascii() => {
+                        bytes.push(*c as u8);
+                        i += 1;
+                    },
+                    Some(_) => return Err(line.error(i + 1, "strings can only hold ASCII")),
+                }
+            }
+            i += 1;
+            Kind::Str(bytes)
+        } else if c == '\'' {
+            match (chars.get(i + 1), chars.get(i + 2)) {
+                (Some(c), Some('\'')) if c.is_ascii() => {
+                    i += 3;
+                    Kind::Number(*c as i64)
+                },
+                _ => return Err(line.error(column, "bad character literal")),
+            }
+        } else {
+            let two: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let punct = match two.as_str() {
+                "<<" => "<<",
+                ">>" => ">>",
+                _ => match c {
+                    ',' => ",",
+                    ':' => ":",
+                    '(' => "(",
+                    ')' => ")",
+                    '[' => "[",
+                    ']' => "]",
+                    '+' => "+",
+                    '-' => "-",
+                    '*' => "*",
+                    '/' => "/",
+                    '%' => "%",
+                    '&' => "&",
+                    '|' => "|",
+                    '^' => "^",
+                    '~' => "~",
+                    '=' => "=",
+                    _ => return Err(line.error(column, format!("unexpected character '{}'", c))),
+                },
+            };
+            i += punct.len();
+            Kind::Punct(punct)
+        };
+
+        line.tokens.push(Token { kind, column });
+    }
+
+    Ok(line)
+}
+
+// Tokenizes a file, pulling in its includes
+fn load(path: &str, source: &str, depth: usize) -> Result<Vec<Line>, AsmError> {
+    let file: Rc<str> = Rc::from(path);
+    let mut lines = Vec::new();
+
+    for (i, text) in source.lines().enumerate() {
+        let line = tokenize(text, &file, i + 1)?;
+
+        match line.tokens.as_slice() {
+            [keyword, name] if keyword.is_keyword("include") => {
+                let name = match &name.kind {
+                    Kind::Str(name) => String::from_utf8_lossy(name).to_string(),
+                    _ => return Err(line.error(name.column, "include needs a \"file name\"")),
+                };
+                if depth >= MAX_INCLUDE_DEPTH {
+                    return Err(line.error(keyword.column, "includes are nested too deeply"));
+                }
+
+                let include = Path::new(path).parent().unwrap_or(Path::new("")).join(&name);
+                let include = include.to_string_lossy().to_string();
+                let source = fs::read_to_string(&include)
+                    .map_err(|error| line.error(keyword.column, format!("could not read {}: {}", include, error)))?;
+                lines.extend(load(&include, &source, depth + 1)?);
+            },
+            [keyword, ..] if keyword.is_keyword("include") => {
+                return Err(line.error(keyword.column, "include needs a \"file name\""));
+            },
+            _ => lines.push(line),
+        }
+    }
+
+    Ok(lines)
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Line>,
+}
+
+// Splits operands on the commas outside parentheses
+fn split_operands<'a>(line: &Line, tokens: &'a [Token]) -> Result<Vec<&'a [Token]>, AsmError> {
+    if tokens.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut operands = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match &token.kind {
+            Kind::Punct("(") => depth += 1,
+            Kind::Punct(")") => depth -= 1,
+            Kind::Punct(",") if depth == 0 => {
+                operands.push(&tokens[start..i]);
+                start = i + 1;
+            },
+            _ => (),
+        }
+    }
+    operands.push(&tokens[start..]);
+
+    if let Some(empty) = operands.iter().position(|operand| operand.is_empty()) {
+        let column = if empty == 0 { tokens[0].column } else { operands[empty - 1].last().map_or(1, |t| t.column + 1) };
+        return Err(line.error(column, "missing operand"));
+    }
+    Ok(operands)
+}
+
+// Pulls out the macro definitions and expands every use of them
+fn expand_macros(lines: Vec<Line>) -> Result<Vec<Line>, AsmError> {
+    let mut macros = HashMap::new();
+    let mut rest = Vec::new();
+    let mut lines = lines.into_iter();
+
+    while let Some(line) = lines.next() {
+        let (_, statement) = line.split_label();
+        match statement.first() {
+            Some(keyword) if keyword.is_keyword("macro") => {
+                let name = match statement.get(1).and_then(|token| token.ident()) {
+                    Some(name) => name.to_string(),
+                    None => return Err(line.error(line.end_column(), "macro needs a name")),
+                };
+
+                let mut params = Vec::new();
+                for param in split_operands(&line, &statement[2..])? {
+                    match param {
+                        [token] if token.ident().is_some() => params.push(token.ident().unwrap_or_default().to_string()),
+                        _ => return Err(line.error(param[0].column, "macro parameters must be plain names")),
+                    }
+                }
+
+                let mut body = Vec::new();
+                loop {
+                    match lines.next() {
+                        Some(body_line) if body_line.tokens.first().is_some_and(|token| token.is_keyword("endm")) => break,
+                        Some(body_line) if body_line.tokens.iter().any(|token| token.is_keyword("macro")) => {
+                            return Err(body_line.error(body_line.tokens[0].column, "macros can't be defined inside macros"));
+                        },
+                        Some(body_line) => body.push(body_line),
+                        None => return Err(line.error(keyword.column, format!("macro {} has no endm", name))),
+                    }
+                }
+
+                if macros.insert(name.clone(), Macro { params, body }).is_some() {
+                    return Err(line.error(statement[1].column, format!("macro {} is already defined", name)));
+                }
+            },
+            Some(keyword) if keyword.is_keyword("endm") => {
+                return Err(line.error(keyword.column, "endm without a macro"));
+            },
+            _ => rest.push(line),
+        }
+    }
+
+    let mut expanded = Vec::new();
+    for line in rest {
+        expand_line(line, &macros, 0, &mut expanded)?;
+    }
+    Ok(expanded)
+}
+
+fn expand_line(line: Line, macros: &HashMap<String, Macro>, depth: usize, out: &mut Vec<Line>) -> Result<(), AsmError> {
+    let (label, statement) = line.split_label();
+    let found = statement.first().and_then(|token| token.ident()).and_then(|name| macros.get(name));
+    let definition = match found {
+        Some(definition) => definition,
+        None => {
+            out.push(line);
+            return Ok(());
+        },
+    };
+
+    if depth >= MAX_MACRO_DEPTH {
+        return Err(line.error(statement[0].column, "macros are nested too deeply"));
+    }
+
+    let args = split_operands(&line, &statement[1..])?;
+    if args.len() != definition.params.len() {
+        return Err(line.error(
+            statement[0].column,
+            format!("expected {} macro arguments, got {}", definition.params.len(), args.len()),
+        ));
+    }
+
+    // The label on the invocation marks where the expansion starts
+    if let Some(label) = label {
+        out.push(Line { tokens: vec![label.clone(), Token { kind: Kind::Punct(":"), column: label.column }], ..line.clone() });
+    }
+
+    for body_line in &definition.body {
+        let mut tokens = Vec::new();
+        for token in &body_line.tokens {
+            match token.ident().and_then(|name| definition.params.iter().position(|param| param == name)) {
+                // Arguments take the place of the parameter, errors point there
+                Some(index) => tokens.extend(args[index].iter().map(|arg| Token { kind: arg.kind.clone(), column: token.column })),
+                None => tokens.push(token.clone()),
+            }
+        }
+        expand_line(Line { tokens, ..body_line.clone() }, macros, depth + 1, out)?;
+    }
+
+    Ok(())
+}
+
+enum Symbol<'a> {
+    Label(u16),
+    Constant(&'a Line, &'a [Token]),
+}
+
+const SPECIAL_REGISTERS: [&str; 8] = ["I", "DT", "ST", "K", "F", "HF", "B", "R"];
+
+fn register(name: &str) -> Option<u8> {
+    match name.as_bytes() {
+        [b'v' | b'V', digit] => (*digit as char).to_digit(16).map(|digit| digit as u8),
+        _ => None,
+    }
+}
+
+fn is_reserved(name: &str) -> bool {
+    register(name).is_some() || SPECIAL_REGISTERS.iter().any(|special| special.eq_ignore_ascii_case(name))
+}
+
+#[derive(Clone, Copy)]
+enum Operand<'a> {
+    Register(u8),
+    // I, DT, ST, K, F, HF, B, R or [I]
+    Special(&'static str),
+    Range(u8, u8),
+    Long(&'a [Token]),
+    Value(&'a [Token]),
+}
+
+fn classify(tokens: &[Token]) -> Operand<'_> {
+    let ident = |token: &Token| token.ident().map(|name| name.to_ascii_uppercase());
+
+    match tokens {
+        [token] => {
+            if let Some(x) = token.ident().and_then(register) {
+                return Operand::Register(x);
+            }
+            match SPECIAL_REGISTERS.iter().find(|special| ident(token).as_deref() == Some(**special)) {
+                Some(special) => Operand::Special(special),
+                None => Operand::Value(tokens),
+            }
+        },
+        [open, i, close] if open.kind == Kind::Punct("[") && ident(i).as_deref() == Some("I") && close.kind == Kind::Punct("]") => {
+            Operand::Special("[I]")
+        },
+        [x, dash, y] if dash.is("-") => match (x.ident().and_then(register), y.ident().and_then(register)) {
+            (Some(x), Some(y)) => Operand::Range(x, y),
+            _ => Operand::Value(tokens),
+        },
+        [long, rest @ ..] if long.is_keyword("long") && !rest.is_empty() => Operand::Long(rest),
+        _ => Operand::Value(tokens),
+    }
+}
+
+enum Statement<'a> {
+    Empty,
+    Constant(&'a Token, &'a [Token]),
+    Data { word: bool, values: Vec<&'a [Token]> },
+    Instruction { mnemonic: &'a Token, operands: Vec<&'a [Token]> },
+}
+
+fn parse_statement<'a>(line: &'a Line, tokens: &'a [Token]) -> Result<Statement<'a>, AsmError> {
+    match tokens {
+        [] => Ok(Statement::Empty),
+        [name, equals, value @ ..] if name.ident().is_some() && equals.is("=") => {
+            if value.is_empty() {
+                return Err(line.error(equals.column + 1, "missing value"));
+            }
+            Ok(Statement::Constant(name, value))
+        },
+        [directive, values @ ..] if directive.is_keyword("db") || directive.is_keyword("dw") => {
+            let values = split_operands(line, values)?;
+            if values.is_empty() {
+                return Err(line.error(line.end_column(), "missing data"));
+            }
+            Ok(Statement::Data { word: directive.is_keyword("dw"), values })
+        },
+        [mnemonic, operands @ ..] => match mnemonic.ident() {
+            Some(_) => Ok(Statement::Instruction { mnemonic, operands: split_operands(line, operands)? }),
+            None => Err(line.error(mnemonic.column, format!("expected an instruction, got '{}'", mnemonic.kind))),
+        },
+    }
+}
+
+impl Statement<'_> {
+    fn size(&self) -> usize {
+        match self {
+            Statement::Empty | Statement::Constant(..) => 0,
+            Statement::Data { word: false, values } => values
+                .iter()
+                .map(|value| match value {
+                    [Token { kind: Kind::Str(bytes), .. }] => bytes.len(),
+                    _ => 1,
+                })
+                .sum(),
+            Statement::Data { word: true, values } => values.len() * 2,
+            Statement::Instruction { mnemonic, operands } => {
+                let long = operands.get(1).is_some_and(|operand| matches!(classify(operand), Operand::Long(_)));
+                if mnemonic.is_keyword("ld") && long { 4 } else { 2 }
+            },
+        }
+    }
+}
+
+struct Assembler<'a> {
+    symbols: HashMap<String, Symbol<'a>>,
+}
+
+impl<'a> Assembler<'a> {
+    fn define(&mut self, line: &Line, name: &Token, symbol: Symbol<'a>) -> Result<(), AsmError> {
+        let text = name.ident().unwrap_or_default();
+        if is_reserved(text) {
+            return Err(line.error(name.column, format!("'{}' is a register name", text)));
+        }
+        if self.symbols.insert(text.to_string(), symbol).is_some() {
+            return Err(line.error(name.column, format!("'{}' is already defined", text)));
+        }
+        Ok(())
+    }
+
+    fn evaluate(&self, line: &Line, tokens: &[Token]) -> Result<i64, AsmError> {
+        let mut resolving = Vec::new();
+        self.evaluate_nested(line, tokens, &mut resolving)
+    }
+
+    fn evaluate_nested(&self, line: &Line, tokens: &[Token], resolving: &mut Vec<String>) -> Result<i64, AsmError> {
+        let mut parser = Expression { assembler: self, line, tokens, position: 0, resolving };
+        let value = parser.binary(0)?;
+        match tokens.get(parser.position) {
+            Some(token) => Err(line.error(token.column, format!("unexpected '{}'", token.kind))),
+            None => Ok(value),
+        }
+    }
+
+    fn resolve(&self, line: &Line, token: &Token, resolving: &mut Vec<String>) -> Result<i64, AsmError> {
+        let name = token.ident().unwrap_or_default();
+        match self.symbols.get(name) {
+            Some(Symbol::Label(address)) => Ok(*address as i64),
+            Some(Symbol::Constant(definition, value)) => {
+                if resolving.iter().any(|other| other == name) {
+                    return Err(line.error(token.column, format!("'{}' is defined in terms of itself", name)));
+                }
+                resolving.push(name.to_string());
+                let value = self.evaluate_nested(definition, value, resolving)?;
+                resolving.pop();
+                Ok(value)
+            },
+            None => Err(line.error(token.column, format!("unknown symbol '{}'", name))),
+        }
+    }
+
+    // An expression that has to fit in `bits` bits. When `signed`, negative numbers
+    // are allowed down to the signed minimum and stored as two's complement
+    fn value(&self, line: &Line, tokens: &[Token], bits: u32, signed: bool) -> Result<u16, AsmError> {
+        let value = self.evaluate(line, tokens)?;
+        let max = (1i64 << bits) - 1;
+        let min = if signed { -(1i64 << (bits - 1)) } else { 0 };
+        if value < min || value > max {
+            return Err(line.error(tokens[0].column, format!("{} doesn't fit in {} bits", value, bits)));
+        }
+        Ok((value & max) as u16)
+    }
+
+    // Bytes may be negative, nibbles and addresses may not
+    fn operand_value(&self, line: &Line, operand: &Operand, column: usize, bits: u32) -> Result<u16, AsmError> {
+        match operand {
+            Operand::Value(tokens) => self.value(line, tokens, bits, bits == 8),
+            _ => Err(line.error(column, "expected a number or a label")),
+        }
+    }
+
+    fn encode(&self, line: &Line, mnemonic: &Token, operands: &[&[Token]]) -> Result<Vec<u16>, AsmError> {
+        use Operand::*;
+
+        let name = mnemonic.ident().unwrap_or_default().to_ascii_uppercase();
+        let columns: Vec<usize> = operands.iter().map(|operand| operand[0].column).collect();
+        let ops: Vec<Operand> = operands.iter().map(|operand| classify(operand)).collect();
+
+        let nibble = |i: usize| self.operand_value(line, &ops[i], columns[i], 4);
+        let byte = |i: usize| self.operand_value(line, &ops[i], columns[i], 8);
+        let address = |i: usize| self.operand_value(line, &ops[i], columns[i], 12);
+        let xy = |x: u8, y: u8| (x as u16) << 8 | (y as u16) << 4;
+        let x = |x: u8| (x as u16) << 8;
+
+        let word = match (name.as_str(), ops.as_slice()) {
+            ("CLS", []) => 0x00E0,
+            ("RET", []) => 0x00EE,
+            ("SCD", [_]) => 0x00C0 | nibble(0)?,
+            ("SCU", [_]) => 0x00D0 | nibble(0)?,
+            ("SCR", []) => 0x00FB,
+            ("SCL", []) => 0x00FC,
+            ("EXIT", []) => 0x00FD,
+            ("LOW", []) => 0x00FE,
+            ("HIGH", []) => 0x00FF,
+            ("JP", [Register(0), _]) => 0xB000 | address(1)?,
+            ("JP", [_]) => 0x1000 | address(0)?,
+            ("CALL", [_]) => 0x2000 | address(0)?,
+            ("SE", [Register(vx), Register(vy)]) => 0x5000 | xy(*vx, *vy),
+            ("SE", [Register(vx), _]) => 0x3000 | x(*vx) | byte(1)?,
+            ("SNE", [Register(vx), Register(vy)]) => 0x9000 | xy(*vx, *vy),
+            ("SNE", [Register(vx), _]) => 0x4000 | x(*vx) | byte(1)?,
+            ("SAVE", [Range(vx, vy)]) => 0x5002 | xy(*vx, *vy),
+            ("LOAD", [Range(vx, vy)]) => 0x5003 | xy(*vx, *vy),
+            ("LD", [Special("I"), Long(tokens)]) => {
+                let target = self.value(line, tokens, 16, false)?;
+                return Ok(vec![0xF000, target]);
+            },
+            ("LD", [Special("I"), _]) => 0xA000 | address(1)?,
+            ("LD", [Register(vx), Register(vy)]) => 0x8000 | xy(*vx, *vy),
+            ("LD", [Register(vx), Special("DT")]) => 0xF007 | x(*vx),
+            ("LD", [Register(vx), Special("K")]) => 0xF00A | x(*vx),
+            ("LD", [Register(vx), Special("[I]")]) => 0xF065 | x(*vx),
+            ("LD", [Register(vx), Special("R")]) => 0xF085 | x(*vx),
+            ("LD", [Register(vx), Value(_)]) => 0x6000 | x(*vx) | byte(1)?,
+            ("LD", [Special("DT"), Register(vx)]) => 0xF015 | x(*vx),
+            ("LD", [Special("ST"), Register(vx)]) => 0xF018 | x(*vx),
+            ("LD", [Special("F"), Register(vx)]) => 0xF029 | x(*vx),
+            ("LD", [Special("HF"), Register(vx)]) => 0xF030 | x(*vx),
+            ("LD", [Special("B"), Register(vx)]) => 0xF033 | x(*vx),
+            ("LD", [Special("[I]"), Register(vx)]) => 0xF055 | x(*vx),
+            ("LD", [Special("R"), Register(vx)]) => 0xF075 | x(*vx),
+            ("ADD", [Register(vx), Register(vy)]) => 0x8004 | xy(*vx, *vy),
+            ("ADD", [Register(vx), Value(_)]) => 0x7000 | x(*vx) | byte(1)?,
+            ("ADD", [Special("I"), Register(vx)]) => 0xF01E | x(*vx),
+            ("OR", [Register(vx), Register(vy)]) => 0x8001 | xy(*vx, *vy),
+            ("AND", [Register(vx), Register(vy)]) => 0x8002 | xy(*vx, *vy),
+            ("XOR", [Register(vx), Register(vy)]) => 0x8003 | xy(*vx, *vy),
+            ("SUB", [Register(vx), Register(vy)]) => 0x8005 | xy(*vx, *vy),
+            ("SHR", [Register(vx)]) => 0x8006 | xy(*vx, *vx),
+            ("SHR", [Register(vx), Register(vy)]) => 0x8006 | xy(*vx, *vy),
+            ("SUBN", [Register(vx), Register(vy)]) => 0x8007 | xy(*vx, *vy),
+            ("SHL", [Register(vx)]) => 0x800E | xy(*vx, *vx),
+            ("SHL", [Register(vx), Register(vy)]) => 0x800E | xy(*vx, *vy),
+            ("RND", [Register(vx), _]) => 0xC000 | x(*vx) | byte(1)?,
+            ("DRW", [Register(vx), Register(vy), _]) => 0xD000 | xy(*vx, *vy) | nibble(2)?,
+            ("SKP", [Register(vx)]) => 0xE09E | x(*vx),
+            ("SKNP", [Register(vx)]) => 0xE0A1 | x(*vx),
+            ("PLANE", [_]) => 0xF001 | nibble(0)? << 8,
+            ("AUDIO", []) => 0xF002,
+            ("PITCH", [Register(vx)]) => 0xF03A | x(*vx),
+            (
+                "CLS" | "RET" | "SCD" | "SCU" | "SCR" | "SCL" | "EXIT" | "LOW" | "HIGH" | "JP" | "CALL" | "SE" | "SNE"
+                | "SAVE" | "LOAD" | "LD" | "ADD" | "OR" | "AND" | "XOR" | "SUB" | "SHR" | "SUBN" | "SHL" | "RND" | "DRW"
+                | "SKP" | "SKNP" | "PLANE" | "AUDIO" | "PITCH",
+                _,
+            ) => return Err(line.error(mnemonic.column, format!("bad operands for {}", name))),
+            _ => return Err(line.error(mnemonic.column, format!("unknown instruction '{}'", mnemonic.kind))),
+        };
+
+        Ok(vec![word])
+    }
+}
+
+struct Expression<'a, 'b> {
+    assembler: &'b Assembler<'a>,
+    line: &'b Line,
+    tokens: &'b [Token],
+    position: usize,
+    resolving: &'b mut Vec<String>,
+}
+
+// Binary operators from loosest to tightest binding
+const PRECEDENCE: [&[&str]; 6] = [&["|"], &["^"], &["&"], &["<<", ">>"], &["+", "-"], &["*", "/", "%"]];
+
+impl Expression<'_, '_> {
+    fn binary(&mut self, level: usize) -> Result<i64, AsmError> {
+        if level == PRECEDENCE.len() {
+            return self.unary();
+        }
+
+        let mut value = self.binary(level + 1)?;
+        while let Some(Token { kind: Kind::Punct(op), column }) = self.tokens.get(self.position) {
+            if !PRECEDENCE[level].contains(op) {
+                break;
+            }
+            let (op, column) = (*op, *column);
+            self.position += 1;
+
+            let rhs = self.binary(level + 1)?;
+            value = match op {
+                "|" => value | rhs,
+                "^" => value ^ rhs,
+                "&" => value & rhs,
+                "<<" => value.wrapping_shl(rhs as u32),
+                ">>" => value.wrapping_shr(rhs as u32),
+                "+" => value.wrapping_add(rhs),
+                "-" => value.wrapping_sub(rhs),
+                "*" => value.wrapping_mul(rhs),
+                _ if rhs == 0 => return Err(self.line.error(column, "division by zero")),
+                "/" => value.wrapping_div(rhs),
+                _ => value.wrapping_rem(rhs),
+            };
+        }
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<i64, AsmError> {
+        let token = match self.tokens.get(self.position) {
+            Some(token) => token,
+            None => {
+                let column = self.tokens.last().map_or(self.line.end_column(), |token| token.column + 1);
+                return Err(self.line.error(column, "expected a value"));
+            },
+        };
+        self.position += 1;
+
+        match &token.kind {
+            Kind::Number(value) => Ok(*value),
+            Kind::Ident(_) => self.assembler.resolve(self.line, token, self.resolving),
+            Kind::Punct("-") => Ok(self.unary()?.wrapping_neg()),
+            Kind::Punct("+") => self.unary(),
+            Kind::Punct("~") => Ok(!self.unary()?),
+            Kind::Punct("(") => {
+                let value = self.binary(0)?;
+                match self.tokens.get(self.position) {
+                    Some(close) if close.is(")") => {
+                        self.position += 1;
+                        Ok(value)
+                    },
+                    _ => Err(self.line.error(token.column, "unclosed parenthesis")),
+                }
+            },
+            kind => Err(self.line.error(token.column, format!("unexpected '{}'", kind))),
+        }
+    }
+}
+
+fn assemble_lines(lines: Vec<Line>) -> Result<Assembly, AsmError> {
+    let lines = expand_macros(lines)?;
+    let mut assembler = Assembler { symbols: HashMap::new() };
+
+    // First pass: find out where everything goes
+    let mut statements = Vec::new();
+    let mut address = PROGRAM_START;
+    for line in &lines {
+        let (label, tokens) = line.split_label();
+        if let Some(label) = label {
+            assembler.define(line, label, Symbol::Label(address as u16))?;
+        }
+
+        let statement = parse_statement(line, tokens)?;
+        if let Statement::Constant(name, value) = statement {
+            assembler.define(line, name, Symbol::Constant(line, value))?;
+        }
+
+        address += statement.size();
+        if address > MEMORY_SIZE {
+            return Err(line.error(1, "the program doesn't fit in memory"));
+        }
+        statements.push((line, statement));
+    }
+
+    // Second pass: now every label is known, fill in the bytes
+    let mut rom = Vec::with_capacity(address - PROGRAM_START);
+    for (line, statement) in statements {
+        match statement {
+            Statement::Empty => (),
+            Statement::Constant(name, _) => {
+                // Checked even when unused, so mistakes don't hide
+                assembler.resolve(line, name, &mut Vec::new())?;
+            },
+            Statement::Data { word, values } => {
+                for value in values {
+                    match (value, word) {
+                        ([Token { kind: Kind::Str(bytes), .. }], false) => rom.extend_from_slice(bytes),
+                        (_, false) => rom.push(assembler.value(line, value, 8, true)? as u8),
+                        (_, true) => rom.extend_from_slice(&assembler.value(line, value, 16, true)?.to_be_bytes()),
+                    }
+                }
+            },
+            Statement::Instruction { mnemonic, operands } => {
+                for word in assembler.encode(line, mnemonic, &operands)? {
+                    rom.extend_from_slice(&word.to_be_bytes());
+                }
+            },
+        }
+    }
+
+    let mut symbols = Symbols::new();
+    for (name, symbol) in &assembler.symbols {
+        if let Symbol::Label(address) = symbol {
+            symbols.insert(name, *address);
+        }
+    }
+
+    Ok(Assembly { rom, symbols })
+}
@@ -0,0 +1,213 @@
+use std::f32::consts::PI;
+use std::str::FromStr;
+
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::AudioSubsystem;
+
+const SAMPLE_RATE: i32 = 44100;
+// How long the volume takes to fade in or out, short enough to sound instant
+// but long enough that starting and stopping the tone doesn't click
+const RAMP_SECONDS: f32 = 0.005;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Square,
+    Triangle,
+    Sine,
+}
+
+impl FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Waveform, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "square" => Ok(Waveform::Square),
+            "triangle" => Ok(Waveform::Triangle),
+            "sine" => Ok(Waveform::Sine),
+            _ => Err(format!("Unknown waveform '{}', expected square, triangle or sine", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AudioSettings {
+    pub frequency: f32,
+    pub volume: f32,
+    pub waveform: Waveform,
+    pub muted: bool,
+}
+
+impl Default for AudioSettings {
+    fn default() -> AudioSettings {
+        AudioSettings {
+            frequency: 440.0,
+            volume: 0.25,
+            waveform: Waveform::Square,
+            muted: false,
+        }
+    }
+}
+
+struct Tone {
+    settings: AudioSettings,
+    active: bool,
+    // XO-CHIP pattern buffer, played one bit per step instead of the waveform
+    pattern: Option<[u8; 16]>,
+    pattern_rate: f32,
+    // Position within the current waveform period (or pattern) from 0 to 1
+    phase: f32,
+    gain: f32,
+}
+
+impl Tone {
+    fn new(settings: AudioSettings) -> Tone {
+        Tone {
+            settings,
+            active: false,
+            pattern: None,
+            pattern_rate: 4000.0,
+            phase: 0.0,
+            gain: 0.0,
+        }
+    }
+
+    fn set_sound(&mut self, active: bool, pattern: &[u8; 16], pitch: u8) {
+        self.active = active;
+
+        // An all-zero buffer means the ROM never loaded a pattern, so use the plain tone
+        self.pattern = if pattern.iter().any(|b| *b != 0) { Some(*pattern) } else { None };
+        self.pattern_rate = 4000.0 * 2f32.powf((pitch as f32 - 64.0) / 48.0);
+    }
+
+    fn sample(&self) -> f32 {
+        if let Some(pattern) = &self.pattern {
+            let bit = (self.phase * 128.0) as usize % 128;
+            let on = pattern[bit / 8] & (0x80 >> (bit % 8)) != 0;
+            return if on { 1.0 } else { -1.0 };
+        }
+
+        match self.settings.waveform {
+            Waveform::Square => if self.phase < 0.5 { 1.0 } else { -1.0 },
+            Waveform::Triangle => 1.0 - 4.0 * (self.phase - 0.5).abs(),
+            Waveform::Sine => (self.phase * 2.0 * PI).sin(),
+        }
+    }
+}
+
+impl AudioCallback for Tone {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        let target = if self.active && !self.settings.muted { self.settings.volume } else { 0.0 };
+        let ramp_step = self.settings.volume.max(0.01) / (RAMP_SECONDS * SAMPLE_RATE as f32);
+
+        let phase_step = match self.pattern {
+            // The pattern is 128 bits long, so one pass through it is one "period"
+            Some(_) => self.pattern_rate / 128.0 / SAMPLE_RATE as f32,
+            None => self.settings.frequency / SAMPLE_RATE as f32,
+        };
+
+        for x in out.iter_mut() {
+            if self.gain < target {
+                self.gain = (self.gain + ramp_step).min(target);
+            } else if self.gain > target {
+                self.gain = (self.gain - ramp_step).max(target);
+            }
+
+            *x = self.sample() * self.gain;
+            self.phase = (self.phase + phase_step) % 1.0;
+        }
+    }
+}
+
+pub struct Beeper {
+    device: AudioDevice<Tone>,
+}
+
+impl Beeper {
+    pub fn new(audio: &AudioSubsystem, settings: AudioSettings) -> Result<Beeper, String> {
+        let desired_spec = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(1),
+            samples: Some(512),
+        };
+
+        let device = audio.open_playback(None, &desired_spec, |_spec| Tone::new(settings))?;
+
+        // The device keeps running and outputs silence while the tone is off,
+        // pausing it mid-wave is what causes clicks
+        device.resume();
+
+        Ok(Beeper { device })
+    }
+
+    // Called once per frame with the interpreter's sound state
+    pub fn update(&mut self, active: bool, pattern: &[u8; 16], pitch: u8) {
+        self.device.lock().set_sound(active, pattern, pitch);
+    }
+
+    pub fn toggle_mute(&mut self) {
+        let mut tone = self.device.lock();
+        tone.settings.muted = !tone.settings.muted;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Plays `count` samples of a tone that has already faded in
+    fn play(tone: &mut Tone, count: usize) -> Vec<f32> {
+        tone.gain = tone.settings.volume;
+        let mut out = vec![0.0; count];
+        tone.callback(&mut out);
+        out
+    }
+
+    #[test]
+    fn an_empty_pattern_plays_the_plain_tone() {
+        let mut tone = Tone::new(AudioSettings::default());
+        tone.set_sound(true, &[0; 16], 64);
+        assert_eq!(tone.pattern, None);
+
+        // A 440 Hz square wave is high for the first 50 samples and low for the next 50
+        let samples = play(&mut tone, 101);
+        assert!(samples[..51].iter().all(|sample| *sample == 0.25));
+        assert!(samples[51..].iter().all(|sample| *sample == -0.25));
+    }
+
+    #[test]
+    fn patterns_play_one_bit_at_a_time() {
+        let mut tone = Tone::new(AudioSettings::default());
+        let mut pattern = [0; 16];
+        pattern[0] = 0xFF;
+        tone.set_sound(true, &pattern, 64);
+
+        // At 4000 bits a second each bit lasts 11 samples, so the first byte is 88 samples
+        let samples = play(&mut tone, 1400);
+        assert!(samples[..88].iter().all(|sample| *sample > 0.0));
+        assert!(samples[89..].iter().all(|sample| *sample < 0.0));
+    }
+
+    #[test]
+    fn pitch_doubles_the_rate_every_48_steps() {
+        let mut tone = Tone::new(AudioSettings::default());
+        for (pitch, rate) in [(64, 4000.0), (112, 8000.0), (16, 2000.0), (0, 1587.4)] {
+            tone.set_sound(true, &[0xAA; 16], pitch);
+            assert!((tone.pattern_rate - rate).abs() < 0.1, "pitch {} plays at {}", pitch, tone.pattern_rate);
+        }
+    }
+
+    #[test]
+    fn silence_when_stopped_or_muted() {
+        let mut tone = Tone::new(AudioSettings::default());
+        tone.set_sound(false, &[0; 16], 64);
+        let samples = play(&mut tone, 1000);
+        assert_eq!(samples[999], 0.0);
+
+        tone.settings.muted = true;
+        tone.set_sound(true, &[0; 16], 64);
+        let samples = play(&mut tone, 1000);
+        assert_eq!(samples[999], 0.0);
+    }
+}
@@ -0,0 +1,251 @@
+// Runs a ROM without a window and writes out the final screen.
+// Meant for CI: no SDL, deterministic by default, and a non-zero exit code
+// when the interpreter fails.
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+use std::slice::Iter;
+
+use chip8::capture::{Capture, CaptureFormat};
+use chip8::image::{self, ImageFormat};
+use chip8::movie::{self, Movie, MoviePlayer};
+use chip8::random::DEFAULT_SEED;
+use chip8::scheduler::DEFAULT_INSTRUCTIONS_PER_SECOND;
+use chip8::palette;
+use chip8::{Chip8, FrameScheduler, Palette, Platform, Quirks};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Ascii,
+    Pbm,
+    Png,
+    Ppm,
+    Hash,
+}
+
+struct Options {
+    rom_path: String,
+    quirks: Quirks,
+    seed: u64,
+    instructions_per_second: u32,
+    frames: u64,
+    cycles: Option<u64>,
+    input: Option<String>,
+    movie: Option<String>,
+    format: Format,
+    scale: usize,
+    palette: Palette,
+    record: Option<String>,
+    output: Option<String>,
+}
+
+fn usage() -> String {
+    String::from(
+        "Usage: chip8-headless [options] path-to-file\n\
+         \n\
+         Options:\n  \
+           --platform NAME   vip, chip48, superchip, xochip or modern (default)\n  \
+           --seed NUMBER     seed for the random number generator\n  \
+           --ips COUNT       instructions run per second (default 600)\n  \
+           --frames COUNT    number of 60 Hz frames to run (default 60)\n  \
+           --cycles COUNT    stop after this many instructions instead\n  \
+           --input FILE      key presses to feed in, one 'frame key down|up' per line\n  \
+           --movie FILE      play a recorded movie, which sets the seed, speed and length\n  \
+           --format NAME     ascii (default), pbm, png, ppm or hash\n  \
+           --scale N         pixel size for images and recordings (default 1)\n  \
+           --palette NAME    colors for png and ppm, a palette name or 2 or 4 hex colors\n  \
+           --record PATH     record every frame to a .gif, a .y4m or a directory of ppm files\n  \
+           --output FILE     where to write the screen (default: standard output)",
+    )
+}
+
+fn value<'a>(iter: &mut Iter<'a, String>, option: &str) -> Result<&'a String, String> {
+    iter.next().ok_or_else(|| format!("{} needs a value\n{}", option, usage()))
+}
+
+fn whole_number<T: std::str::FromStr>(value: &str, option: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("{} expects a whole number, got '{}'", option, value))
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        rom_path: String::new(),
+        quirks: Quirks::default(),
+        seed: DEFAULT_SEED,
+        instructions_per_second: DEFAULT_INSTRUCTIONS_PER_SECOND,
+        frames: 60,
+        cycles: None,
+        input: None,
+        movie: None,
+        format: Format::Ascii,
+        scale: 1,
+        palette: Palette::builtin().remove(0),
+        record: None,
+        output: None,
+    };
+    let mut rom_path = None;
+
+    let mut iter = args[1..].iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--platform" | "--quirks" => {
+                let platform: Platform = value(&mut iter, arg)?.parse()?;
+                options.quirks = Quirks::for_platform(platform);
+            },
+            "--seed" => options.seed = whole_number(value(&mut iter, arg)?, arg)?,
+            "--ips" => options.instructions_per_second = whole_number(value(&mut iter, arg)?, arg)?,
+            "--frames" => options.frames = whole_number(value(&mut iter, arg)?, arg)?,
+            "--cycles" => options.cycles = Some(whole_number(value(&mut iter, arg)?, arg)?),
+            "--input" => options.input = Some(value(&mut iter, arg)?.clone()),
+            "--movie" => options.movie = Some(value(&mut iter, arg)?.clone()),
+            "--format" => {
+                options.format = match value(&mut iter, arg)?.as_str() {
+                    "ascii" => Format::Ascii,
+                    "pbm" => Format::Pbm,
+                    "png" => Format::Png,
+                    "ppm" => Format::Ppm,
+                    "hash" => Format::Hash,
+                    other => return Err(format!("Unknown format '{}', expected ascii, pbm, png, ppm or hash", other)),
+                };
+            },
+            "--scale" => options.scale = whole_number(value(&mut iter, arg)?, arg)?,
+            "--palette" => {
+                let mut palettes = Palette::builtin();
+                let index = palette::choose(&mut palettes, value(&mut iter, arg)?)?;
+                options.palette = palettes.swap_remove(index);
+            },
+            "--record" => options.record = Some(value(&mut iter, arg)?.clone()),
+            "--output" => options.output = Some(value(&mut iter, arg)?.clone()),
+            "-h" | "--help" => return Err(usage()),
+            _ if arg.starts_with("--") => {
+                return Err(format!("Unknown option '{}'\n{}", arg, usage()));
+            },
+            _ => rom_path = Some(arg.clone()),
+        }
+    }
+
+    options.rom_path = rom_path.ok_or_else(usage)?;
+    Ok(options)
+}
+
+fn run(options: &Options) -> Result<Chip8, String> {
+    let rom = fs::read(&options.rom_path)
+        .map_err(|error| format!("Could not read {}: {}", options.rom_path, error))?;
+
+    // Either a full movie, or a bare input script wrapped into one
+    let movie = match (&options.movie, &options.input) {
+        (Some(path), _) => {
+            let movie = Movie::load(path)?;
+            for warning in movie.check(&rom, options.quirks) {
+                eprintln!("Warning: {}", warning);
+            }
+            movie
+        },
+        (None, input) => {
+            let mut movie = Movie::new(&rom, options.seed, options.instructions_per_second, options.quirks);
+            movie.frames = options.frames;
+            if let Some(path) = input {
+                let text = fs::read_to_string(path).map_err(|error| format!("Could not read {}: {}", path, error))?;
+                movie.events = movie::parse_input_script(&text).map_err(|error| format!("{}: {}", path, error))?;
+            }
+            movie
+        },
+    };
+
+    // At 0 instructions a second no frame runs any, so the budget would never run out
+    if options.cycles.is_some() && movie.instructions_per_second == 0 {
+        return Err("--cycles needs more than 0 instructions per second".to_string());
+    }
+
+    let mut chip8 = Chip8::initialize(options.quirks);
+    chip8.load_fontset();
+    chip8.seed(movie.seed);
+    chip8.load_rom(&rom).map_err(|error| error.to_string())?;
+
+    let mut scheduler = FrameScheduler::new(movie.instructions_per_second);
+    let mut player = MoviePlayer::new(movie);
+    let mut cycles_left = options.cycles;
+
+    let mut capture = match &options.record {
+        Some(path) => {
+            let path = Path::new(path);
+            let recording = Capture::create(path, CaptureFormat::from_path(path), &options.palette.colors, options.scale);
+            Some(recording.map_err(|error| format!("Can't record to {}: {}", path.display(), error))?)
+        },
+        None => None,
+    };
+
+    loop {
+        // With a cycle budget, a program waiting for a key that the input never presses would wait forever
+        let stuck = chip8.is_waiting_for_key() && player.movie().events.last().is_none_or(|event| event.frame < player.frame());
+        let done = match cycles_left {
+            Some(cycles) => cycles == 0 || stuck,
+            None => player.finished(),
+        };
+        if done || chip8.has_exited() {
+            break;
+        }
+
+        player.apply(&mut chip8);
+
+        let mut cycles = scheduler.cycles_for_frame() as u64;
+        if let Some(left) = cycles_left {
+            cycles = cycles.min(left);
+        }
+
+        let ran = chip8.run_frame(cycles as u32).map_err(|error| error.to_string())?;
+        if let Some(left) = cycles_left.as_mut() {
+            // A key wait cuts the frame short, the rest of the budget carries over
+            *left -= ran as u64;
+        }
+        if let Some(recording) = capture.as_mut() {
+            recording.add_frame(&chip8.get_display()).map_err(|error| format!("Recording failed: {}", error))?;
+        }
+    }
+
+    if let Some(recording) = capture {
+        recording.finish().map_err(|error| format!("Recording failed: {}", error))?;
+    }
+
+    Ok(chip8)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(2);
+        }
+    };
+
+    let chip8 = match run(&options) {
+        Ok(chip8) => chip8,
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+    };
+
+    let display = chip8.get_display();
+    let output = match options.format {
+        Format::Ascii => image::to_ascii(&display).into_bytes(),
+        Format::Pbm => image::to_pbm(&display),
+        Format::Png => image::screenshot(&display, &options.palette.colors, options.scale, ImageFormat::Png),
+        Format::Ppm => image::screenshot(&display, &options.palette.colors, options.scale, ImageFormat::Ppm),
+        Format::Hash => format!("{:016x}\n", image::display_hash(&display)).into_bytes(),
+    };
+
+    let written = match &options.output {
+        Some(path) => fs::write(path, &output),
+        None => io::stdout().write_all(&output),
+    };
+    if let Err(error) = written {
+        eprintln!("Could not write the screen: {}", error);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use crate::chip_8::Display;
+use crate::image;
+use crate::scheduler::TIMER_HZ;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureFormat {
+    // An animated GIF that loops forever
+    Gif,
+    // Uncompressed YUV4MPEG2 video, which ffmpeg and most editors read
+    Y4m,
+    // A directory of numbered PPM images, one per frame
+    PpmSequence,
+}
+
+impl CaptureFormat {
+    // Guessed from the extension, anything that isn't .gif or .y4m is a directory
+    pub fn from_path(path: &Path) -> CaptureFormat {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("gif") => CaptureFormat::Gif,
+            Some(extension) if extension.eq_ignore_ascii_case("y4m") => CaptureFormat::Y4m,
+            _ => CaptureFormat::PpmSequence,
+        }
+    }
+
+    // What to add to a file name for this format, nothing for a directory
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CaptureFormat::Gif => ".gif",
+            CaptureFormat::Y4m => ".y4m",
+            CaptureFormat::PpmSequence => "",
+        }
+    }
+}
+
+impl FromStr for CaptureFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<CaptureFormat, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "gif" => Ok(CaptureFormat::Gif),
+            "y4m" => Ok(CaptureFormat::Y4m),
+            "ppm" => Ok(CaptureFormat::PpmSequence),
+            _ => Err(format!("Unknown capture format '{}', expected gif, y4m or ppm", name)),
+        }
+    }
+}
+
+// GIF delays are in hundredths of a second, and browsers slow down anything
+// shorter than this, so briefer frames are dropped instead
+const GIF_MIN_DELAY: u64 = 2;
+
+enum Output {
+    // The frame being shown and the hundredth of a second it started at. It's
+    // only written once the next different frame arrives and its length is known
+    Gif { file: BufWriter<File>, pending: Option<(Vec<u8>, u64)> },
+    // The Y, Cb and Cr values of each of the four colors
+    Y4m { file: BufWriter<File>, planes: [[u8; 4]; 3] },
+    PpmSequence { directory: PathBuf },
+}
+
+// Records the screen once per 60 Hz frame into a video or image sequence.
+//
+// The size is fixed by the first frame, times `scale`. Frames in a different
+// resolution later on, after a switch between lo-res and hi-res, are stretched
+// to fit. The colors are fixed when recording starts
+pub struct Capture {
+    output: Output,
+    colors: [[u8; 3]; 4],
+    scale: usize,
+    size: Option<(usize, usize)>,
+    frames: u64,
+}
+
+impl Capture {
+    pub fn create(path: &Path, format: CaptureFormat, colors: &[[u8; 3]; 4], scale: usize) -> io::Result<Capture> {
+        let output = match format {
+            CaptureFormat::Gif => Output::Gif { file: BufWriter::new(File::create(path)?), pending: None },
+            CaptureFormat::Y4m => {
+                let ycbcr = colors.map(to_ycbcr);
+                let planes = [0, 1, 2].map(|plane| ycbcr.map(|color| color[plane]));
+                Output::Y4m { file: BufWriter::new(File::create(path)?), planes }
+            },
+            CaptureFormat::PpmSequence => {
+                fs::create_dir_all(path)?;
+                Output::PpmSequence { directory: path.to_path_buf() }
+            },
+        };
+
+        Ok(Capture { output, colors: *colors, scale: scale.max(1), size: None, frames: 0 })
+    }
+
+    // Number of 60 Hz frames recorded so far
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn add_frame(&mut self, display: &Display) -> io::Result<()> {
+        let (width, height) = *self.size.get_or_insert((display.width, display.height));
+        let (width, height) = (width * self.scale, height * self.scale);
+        let pixels = resample(display, width, height);
+        let time = self.frames * 100 / TIMER_HZ as u64;
+
+        match &mut self.output {
+            Output::Gif { file, pending } => {
+                if self.frames == 0 {
+                    write_gif_header(file, width, height, &self.colors)?;
+                }
+
+                match pending.take() {
+                    None => *pending = Some((pixels, time)),
+                    Some((shown, start)) if shown == pixels => *pending = Some((shown, start)),
+                    Some((_, start)) if time - start < GIF_MIN_DELAY => *pending = Some((pixels, start)),
+                    Some((shown, start)) => {
+                        write_gif_frame(file, width, height, &shown, time - start)?;
+                        *pending = Some((pixels, time));
+                    },
+                }
+            },
+            Output::Y4m { file, planes } => {
+                if self.frames == 0 {
+                    writeln!(file, "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444", width, height, TIMER_HZ)?;
+                }
+
+                file.write_all(b"FRAME\n")?;
+                for plane in planes.iter() {
+                    let bytes: Vec<u8> = pixels.iter().map(|pixel| plane[*pixel as usize]).collect();
+                    file.write_all(&bytes)?;
+                }
+            },
+            Output::PpmSequence { directory } => {
+                let rgb: Vec<u8> = pixels.iter().flat_map(|pixel| self.colors[*pixel as usize]).collect();
+                let path = directory.join(format!("frame-{:06}.ppm", self.frames));
+                fs::write(path, image::encode_ppm(width, height, &rgb))?;
+            },
+        }
+
+        self.frames += 1;
+        Ok(())
+    }
+
+    // Writes out whatever is still buffered. Returns the number of frames recorded
+    pub fn finish(self) -> io::Result<u64> {
+        match self.output {
+            Output::Gif { mut file, pending } => {
+                if let Some((shown, start)) = pending {
+                    let (width, height) = self.size.map_or((0, 0), |(width, height)| (width * self.scale, height * self.scale));
+                    let end = self.frames * 100 / TIMER_HZ as u64;
+                    write_gif_frame(&mut file, width, height, &shown, (end - start).max(GIF_MIN_DELAY))?;
+                }
+                // An empty recording still ends up a valid file
+                if self.frames == 0 {
+                    write_gif_header(&mut file, 1, 1, &self.colors)?;
+                }
+                file.write_all(&[0x3B])?;
+                file.flush()?;
+            },
+            Output::Y4m { mut file, .. } => file.flush()?,
+            Output::PpmSequence { .. } => (),
+        }
+        Ok(self.frames)
+    }
+}
+
+// Where to record a session of `base` (usually the ROM) started at `time`,
+// e.g. roms/pong-20240131-142501.gif next to roms/pong.ch8
+pub fn capture_path(base: &Path, format: CaptureFormat, time: SystemTime) -> PathBuf {
+    let stem = base.file_stem().map_or("capture".into(), |stem| stem.to_string_lossy());
+    base.with_file_name(format!("{}-{}{}", stem, image::timestamp(time), format.extension()))
+}
+
+// The display's color indices stretched to `width` by `height`, nearest neighbour
+fn resample(display: &Display, width: usize, height: usize) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let row = y * display.height / height * display.width;
+        for x in 0..width {
+            pixels.push(display.pixels[row + x * display.width / width] & 0b11);
+        }
+    }
+    pixels
+}
+
+// BT.601 in the limited range video players expect
+fn to_ycbcr([r, g, b]: [u8; 3]) -> [u8; 3] {
+    let (r, g, b) = (r as f32, g as f32, b as f32);
+    let y = 16.0 + (65.738 * r + 129.057 * g + 25.064 * b) / 256.0;
+    let cb = 128.0 + (-37.945 * r - 74.494 * g + 112.439 * b) / 256.0;
+    let cr = 128.0 + (112.439 * r - 94.154 * g - 18.285 * b) / 256.0;
+    [y.round() as u8, cb.round() as u8, cr.round() as u8]
+}
+
+fn write_gif_header(file: &mut impl Write, width: usize, height: usize, colors: &[[u8; 3]; 4]) -> io::Result<()> {
+    file.write_all(b"GIF89a")?;
+    file.write_all(&(width as u16).to_le_bytes())?;
+    file.write_all(&(height as u16).to_le_bytes())?;
+    // A global color table of 4 colors, background color 0, square pixels
+    file.write_all(&[0x91, 0, 0])?;
+    for color in colors {
+        file.write_all(color)?;
+    }
+    // The NETSCAPE2.0 extension, repeating forever
+    file.write_all(&[0x21, 0xFF, 0x0B])?;
+    file.write_all(b"NETSCAPE2.0")?;
+    file.write_all(&[0x03, 0x01, 0x00, 0x00, 0x00])
+}
+
+fn write_gif_frame(file: &mut impl Write, width: usize, height: usize, pixels: &[u8], delay: u64) -> io::Result<()> {
+    // Graphic control extension: leave the frame in place, show it for `delay` hundredths
+    let delay = delay.min(u16::MAX as u64) as u16;
+    file.write_all(&[0x21, 0xF9, 0x04, 0x04])?;
+    file.write_all(&delay.to_le_bytes())?;
+    file.write_all(&[0x00, 0x00])?;
+
+    // Image descriptor covering the whole screen, with no local color table
+    file.write_all(&[0x2C, 0, 0, 0, 0])?;
+    file.write_all(&(width as u16).to_le_bytes())?;
+    file.write_all(&(height as u16).to_le_bytes())?;
+    file.write_all(&[0x00])?;
+
+    // The LZW data goes out in blocks of at most 255 bytes
+    const MIN_CODE_SIZE: u8 = 2;
+    file.write_all(&[MIN_CODE_SIZE])?;
+    for block in lzw_encode(pixels, MIN_CODE_SIZE).chunks(255) {
+        file.write_all(&[block.len() as u8])?;
+        file.write_all(block)?;
+    }
+    file.write_all(&[0x00])
+}
+
+// GIF flavoured LZW: codes start one bit wider than the pixel values and grow
+// up to 12 bits, at which point the table is cleared and starts over
+fn lzw_encode(pixels: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+
+    let mut out = BitWriter::default();
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next = end + 1;
+    let mut width = min_code_size + 1;
+    out.put(clear, width);
+
+    let mut prefix: Option<u16> = None;
+    for &pixel in pixels {
+        let Some(code) = prefix else {
+            prefix = Some(pixel as u16);
+            continue;
+        };
+        if let Some(&longer) = table.get(&(code, pixel)) {
+            prefix = Some(longer);
+            continue;
+        }
+
+        out.put(code, width);
+        if next < 0x1000 {
+            if next == 1 << width {
+                width += 1;
+            }
+            table.insert((code, pixel), next);
+            next += 1;
+        } else {
+            out.put(clear, width);
+            table.clear();
+            next = end + 1;
+            width = min_code_size + 1;
+        }
+        prefix = Some(pixel as u16);
+    }
+
+    if let Some(code) = prefix {
+        out.put(code, width);
+    }
+    out.put(end, width);
+    out.finish()
+}
+
+// Packs codes least significant bit first, the way GIF wants them
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bits: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn put(&mut self, code: u16, width: u8) {
+        self.bits |= (code as u32) << self.count;
+        self.count += width as u32;
+        while self.count >= 8 {
+            self.bytes.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.bits as u8);
+        }
+        self.bytes
+    }
+}
@@ -1,5 +1,12 @@
-use std::{fs::File, io::Read, usize};
-use rand::Rng;
+use std::{fs::File, io::Read};
+use crate::debugger::Register;
+use crate::disasm::decode;
+use crate::error::Chip8Error;
+use crate::quirks::{LoadStore, Quirks};
+use crate::random::{RandomSource, XorShiftRng};
+use crate::state::{StateReader, StateSink, StateWriter};
+use crate::trace::{Change, TraceRecord, Tracer};
+use crate::watch::{Access, WatchHit, Watchpoint};
 
 const CHIP8_FONTSET: [u8; 80] =
 [
@@ -21,16 +28,94 @@ const CHIP8_FONTSET: [u8; 80] =
   0xF0, 0x80, 0xF0, 0x80, 0x80  // F
 ];
 
+// SUPER-CHIP 8x10 digits used by Fx30
+const SCHIP_BIG_FONTSET: [u8; 160] =
+[
+  0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+  0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+  0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+  0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+  0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+  0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+  0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+  0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+  0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+  0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+  0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+  0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+  0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+  0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+  0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+  0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0  // F
+];
+
+// The big font sits right after the small one, well below 0x200
+const BIG_FONT_ADDRESS: usize = 0x50;
+
+// XO-CHIP extends the address space to the full 64 KiB reachable by a 16 bit I
+pub const MEMORY_SIZE: usize = 0x10000;
+
+// Programs are loaded at 0x200, everything below that belongs to the interpreter
+pub const PROGRAM_START: usize = 0x200;
+
+// Lo-res is the original CHIP-8 screen, hi-res is the SUPER-CHIP one
 pub const GAME_WIDTH: usize = 64;
 pub const GAME_HEIGHT: usize = 32;
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+
+// The part of the screen buffer in use, laid out row by row `width` pixels wide.
+// Each pixel holds one bit per XO-CHIP plane, so its value is a color index from 0 to 3
+pub struct Display<'a> {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: &'a [u8],
+}
+
+// Where Fx0A is in waiting for a key. Like on the COSMAC VIP, a key only counts once it
+// has gone down and come back up again while the wait is on, so holding one down from a
+// previous screen doesn't fall straight through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyWait {
+    Idle,
+    Waiting,
+    Held(u8),
+    Released(u8),
+}
+
+// What a single call to Chip8::cycle did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Step {
+    // Where the instruction was fetched from, and its first two bytes
+    pub pc: u16,
+    pub opcode: u16,
+    // Pixels were drawn, cleared or scrolled, or the resolution changed
+    pub display_changed: bool,
+    // Fx0A is still waiting for a key to be pressed and released, so it runs again next cycle
+    pub waiting_for_key: bool,
+    // 00FD ran, or had already run and nothing happened
+    pub exited: bool,
+    // The buzzer started or stopped
+    pub sound_changed: bool,
+}
 
 #[derive(Debug)]
-#[allow(dead_code)]
 pub struct Chip8 {
     opcode: u16,
-    memory: [u8; 4096],
+    // Address the current opcode was fetched from, reported in errors
+    opcode_pc: u16,
+    memory: Vec<u8>,
     cpu_register_v: [u8; 16],
-    screen: [bool; GAME_WIDTH * GAME_HEIGHT],
+    screen: [u8; HIRES_WIDTH * HIRES_HEIGHT],
+    hires: bool,
+    exited: bool,
+    // Set whenever the screen buffer is written, for reporting in Step
+    display_changed: bool,
+    // Bitmask of the XO-CHIP planes that drawing, clearing and scrolling affect
+    planes: u8,
+    rpl_flags: [u8; 16],
+    audio_pattern: [u8; 16],
+    audio_pitch: u8,
     register_index: u16,
     pc: u16,
     delay_timer: u8,
@@ -38,114 +123,633 @@ pub struct Chip8 {
     stack_pointer: u16,
     stack: [u16; 16],
     keys: [bool; 16],
+    key_wait: KeyWait,
+    quirks: Quirks,
+    rng: Box<dyn RandomSource>,
+    // Not part of the machine state, so save states leave them alone
+    watchpoints: Vec<Watchpoint>,
+    watch_hits: Vec<WatchHit>,
+    tracer: Option<Tracer>,
 }
 
 impl Chip8 {
-    pub fn initialize() -> Chip8 {
+    pub fn initialize(quirks: Quirks) -> Chip8 {
         Chip8 {
             opcode: 0,
-            memory: [0; 4096],
-            screen: [false; GAME_WIDTH * GAME_HEIGHT],
+            opcode_pc: 0,
+            memory: vec![0; MEMORY_SIZE],
+            screen: [0; HIRES_WIDTH * HIRES_HEIGHT],
+            hires: false,
+            exited: false,
+            display_changed: false,
+            planes: 1,
+            rpl_flags: [0; 16],
+            audio_pattern: [0; 16],
+            audio_pitch: 64,
             cpu_register_v: [0; 16],
             register_index: 0, 
-            pc: 512, 
+            pc: PROGRAM_START as u16,
             delay_timer: 0,
             sound_timer: 0, 
             stack_pointer: 0,
             stack: [0; 16],
-            keys: [false; 16]
+            keys: [false; 16],
+            key_wait: KeyWait::Idle,
+            quirks,
+            rng: Box::new(XorShiftRng::default()),
+            watchpoints: Vec::new(),
+            watch_hits: Vec::new(),
+            tracer: None,
         }
     }
 
-    fn push(&mut self, value: u16) {
+    fn push(&mut self, value: u16) -> Result<(), Chip8Error> {
+        if self.stack_pointer as usize >= self.stack.len() {
+            return Err(Chip8Error::StackOverflow { pc: self.opcode_pc });
+        }
+
         self.stack[self.stack_pointer as usize] = value;
         self.stack_pointer += 1;
+        Ok(())
     }
 
-    fn pop(&mut self) -> u16 {
+    fn pop(&mut self) -> Result<u16, Chip8Error> {
+        if self.stack_pointer == 0 {
+            return Err(Chip8Error::StackUnderflow { pc: self.opcode_pc });
+        }
+
         self.stack_pointer -= 1;
-        self.stack[self.stack_pointer as usize]
+        Ok(self.stack[self.stack_pointer as usize])
+    }
+
+    // Records the access if a watchpoint covers it
+    fn watch(&mut self, access: Access, address: usize, old: u8, new: u8) {
+        if self.watchpoints.iter().any(|watchpoint| watchpoint.matches(access, address)) {
+            self.watch_hits.push(WatchHit {
+                access,
+                address: address as u16,
+                pc: self.opcode_pc,
+                opcode: self.opcode,
+                old,
+                new,
+            });
+        }
+    }
+
+    // Reads on behalf of an instruction, as opposed to fetching one
+    fn read_byte(&mut self, address: usize) -> Result<u8, Chip8Error> {
+        match self.memory.get(address) {
+            Some(byte) => {
+                let byte = *byte;
+                self.watch(Access::Read, address, byte, byte);
+                Ok(byte)
+            },
+            None => Err(Chip8Error::MemoryOutOfBounds { address, pc: self.opcode_pc }),
+        }
+    }
+
+    // Reads a word without it counting as an access
+    fn read_word(&self, address: usize) -> Result<u16, Chip8Error> {
+        match self.memory.get(address..address + 2) {
+            Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]])),
+            None => {
+                let address = if address < self.memory.len() { address + 1 } else { address };
+                Err(Chip8Error::MemoryOutOfBounds { address, pc: self.opcode_pc })
+            },
+        }
+    }
+
+    // Records fetching the instruction word at `address`, which read_word already checked
+    fn watch_fetch(&mut self, address: usize) {
+        for address in address..address + 2 {
+            let byte = self.memory[address];
+            self.watch(Access::Execute, address, byte, byte);
+        }
+    }
+
+    fn write_byte(&mut self, address: usize, value: u8) -> Result<(), Chip8Error> {
+        match self.memory.get(address) {
+            Some(old) => {
+                let old = *old;
+                self.memory[address] = value;
+                self.watch(Access::Write, address, old, value);
+                Ok(())
+            },
+            None => Err(Chip8Error::MemoryOutOfBounds { address, pc: self.opcode_pc }),
+        }
+    }
+
+    fn unknown_opcode(&self) -> Chip8Error {
+        Chip8Error::UnknownOpcode { opcode: self.opcode, pc: self.opcode_pc }
     }
 
     pub fn load_fontset(&mut self) {
-        let mut i = 0;
-        while i < 80 {
-            self.memory[i] = CHIP8_FONTSET[i];
-            i += 1;
+        self.memory[..CHIP8_FONTSET.len()].copy_from_slice(&CHIP8_FONTSET);
+        self.memory[BIG_FONT_ADDRESS..BIG_FONT_ADDRESS + SCHIP_BIG_FONTSET.len()]
+            .copy_from_slice(&SCHIP_BIG_FONTSET);
+    }
+
+    fn width(&self) -> usize {
+        if self.hires { HIRES_WIDTH } else { GAME_WIDTH }
+    }
+
+    fn height(&self) -> usize {
+        if self.hires { HIRES_HEIGHT } else { GAME_HEIGHT }
+    }
+
+    pub fn get_display(&self) -> Display<'_> {
+        let width = self.width();
+        let height = self.height();
+
+        Display {
+            width,
+            height,
+            pixels: &self.screen[..width * height],
         }
     }
 
-    pub fn get_display(&self) -> &[bool] {
-        &self.screen
+    // Set by 00FD, after which the interpreter stops executing
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.screen = [0; HIRES_WIDTH * HIRES_HEIGHT];
+        self.display_changed = true;
+    }
+
+    // Clears the selected planes only
+    fn clear_screen(&mut self) {
+        for pixel in self.screen.iter_mut() {
+            *pixel &= !self.planes;
+        }
+        self.display_changed = true;
+    }
+
+    // Moves the selected planes by (dx, dy) pixels, whatever scrolls off the edge is lost
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let width = self.width() as isize;
+        let height = self.height() as isize;
+        let old = self.screen;
+
+        for y in 0..height {
+            for x in 0..width {
+                let (src_x, src_y) = (x - dx, y - dy);
+                let inside = src_x >= 0 && src_x < width && src_y >= 0 && src_y < height;
+                let moved = if inside { old[(src_x + src_y * width) as usize] } else { 0 };
+
+                let idx = (x + y * width) as usize;
+                self.screen[idx] = (old[idx] & !self.planes) | (moved & self.planes);
+            }
+        }
+        self.display_changed = true;
+    }
+
+    // The registers 5xy2 and 5xy3 go through, Vx to Vy in either direction as XO-CHIP allows x > y
+    fn register_range(&self) -> impl Iterator<Item = usize> {
+        let x = ((self.opcode & 0x0F00) >> 8) as usize;
+        let y = ((self.opcode & 0x00F0) >> 4) as usize;
+
+        (0..=x.abs_diff(y)).map(move |i| if x <= y { x + i } else { x - i })
+    }
+
+    // Skips the next instruction, which is 4 bytes long if it's an XO-CHIP F000 NNNN
+    fn skip_next(&mut self) -> Result<(), Chip8Error> {
+        let next = self.read_word(self.pc as usize)?;
+
+        if next == 0xF000 {
+            self.pc = self.pc.wrapping_add(4);
+        } else {
+            self.pc = self.pc.wrapping_add(2);
+        }
+        Ok(())
+    }
+
+    // Memory accesses in these ranges get recorded, to be collected with take_watch_hits
+    pub fn set_watchpoints(&mut self, watchpoints: &[Watchpoint]) {
+        self.watchpoints = watchpoints.to_vec();
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    // Everything the watchpoints caught since the last call, oldest first
+    pub fn take_watch_hits(&mut self) -> Vec<WatchHit> {
+        std::mem::take(&mut self.watch_hits)
+    }
+
+    // Every instruction run from now on is passed to the tracer
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    pub fn tracer(&self) -> Option<&Tracer> {
+        self.tracer.as_ref()
+    }
+
+    pub fn tracer_mut(&mut self) -> Option<&mut Tracer> {
+        self.tracer.as_mut()
+    }
+
+    // Stops tracing and hands the tracer back
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    // Replaces the generator behind Cxkk
+    pub fn set_random_source(&mut self, rng: Box<dyn RandomSource>) {
+        self.rng = rng;
+    }
+
+    // Shorthand for using the default generator with the given seed
+    pub fn seed(&mut self, seed: u64) {
+        self.set_random_source(Box::new(XorShiftRng::new(seed)));
     }
 
     pub fn keypress(&mut self, i: usize, pressed: bool) {
+        self.key_wait = match self.key_wait {
+            KeyWait::Waiting if pressed && !self.keys[i] => KeyWait::Held(i as u8),
+            KeyWait::Held(key) if !pressed && key as usize == i => KeyWait::Released(key),
+            key_wait => key_wait,
+        };
         self.keys[i] = pressed;
     }
 
-    pub fn load_game(&mut self, file_name: &str) {
-        let file_result = File::open(file_name);
+    // Whether the program is stuck on Fx0A until a key is pressed and released
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.key_wait != KeyWait::Idle
+    }
 
-        let mut file = match file_result {
-            Ok(file) => file,
-            Err(error) => panic!("Error opening file: {:?}", error)
-        };
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.cpu_register_v
+    }
+
+    pub fn index_register(&self) -> u16 {
+        self.register_index
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    // Return addresses currently on the stack, oldest first
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.stack_pointer as usize]
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn keys(&self) -> &[bool; 16] {
+        &self.keys
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    // XO-CHIP audio pattern buffer (F002) and its playback pitch (Fx3A)
+    pub fn audio_pattern(&self) -> &[u8; 16] {
+        &self.audio_pattern
+    }
+
+    pub fn audio_pitch(&self) -> u8 {
+        self.audio_pitch
+    }
+
+    pub fn load_game(&mut self, file_name: &str) -> Result<(), Chip8Error> {
+        let pc = PROGRAM_START as u16;
+
+        let mut file = File::open(file_name).map_err(|error| Chip8Error::Io { error, pc })?;
 
         let mut buffer = Vec::new();
-        let _ = file.read_to_end(&mut buffer);
+        file.read_to_end(&mut buffer).map_err(|error| Chip8Error::Io { error, pc })?;
+
+        self.load_rom(&buffer)
+    }
 
-        let mut i = 0;
-        for byte in &buffer {
-            // Start reading to memory at position 0x200 which is 512
-            self.memory[i + 512] = *byte;
-            i += 1;
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), Chip8Error> {
+        let max = MEMORY_SIZE - PROGRAM_START;
+        if rom.len() > max {
+            return Err(Chip8Error::RomTooLarge { size: rom.len(), max, pc: PROGRAM_START as u16 });
         }
+
+        // Start reading to memory at position 0x200 which is 512
+        self.memory[PROGRAM_START..PROGRAM_START + rom.len()].copy_from_slice(rom);
+        Ok(())
     }
 
-    pub fn cycle_timers(&mut self) {
+    // Snapshot of the whole machine, see the state module for the format
+    pub fn save_state(&self) -> Vec<u8> {
+        self.write_state(Vec::new())
+    }
+
+    // Writes the snapshot save_state returns into any sink, handing the sink back
+    pub(crate) fn write_state<S: StateSink>(&self, sink: S) -> S {
+        let mut writer = StateWriter::new(sink);
+
+        writer.u16(self.opcode);
+        writer.u16(self.opcode_pc);
+        writer.bytes(&self.memory);
+        writer.bytes(&self.cpu_register_v);
+        writer.bytes(&self.screen);
+        writer.bool(self.hires);
+        writer.bool(self.exited);
+        writer.u8(self.planes);
+        writer.bytes(&self.rpl_flags);
+        writer.bytes(&self.audio_pattern);
+        writer.u8(self.audio_pitch);
+        writer.u16(self.register_index);
+        writer.u16(self.pc);
+        writer.u8(self.delay_timer);
+        writer.u8(self.sound_timer);
+        writer.u16(self.stack_pointer);
+        for address in self.stack {
+            writer.u16(address);
+        }
+        for key in self.keys {
+            writer.bool(key);
+        }
+        let (wait, key) = match self.key_wait {
+            KeyWait::Idle => (0, 0),
+            KeyWait::Waiting => (1, 0),
+            KeyWait::Held(key) => (2, key),
+            KeyWait::Released(key) => (3, key),
+        };
+        writer.u8(wait);
+        writer.u8(key);
+
+        writer.bool(self.quirks.logic_resets_vf);
+        writer.bool(self.quirks.shift_ignores_vy);
+        // 0 and 1 are what the old on/off flag stored, so earlier states still load
+        writer.u8(match self.quirks.load_store {
+            LoadStore::Unchanged => 0,
+            LoadStore::IncrementByXPlus1 => 1,
+            LoadStore::IncrementByX => 2,
+        });
+        writer.bool(self.quirks.jump_uses_vx);
+        writer.bool(self.quirks.clip_sprites);
+
+        writer.u64(self.rng.state());
+
+        writer.finish()
+    }
+
+    // Restores a snapshot made by save_state. On error the machine is left untouched
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        let mut reader = StateReader::new(data, self.pc)?;
+
+        let opcode = reader.u16()?;
+        let opcode_pc = reader.u16()?;
+        let memory = reader.bytes(MEMORY_SIZE)?.to_vec();
+        let cpu_register_v = reader.array()?;
+        let screen = reader.array()?;
+        let hires = reader.bool()?;
+        let exited = reader.bool()?;
+        let planes = reader.u8()?;
+        let rpl_flags = reader.array()?;
+        let audio_pattern = reader.array()?;
+        let audio_pitch = reader.u8()?;
+        let register_index = reader.u16()?;
+        let pc = reader.u16()?;
+        let delay_timer = reader.u8()?;
+        let sound_timer = reader.u8()?;
+        let stack_pointer = reader.u16()?;
+        let mut stack = [0; 16];
+        for address in stack.iter_mut() {
+            *address = reader.u16()?;
+        }
+        let mut keys = [false; 16];
+        for key in keys.iter_mut() {
+            *key = reader.bool()?;
+        }
+        let key_wait = match (reader.u8()?, reader.u8()?) {
+            (0, _) => KeyWait::Idle,
+            (1, _) => KeyWait::Waiting,
+            (2, key) if key < 16 => KeyWait::Held(key),
+            (3, key) if key < 16 => KeyWait::Released(key),
+            _ => return Err(reader.invalid("bad key wait")),
+        };
+
+        let quirks = Quirks {
+            logic_resets_vf: reader.bool()?,
+            shift_ignores_vy: reader.bool()?,
+            load_store: match reader.u8()? {
+                0 => LoadStore::Unchanged,
+                1 => LoadStore::IncrementByXPlus1,
+                2 => LoadStore::IncrementByX,
+                _ => return Err(reader.invalid("bad load/store quirk")),
+            },
+            jump_uses_vx: reader.bool()?,
+            clip_sprites: reader.bool()?,
+        };
+
+        let rng_state = reader.u64()?;
+
+        if stack_pointer as usize > stack.len() {
+            return Err(reader.invalid("stack pointer out of range"));
+        }
+        if planes > 0b11 {
+            return Err(reader.invalid("bad plane selection"));
+        }
+        reader.finish()?;
+
+        // Keep whatever generator was plugged in, just rewind it to where it was
+        let mut rng = std::mem::replace(&mut self.rng, Box::new(XorShiftRng::default()));
+        rng.set_state(rng_state);
+
+        *self = Chip8 {
+            opcode,
+            opcode_pc,
+            memory,
+            cpu_register_v,
+            screen,
+            hires,
+            exited,
+            display_changed: false,
+            planes,
+            rpl_flags,
+            audio_pattern,
+            audio_pitch,
+            register_index,
+            pc,
+            delay_timer,
+            sound_timer,
+            stack_pointer,
+            stack,
+            keys,
+            key_wait,
+            quirks,
+            rng,
+            watchpoints: std::mem::take(&mut self.watchpoints),
+            watch_hits: Vec::new(),
+            tracer: self.tracer.take(),
+        };
+        Ok(())
+    }
+
+    // Counts both timers down. Returns whether that stopped the buzzer, the one
+    // sound change that happens outside an instruction
+    pub fn cycle_timers(&mut self) -> bool {
+        let sound = self.sound_active();
+
         // Update timers
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
 
         if self.sound_timer > 0 {
-            if self.sound_timer == 1 {
-                println!("BEEP!");
+            self.sound_timer -= 1;
+        }
+
+        self.sound_active() != sound
+    }
+
+    // Runs one 60 Hz frame: `cycles` instructions followed by a timer tick.
+    // Returns how many instructions actually ran, fewer when the program exits
+    // or waits for a key
+    pub fn run_frame(&mut self, cycles: u32) -> Result<u32, Chip8Error> {
+        let mut ran = 0;
+        while ran < cycles && !self.exited {
+            ran += 1;
+
+            // Nothing else can happen until a key changes, which it can't mid-frame
+            if self.cycle()?.waiting_for_key {
+                break;
             }
+        }
 
-            self.sound_timer -= 1;
+        self.cycle_timers();
+        Ok(ran)
+    }
+
+    // The buzzer sounds for as long as the sound timer is non-zero
+    pub fn sound_active(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    pub fn cycle(&mut self) -> Result<Step, Chip8Error> {
+        if self.exited {
+            return Ok(Step { pc: self.pc, opcode: self.opcode, exited: true, ..Step::default() });
+        }
+
+        let pc = self.pc;
+        let sound = self.sound_active();
+        self.display_changed = false;
+
+        match self.tracer.take() {
+            Some(tracer) => self.traced_execute(tracer)?,
+            None => self.execute()?,
+        }
+
+        Ok(Step {
+            pc,
+            opcode: self.opcode,
+            display_changed: self.display_changed,
+            waiting_for_key: self.is_waiting_for_key(),
+            exited: self.exited,
+            sound_changed: self.sound_active() != sound,
+        })
+    }
+
+    // Runs one instruction and hands the tracer what it did
+    fn traced_execute(&mut self, mut tracer: Tracer) -> Result<(), Chip8Error> {
+        // Decoded up front, the instruction may overwrite itself
+        let pc = self.pc;
+        let instruction = self.memory.get(pc as usize..).and_then(decode);
+        let registers = self.cpu_register_v;
+        let index = self.register_index;
+
+        // Failed instructions are recorded too, they're the interesting ones
+        let result = self.execute();
+
+        let mut changes: Vec<Change> = (0..16)
+            .filter(|&x| registers[x] != self.cpu_register_v[x])
+            .map(|x| Change {
+                register: Register::V(x as u8),
+                old: registers[x] as u16,
+                new: self.cpu_register_v[x] as u16,
+            })
+            .collect();
+        if index != self.register_index {
+            changes.push(Change { register: Register::I, old: index, new: self.register_index });
         }
+
+        tracer.record(TraceRecord { pc, opcode: self.opcode, instruction, changes });
+        self.tracer = Some(tracer);
+        result
     }
 
-    pub fn cycle(&mut self) {
-        self.opcode = 
-            (self.memory[self.pc as usize] as u16) << 8 |
-            self.memory[(self.pc + 1) as usize] as u16;
+    // Fetches and runs one instruction
+    fn execute(&mut self) -> Result<(), Chip8Error> {
+        self.opcode_pc = self.pc;
+        self.opcode = self.read_word(self.pc as usize)?;
+        self.watch_fetch(self.pc as usize);
 
-        self.pc += 2;
-        println!("opcode: {:#06x}", self.opcode);
+        self.pc = self.pc.wrapping_add(2);
 
         match self.opcode & 0xF000 {
-            // This is for 0x00e0 and 0x000e
-            // Figure out a way to write this match more cleanly
+            // 00E0, 00EE and the SUPER-CHIP screen control opcodes
             0x0000 => {
-                match self.opcode & 0x000F {
-                    // 0x00e0
-                    0x0000 => {
+                match self.opcode & 0x00FF {
+                    // 00Cn: Scroll the display down by n pixels
+                    0x00C0..=0x00CF => {
+                        let n = (self.opcode & 0x000F) as isize;
+                        self.scroll(0, n);
+                    },
+                    // 00Dn: Scroll the display up by n pixels (XO-CHIP)
+                    0x00D0..=0x00DF => {
+                        let n = (self.opcode & 0x000F) as isize;
+                        self.scroll(0, -n);
+                    },
+                    // 00E0
+                    0x00E0 => {
                         // Clear the screen
-                        self.screen = [false; GAME_WIDTH * GAME_HEIGHT];
+                        self.clear_screen();
                     },
-                    // 0x000e
-                    0x000E => {
+                    // 00EE
+                    0x00EE => {
                         // Return from subroutine
                         // Subroutine is the same as jump, but expects to return
-                        let return_address = self.pop();
+                        let return_address = self.pop()?;
                         self.pc = return_address;
                     },
-                    _ => {
-                        println!("No such opcode: {:#x}", self.opcode);
-                        return;
-                    }
+                    // 00FB: Scroll right by 4 pixels
+                    0x00FB => {
+                        self.scroll(4, 0);
+                    },
+                    // 00FC: Scroll left by 4 pixels
+                    0x00FC => {
+                        self.scroll(-4, 0);
+                    },
+                    // 00FD: Exit the interpreter
+                    0x00FD => {
+                        self.exited = true;
+                    },
+                    // 00FE: Switch to lo-res (64x32)
+                    0x00FE => {
+                        self.set_hires(false);
+                    },
+                    // 00FF: Switch to hi-res (128x64)
+                    0x00FF => {
+                        self.set_hires(true);
+                    },
+                    _ => return Err(self.unknown_opcode()),
                 }
             },
             // Set program counter to location NNN
@@ -154,37 +758,54 @@ impl Chip8 {
             },
             // Increment stack pointer, put current PC on top of stack. PC is set to NNN
             0x2000 => {
-                self.push(self.pc);
+                self.push(self.pc)?;
                 self.pc = self.opcode & 0x0FFF;
             },
             // 3xkk
-            // Compares register Vx to kk, if equal => pc += 2
+            // Compares register Vx to kk, if equal => skip
             0x3000 => {
                 let x = ((self.opcode & 0x0F00) >> 8) as usize;
                 let kk = (self.opcode & 0x00FF) as u8;
                 
                 if self.cpu_register_v[x] == kk {
-                    self.pc += 2;
+                    self.skip_next()?;
                 }
             },
             // 4xkk
-            // Compares register Vx to kk, if NOT equal => pc += 2
+            // Compares register Vx to kk, if NOT equal => skip
             0x4000 => {
                 let x = ((self.opcode & 0x0F00) >> 8) as usize;
                 let kk = (self.opcode & 0x00FF) as u8;
 
                 if self.cpu_register_v[x] != kk {
-                    self.pc += 2;
+                    self.skip_next()?;
                 }
             },
-            // 5xy0
-            // Compares register Vx with Vy, if equal => pc += 2
             0x5000 => {
                 let x = ((self.opcode & 0x0F00) >> 8) as usize;
-                let y = (self.opcode & 0x00F0 >> 4) as usize;
+                let y = ((self.opcode & 0x00F0) >> 4) as usize;
 
-                if self.cpu_register_v[x] == self.cpu_register_v[y] {
-                    self.pc += 2;
+                match self.opcode & 0x000F {
+                    // 5xy0
+                    // Compares register Vx with Vy, if equal => skip
+                    0x0000 => {
+                        if self.cpu_register_v[x] == self.cpu_register_v[y] {
+                            self.skip_next()?;
+                        }
+                    },
+                    // 5xy2: Store Vx through Vy in memory starting at I, I is left alone (XO-CHIP)
+                    0x0002 => {
+                        for (i, register) in self.register_range().enumerate() {
+                            self.write_byte(self.register_index as usize + i, self.cpu_register_v[register])?;
+                        }
+                    },
+                    // 5xy3: Read Vx through Vy from memory starting at I (XO-CHIP)
+                    0x0003 => {
+                        for (i, register) in self.register_range().enumerate() {
+                            self.cpu_register_v[register] = self.read_byte(self.register_index as usize + i)?;
+                        }
+                    },
+                    _ => return Err(self.unknown_opcode()),
                 }
             },
             // 6xkk
@@ -210,17 +831,33 @@ impl Chip8 {
                 let y = ((self.opcode & 0x00F0) >> 4) as usize;
 
                 match self.opcode & 0x000F {
+                    // Set Vx = Vy
+                    0x0000 => {
+                        self.cpu_register_v[x] = self.cpu_register_v[y];
+                    },
                     // Set Vx = Vx OR Vy
                     0x0001 => {
-                        self.cpu_register_v[x] = self.cpu_register_v[x] | self.cpu_register_v[y];
+                        self.cpu_register_v[x] |= self.cpu_register_v[y];
+
+                        if self.quirks.logic_resets_vf {
+                            self.cpu_register_v[0xF] = 0;
+                        }
                     },
                     // Set Vx = Vx AND Vy.
                     0x0002 => {
-                        self.cpu_register_v[x] = self.cpu_register_v[x] & self.cpu_register_v[y];
+                        self.cpu_register_v[x] &= self.cpu_register_v[y];
+
+                        if self.quirks.logic_resets_vf {
+                            self.cpu_register_v[0xF] = 0;
+                        }
                     },
                     // Set Vx = Vx XOR Vy.
                     0x0003 => {
-                        self.cpu_register_v[x] = self.cpu_register_v[x] ^ self.cpu_register_v[y];
+                        self.cpu_register_v[x] ^= self.cpu_register_v[y];
+
+                        if self.quirks.logic_resets_vf {
+                            self.cpu_register_v[0xF] = 0;
+                        }
                     },
                     // Set Vx = Vx + Vy, set VF = carry.
                     0x0004 => {
@@ -231,16 +868,23 @@ impl Chip8 {
                     },
                     // Set Vx = Vx - Vy, set VF = NOT borrow.
                     0x0005 => {
-                        let (new_vx, carry) = self.cpu_register_v[x].overflowing_sub(self.cpu_register_v[y]);
-                        let new_vf = if carry { 1 } else { 0 };
+                        let (new_vx, borrow) = self.cpu_register_v[x].overflowing_sub(self.cpu_register_v[y]);
+                        let new_vf = if borrow { 0 } else { 1 };
                         self.cpu_register_v[x] = new_vx;
                         self.cpu_register_v[0xF] = new_vf;
                     },
                     // Set Vx = Vx SHR 1.
+                    // On the VIP the value shifted is Vy, and the result lands in Vx
                     0x0006 => {
+                        let value = if self.quirks.shift_ignores_vy {
+                            self.cpu_register_v[x]
+                        } else {
+                            self.cpu_register_v[y]
+                        };
+
                         // Chip 8 is big endian
-                        self.cpu_register_v[0xF] = self.cpu_register_v[x] & 1;
-                        self.cpu_register_v[x] >>= 1;
+                        self.cpu_register_v[x] = value >> 1;
+                        self.cpu_register_v[0xF] = value & 1;
                     },
                     // Set Vx = Vy - Vx, set VF = NOT borrow.
                     0x0007 => {
@@ -252,14 +896,17 @@ impl Chip8 {
                     },
                     // Set Vx = Vx SHL 1.
                     0x000E => {
+                        let value = if self.quirks.shift_ignores_vy {
+                            self.cpu_register_v[x]
+                        } else {
+                            self.cpu_register_v[y]
+                        };
+
                         // Chip 8 is big endian
-                        self.cpu_register_v[0xF] = (self.cpu_register_v[x] >> 7) & 1;
-                        self.cpu_register_v[x] <<= 1;
+                        self.cpu_register_v[x] = value << 1;
+                        self.cpu_register_v[0xF] = (value >> 7) & 1;
                     },
-                    _ => {
-                        println!("No such opcode: {:#x}", self.opcode);
-                        return;
-                    }
+                    _ => return Err(self.unknown_opcode()),
                 }
             },
             // 9xy0 - SNE Vx, Vy
@@ -268,7 +915,7 @@ impl Chip8 {
                 let y = ((self.opcode & 0x00F0) >> 4) as usize;
 
                 if self.cpu_register_v[x] != self.cpu_register_v[y] {
-                    self.pc += 2;
+                    self.skip_next()?;
                 }
             }
             // ANNN: Register Index = NNN
@@ -276,16 +923,21 @@ impl Chip8 {
                 self.register_index = self.opcode & 0x0FFF;
             },
             // BNNN: Jump to location nnn + V0.
+            // With the jump quirk this is Bxnn: jump to xnn + Vx
             0xB000 => {
-                self.pc = (self.cpu_register_v[0] as u16) + (self.opcode & 0x0FFF);
+                let register = if self.quirks.jump_uses_vx {
+                    ((self.opcode & 0x0F00) >> 8) as usize
+                } else {
+                    0
+                };
+
+                self.pc = (self.cpu_register_v[register] as u16) + (self.opcode & 0x0FFF);
             },
             // Cxkk: Set Vx = random byte & kk.
-            // Might not work
             0xC000 => {
                 let x = ((self.opcode & 0x0F00) >> 8) as usize;
-                let kk = self.opcode & 0x00FF;
-                let random_number = rand::thread_rng().gen_range(0..=255);
-                self.cpu_register_v[x] = (random_number & kk) as u8;
+                let kk = (self.opcode & 0x00FF) as u8;
+                self.cpu_register_v[x] = self.rng.next_byte() & kk;
             },
             // Display n-byte sprite starting at memory location I at (Vx, Vy), set VF = collision.
             // Dxyn
@@ -293,28 +945,56 @@ impl Chip8 {
                 let digit2 = ((self.opcode & 0x0F00) >> 8) as usize;
                 let digit3 = ((self.opcode & 0x00F0) >> 4) as usize;
 
-                let x_coord = self.cpu_register_v[digit2] as u16;
-                let y_coord = self.cpu_register_v[digit3] as u16;
+                let width = self.width();
+                let height = self.height();
+
+                // The starting position always wraps, only the rest of the sprite gets clipped
+                let x_coord = self.cpu_register_v[digit2] as usize % width;
+                let y_coord = self.cpu_register_v[digit3] as usize % height;
 
-                let n_bytes = self.opcode & 0x000F;
+                // Dxy0 draws a 16x16 SUPER-CHIP sprite, two bytes per row
+                let (rows, row_bytes) = match self.opcode & 0x000F {
+                    0 => (16, 2),
+                    n => (n as usize, 1),
+                };
+                let sprite_bytes = rows * row_bytes;
                 let mut flipped = false;
 
-                for i in 0..n_bytes {
-                    let addr = self.register_index + i as u16;
-                    let pixels = self.memory[addr as usize];
+                // With both XO-CHIP planes selected, the sprite for the second plane
+                // follows right after the one for the first
+                let mut addr = self.register_index as usize;
+                for plane in [1u8, 2u8] {
+                    if self.planes & plane == 0 {
+                        continue;
+                    }
 
-                    // 8 bits long
-                    for j in 0..8 {
-                        if (pixels & (0b1000_0000 >> j)) != 0 {
-                            let x = (x_coord + j) as usize % GAME_WIDTH;
-                            let y = (y_coord + i) as usize % GAME_HEIGHT;
+                    for i in 0..rows {
+                        let mut pixels: u16 = 0;
+                        for b in 0..row_bytes {
+                            pixels = (pixels << 8) | self.read_byte(addr + i * row_bytes + b)? as u16;
+                        }
 
-                            let idx = x + GAME_WIDTH * y;
+                        let sprite_width = row_bytes * 8;
+                        for j in 0..sprite_width {
+                            if (pixels >> (sprite_width - 1 - j)) & 1 != 0 {
+                                if self.quirks.clip_sprites
+                                    && (x_coord + j >= width || y_coord + i >= height) {
+                                    continue;
+                                }
 
-                            flipped |= self.screen[idx];
-                            self.screen[idx] ^= true;
+                                let x = (x_coord + j) % width;
+                                let y = (y_coord + i) % height;
+
+                                let idx = x + width * y;
+
+                                flipped |= self.screen[idx] & plane != 0;
+                                self.screen[idx] ^= plane;
+                                self.display_changed = true;
+                            }
                         }
                     }
+
+                    addr += sprite_bytes;
                 }
 
                 if flipped {
@@ -332,10 +1012,11 @@ impl Chip8 {
                     // Checks the keyboard, and if the key corresponding to the value of Vx 
                     // is currently in the down position, PC is increased by 2.
                     0x009E => {
-                        let key = self.keys[self.cpu_register_v[x] as usize];
+                        // Only the low nibble of Vx names a key
+                        let key = self.keys[(self.cpu_register_v[x] & 0xF) as usize];
 
                         if key {
-                            self.pc += 2;
+                            self.skip_next()?;
                         }
                     },
                     // ExA1 - SKNP Vx
@@ -343,15 +1024,13 @@ impl Chip8 {
                     // Checks the keyboard, and if the key corresponding to the value of Vx 
                     // is currently in the up position, PC is increased by 2.
                     0x00A1 => {
-                        let key = self.keys[self.cpu_register_v[x] as usize];
+                        let key = self.keys[(self.cpu_register_v[x] & 0xF) as usize];
 
                         if !key {
-                            self.pc += 2;
+                            self.skip_next()?;
                         }
                     },
-                    _ => {
-                        println!("No such opcode: {:#x}", self.opcode);
-                    }
+                    _ => return Err(self.unknown_opcode()),
                 }
             }
             // FxZZ
@@ -359,26 +1038,44 @@ impl Chip8 {
                 let x = ((self.opcode & 0x0F00) >> 8) as usize;
 
                 match self.opcode & 0x00FF {
+                    // F000 NNNN: Set I to the 16 bit address in the next two bytes (XO-CHIP)
+                    0x0000 if x == 0 => {
+                        self.register_index = self.read_word(self.pc as usize)?;
+                        self.watch_fetch(self.pc as usize);
+                        self.pc = self.pc.wrapping_add(2);
+                    },
+                    // Fn01: Select the drawing planes given by the bitmask n (XO-CHIP)
+                    0x0001 => {
+                        self.planes = (x & 0b11) as u8;
+                    },
+                    // F002: Load the 16 byte audio pattern buffer from memory at I (XO-CHIP)
+                    0x0002 if x == 0 => {
+                        let start = self.register_index as usize;
+                        for i in 0..self.audio_pattern.len() {
+                            self.audio_pattern[i] = self.read_byte(start + i)?;
+                        }
+                    },
                     // Set Vx = delay timer value.
                     0x0007 => {
                         self.cpu_register_v[x] = self.delay_timer; 
                     },
                     // Fx0A - LD Vx, K
                     // Wait for a key press, store the value of the key in Vx.
-                    // All execution stops until a key is pressed, then the value of that key is stored in Vx.
+                    // All execution stops until a key is pressed and released, then the value of that key is stored in Vx.
                     0x000A => {
-                        let mut pressed = false;
-
-                        for i in 0..self.keys.len() {
-                            if self.keys[i] {
-                                self.cpu_register_v[x] = i as u8;
-                                pressed = true;
-                                break;
-                            }
-                        }
-
-                        if !pressed {
-                            self.pc += 2;
+                        match self.key_wait {
+                            KeyWait::Released(key) => {
+                                self.cpu_register_v[x] = key;
+                                self.key_wait = KeyWait::Idle;
+                            },
+                            // Run this instruction again until a key has been pressed and released.
+                            // The timers carry on counting down meanwhile
+                            key_wait => {
+                                if key_wait == KeyWait::Idle {
+                                    self.key_wait = KeyWait::Waiting;
+                                }
+                                self.pc = self.opcode_pc;
+                            },
                         }
                     },
                     // Set delay timer = Vx
@@ -389,15 +1086,24 @@ impl Chip8 {
                     0x0018 => {
                         self.sound_timer = self.cpu_register_v[x]; 
                     },
+                    // Fx3A: Set the audio pattern playback pitch to Vx (XO-CHIP)
+                    0x003A => {
+                        self.audio_pitch = self.cpu_register_v[x];
+                    },
                     // Set I = I + Vx
                     0x001E => {
-                        self.register_index += self.cpu_register_v[x] as u16;
+                        self.register_index = self.register_index.wrapping_add(self.cpu_register_v[x] as u16);
                     },
                     // Set I = location of sprite for digit Vx.
                     0x0029 => {
                         let font_sprite = self.cpu_register_v[x] as u16;
                         self.register_index = font_sprite * 5;
                     },
+                    // Fx30: Set I = location of the big SUPER-CHIP sprite for digit Vx.
+                    0x0030 => {
+                        let font_sprite = self.cpu_register_v[x] as u16;
+                        self.register_index = BIG_FONT_ADDRESS as u16 + font_sprite * 10;
+                    },
                     // Store BCD representation of Vx in memory locations I, I+1, and I+2.
                     0x0033 => {
                         // The interpreter takes the decimal value of Vx,
@@ -407,9 +1113,10 @@ impl Chip8 {
 
                         // Casting might not work!
                         let decimal = self.cpu_register_v[x] as u16;
-                        self.memory[self.register_index as usize] = (decimal / 100) as u8;
-                        self.memory[(self.register_index + 1) as usize] = ((decimal / 10) % 10) as u8;
-                        self.memory[(self.register_index + 2) as usize] = ((decimal / 100) % 10) as u8;
+                        let address = self.register_index as usize;
+                        self.write_byte(address, (decimal / 100) as u8)?;
+                        self.write_byte(address + 1, ((decimal / 10) % 10) as u8)?;
+                        self.write_byte(address + 2, (decimal % 10) as u8)?;
                     },
                     // Store registers V0 through Vx in memory starting at location I.
                     0x0055 => {
@@ -419,25 +1126,34 @@ impl Chip8 {
                         // Might need to check if program counter is out of range
                         // also cast
                         for i in 0..=x {
-                            self.memory[(self.register_index as usize) + i] = self.cpu_register_v[i];
+                            self.write_byte(self.register_index as usize + i, self.cpu_register_v[i])?;
                         }
+
+                        self.register_index = self.register_index.wrapping_add(self.quirks.load_store.increment(x as u16));
                     },
                     // Read registers V0 through Vx from memory starting at location I.
                     0x0065 => {
                         for i in 0..=x {
-                            self.cpu_register_v[i] = self.memory[(self.register_index as usize) + i];
+                            self.cpu_register_v[i] = self.read_byte(self.register_index as usize + i)?;
                         }
+
+                        self.register_index = self.register_index.wrapping_add(self.quirks.load_store.increment(x as u16));
                     },
-                    _ => {
-                        println!("No such opcode: {:#x}", self.opcode);
-                        return;
-                    }
+                    // Fx75: Store V0 through Vx in the RPL user flags
+                    // SUPER-CHIP only has 8 of them, XO-CHIP extends that to 16
+                    0x0075 => {
+                        self.rpl_flags[..=x].copy_from_slice(&self.cpu_register_v[..=x]);
+                    },
+                    // Fx85: Read V0 through Vx from the RPL user flags
+                    0x0085 => {
+                        self.cpu_register_v[..=x].copy_from_slice(&self.rpl_flags[..=x]);
+                    },
+                    _ => return Err(self.unknown_opcode()),
                 }
             },
-            _ => {
-                println!("No such opcode: {:#x}", self.opcode);
-                return;
-            }
+            _ => return Err(self.unknown_opcode()),
         }
+
+        Ok(())
     }
 }
@@ -0,0 +1,257 @@
+use std::slice::Iter;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chip8::scheduler::DEFAULT_INSTRUCTIONS_PER_SECOND;
+use chip8::capture::CaptureFormat;
+use chip8::filter::DEFAULT_DECAY;
+use chip8::image::ImageFormat;
+use chip8::{FilterMode, OpcodeClass, Platform, Quirks, TraceFilter, TraceFormat};
+
+use crate::audio::{AudioSettings, Waveform};
+use crate::video::Scaling;
+
+pub struct Options {
+    pub rom_path: String,
+    pub quirks: Quirks,
+    pub audio: AudioSettings,
+    pub instructions_per_second: u32,
+    pub scale: u32,
+    pub scaling: Scaling,
+    pub palette: Option<String>,
+    pub palette_file: Option<String>,
+    pub filter: FilterMode,
+    pub decay: f32,
+    pub screenshot_format: ImageFormat,
+    pub capture_format: CaptureFormat,
+    pub rewind_interval: u32,
+    pub rewind_seconds: u32,
+    // None when it should come from the clock
+    pub seed: Option<u64>,
+    pub record_movie: Option<String>,
+    pub play_movie: Option<String>,
+    pub debug: bool,
+    pub symbols: Option<String>,
+    pub trace: Option<String>,
+    pub trace_format: TraceFormat,
+    pub trace_filter: TraceFilter,
+    pub trace_last: usize,
+}
+
+fn usage() -> String {
+    String::from(
+        "Usage: chip8 [options] path-to-file\n       \
+                chip8 disasm [--syntax cowgod|octo] path-to-file\n       \
+                chip8 asm [--output FILE] [--symbols FILE] path-to-source\n\
+         \n\
+         Options:\n  \
+           --platform NAME     vip, chip48, superchip, xochip or modern (default)\n  \
+           --ips COUNT         instructions run per second (default 600)\n  \
+           --scale N           starting window size, N times 64x32 (default 10)\n  \
+           --scaling NAME      integer (default), fit or stretch to the window. F10 switches it\n  \
+           --palette NAME      mono (default), green, amber, octo, contrast, a name from the\n                      \
+                               palette file, or 2 or 4 hex colors like '#000000,#33FF66'\n  \
+           --palette-file FILE more palettes, one 'name = colors' a line. P cycles through them\n  \
+           --filter NAME       flicker filter: off (default), phosphor or blend. G switches it\n  \
+           --decay D           brightness phosphor keeps each frame, 0 to 1 (default 0.5)\n  \
+           --screenshot-format png (default) or ppm, for the screenshots F12 takes\n  \
+           --capture-format F  gif (default), y4m or ppm (a directory of frames), for F9 recordings\n  \
+           --seed NUMBER       seed for the random number generator (default: from the clock)\n  \
+           --record-movie FILE record every key press to a movie file\n  \
+           --play-movie FILE   replay a recorded movie\n  \
+           --debug             start paused, with a debugger reading commands from the terminal\n  \
+           --symbols FILE      label names for the debugger (default: the ROM's .sym file)\n  \
+           --trace FILE        write every instruction run to a file\n  \
+           --trace-format NAME text (default) or json, one object a line\n  \
+           --trace-range A-B   only trace instructions at addresses A to B\n  \
+           --trace-only LIST   only trace these classes, comma separated: flow, arithmetic,\n                      \
+                               memory, display, input, timers, random\n  \
+           --trace-last N      print the last N instructions if the ROM crashes\n  \
+           --rewind-interval N snapshot for rewinding every N frames (default 2)\n  \
+           --rewind-seconds S  how far back backspace can rewind, 0 disables it (default 10)\n  \
+           --frequency HZ      buzzer tone frequency (default 440)\n  \
+           --volume LEVEL      buzzer volume from 0 to 1 (default 0.25)\n  \
+           --waveform NAME     square (default), triangle or sine\n  \
+           --mute              start with the sound muted, M toggles it",
+    )
+}
+
+// Decimal, or hex with 0x
+fn address(value: &str, option: &str) -> Result<u16, String> {
+    let parsed = match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    };
+    parsed.ok_or_else(|| format!("{} expects an address, got '{}'", option, value))
+}
+
+fn value<'a>(iter: &mut Iter<'a, String>, option: &str) -> Result<&'a String, String> {
+    iter.next().ok_or_else(|| format!("{} needs a value\n{}", option, usage()))
+}
+
+fn number(value: &str, option: &str) -> Result<f32, String> {
+    value.parse().map_err(|_| format!("{} expects a number, got '{}'", option, value))
+}
+
+fn whole_number(value: &str, option: &str) -> Result<u32, String> {
+    value.parse().map_err(|_| format!("{} expects a whole number, got '{}'", option, value))
+}
+
+pub fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut rom_path = None;
+    let mut quirks = Quirks::default();
+    let mut audio = AudioSettings::default();
+    let mut instructions_per_second = DEFAULT_INSTRUCTIONS_PER_SECOND;
+    let mut scale = 10;
+    let mut scaling = Scaling::Integer;
+    let mut palette = None;
+    let mut palette_file = None;
+    let mut filter = FilterMode::Off;
+    let mut decay = DEFAULT_DECAY;
+    let mut screenshot_format = ImageFormat::Png;
+    let mut capture_format = CaptureFormat::Gif;
+    let mut rewind_interval = 2;
+    let mut rewind_seconds = 10;
+    let mut seed = None;
+    let mut record_movie = None;
+    let mut play_movie = None;
+    let mut debug = false;
+    let mut symbols = None;
+    let mut trace = None;
+    let mut trace_format = TraceFormat::Text;
+    let mut trace_filter = TraceFilter::default();
+    let mut trace_last = 0;
+
+    let mut iter = args[1..].iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--platform" | "--quirks" => {
+                let platform: Platform = value(&mut iter, arg)?.parse()?;
+                quirks = Quirks::for_platform(platform);
+            },
+            "--ips" => {
+                instructions_per_second = whole_number(value(&mut iter, arg)?, arg)?;
+            },
+            "--scale" => {
+                scale = whole_number(value(&mut iter, arg)?, arg)?.max(1);
+            },
+            "--scaling" => {
+                scaling = value(&mut iter, arg)?.parse()?;
+            },
+            "--palette" => {
+                palette = Some(value(&mut iter, arg)?.clone());
+            },
+            "--palette-file" => {
+                palette_file = Some(value(&mut iter, arg)?.clone());
+            },
+            "--filter" => {
+                filter = value(&mut iter, arg)?.parse()?;
+            },
+            "--decay" => {
+                decay = number(value(&mut iter, arg)?, arg)?.clamp(0.0, 1.0);
+            },
+            "--screenshot-format" => {
+                screenshot_format = value(&mut iter, arg)?.parse()?;
+            },
+            "--capture-format" => {
+                capture_format = value(&mut iter, arg)?.parse()?;
+            },
+            "--seed" => {
+                let value = value(&mut iter, arg)?;
+                seed = Some(value.parse()
+                    .map_err(|_| format!("{} expects a whole number, got '{}'", arg, value))?);
+            },
+            "--record-movie" => {
+                record_movie = Some(value(&mut iter, arg)?.clone());
+            },
+            "--play-movie" => {
+                play_movie = Some(value(&mut iter, arg)?.clone());
+            },
+            "--debug" => debug = true,
+            "--symbols" => {
+                symbols = Some(value(&mut iter, arg)?.clone());
+            },
+            "--trace" => {
+                trace = Some(value(&mut iter, arg)?.clone());
+            },
+            "--trace-format" => {
+                trace_format = value(&mut iter, arg)?.parse()?;
+            },
+            "--trace-range" => {
+                let range = value(&mut iter, arg)?;
+                let (start, end) = range.split_once('-')
+                    .ok_or_else(|| format!("{} expects a range like 0x200-0x2FF, got '{}'", arg, range))?;
+                trace_filter.start = address(start, arg)?;
+                trace_filter.end = address(end, arg)?;
+            },
+            "--trace-only" => {
+                trace_filter.classes = value(&mut iter, arg)?
+                    .split(',')
+                    .map(|class| class.trim().parse::<OpcodeClass>())
+                    .collect::<Result<_, _>>()?;
+            },
+            "--trace-last" => {
+                trace_last = whole_number(value(&mut iter, arg)?, arg)? as usize;
+            },
+            "--rewind-interval" => {
+                rewind_interval = whole_number(value(&mut iter, arg)?, arg)?;
+            },
+            "--rewind-seconds" => {
+                rewind_seconds = whole_number(value(&mut iter, arg)?, arg)?;
+            },
+            "--frequency" => {
+                audio.frequency = number(value(&mut iter, arg)?, arg)?;
+            },
+            "--volume" => {
+                audio.volume = number(value(&mut iter, arg)?, arg)?.clamp(0.0, 1.0);
+            },
+            "--waveform" => {
+                let waveform: Waveform = value(&mut iter, arg)?.parse()?;
+                audio.waveform = waveform;
+            },
+            "--mute" => audio.muted = true,
+            "-h" | "--help" => return Err(usage()),
+            _ if arg.starts_with("--") => {
+                return Err(format!("Unknown option '{}'\n{}", arg, usage()));
+            },
+            _ => rom_path = Some(arg.clone()),
+        }
+    }
+
+    let rom_path = rom_path.ok_or_else(usage)?;
+
+    // Stopping halfway through a frame would throw a movie out of sync
+    if debug && (record_movie.is_some() || play_movie.is_some()) {
+        return Err(String::from("--debug can't be combined with recording or playing a movie"));
+    }
+
+    Ok(Options {
+        rom_path,
+        quirks,
+        audio,
+        instructions_per_second,
+        scale,
+        scaling,
+        palette,
+        palette_file,
+        filter,
+        decay,
+        screenshot_format,
+        capture_format,
+        rewind_interval,
+        rewind_seconds,
+        seed,
+        record_movie,
+        play_movie,
+        debug,
+        symbols,
+        trace,
+        trace_format,
+        trace_filter,
+        trace_last,
+    })
+}
+
+// Without an explicit seed every run is different, like real hardware
+pub fn clock_seed() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64)
+}
@@ -0,0 +1,60 @@
+// The debugger's terminal side: a thread reads commands from stdin while the
+// window keeps running, and the main loop picks them up between frames
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use chip8::{Chip8, Chip8Error, Debugger};
+
+const PROMPT: &str = "(chip8) ";
+
+pub struct DebugConsole {
+    debugger: Debugger,
+    commands: Receiver<String>,
+}
+
+impl DebugConsole {
+    pub fn start(debugger: Debugger) -> DebugConsole {
+        let (sender, commands) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        println!("Debugger paused before the first instruction, type 'help' for the commands");
+        print_output("");
+        DebugConsole { debugger, commands }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.debugger.is_paused()
+    }
+
+    // Runs whatever was typed since the last call
+    pub fn handle_commands(&mut self, chip8: &Chip8) {
+        while let Ok(line) = self.commands.try_recv() {
+            let output = self.debugger.execute(&line, chip8);
+            print_output(&output);
+        }
+    }
+
+    pub fn run_frame(&mut self, chip8: &mut Chip8, cycles: u32) -> Result<(), Chip8Error> {
+        let result = self.debugger.run_frame(chip8, cycles);
+        if let Some(report) = self.debugger.take_report() {
+            print_output(&report);
+        }
+        result
+    }
+}
+
+fn print_output(output: &str) {
+    if !output.is_empty() {
+        println!("{}", output);
+    }
+    print!("{}", PROMPT);
+    let _ = io::stdout().flush();
+}
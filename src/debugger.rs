@@ -0,0 +1,569 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::asm::Symbols;
+use crate::chip_8::Chip8;
+use crate::disasm::{decode, Syntax};
+use crate::error::Chip8Error;
+use crate::watch::Watchpoint;
+
+const HELP: &str = "\
+break ADDR [if COND]   stop before the instruction at ADDR, optionally only when COND holds
+break if COND          stop before any instruction once COND holds, e.g. 'break if v3 == 5'
+watch MODE ADDR [END]  stop after an instruction reads (r), writes (w) or executes (x) memory
+                       from ADDR to END, MODE being any mix of r, w and x, e.g. 'watch rw score'
+delete N | all         remove breakpoint or watchpoint N, or all of them
+breakpoints            list the breakpoints and watchpoints
+step [N]               run N instructions (default 1), then stop
+frame                  run to the end of the current frame, then stop
+continue               carry on until a breakpoint
+pause                  stop where the program is
+regs                   show the registers, stack and timers
+mem [ADDR|i|pc] [LEN]  hex dump LEN bytes (default 64) from ADDR (default I), starting
+                       at the beginning of the 16-byte row that holds ADDR
+dis [ADDR] [COUNT]     disassemble COUNT instructions (default 8) from ADDR (default PC)
+
+Numbers are decimal, or hex with 0x. Addresses can also be labels from a symbol file.
+Conditions compare v0 to vf, i, pc, sp, dt or st to a number with == != < <= > >=";
+
+// What a condition looks at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    V(u8),
+    I,
+    Pc,
+    Sp,
+    Delay,
+    Sound,
+}
+
+impl Register {
+    fn read(&self, chip8: &Chip8) -> u16 {
+        match *self {
+            Register::V(x) => chip8.registers()[x as usize] as u16,
+            Register::I => chip8.index_register(),
+            Register::Pc => chip8.pc(),
+            Register::Sp => chip8.stack().len() as u16,
+            Register::Delay => chip8.delay_timer() as u16,
+            Register::Sound => chip8.sound_timer() as u16,
+        }
+    }
+}
+
+impl FromStr for Register {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Register, String> {
+        let name = name.to_ascii_lowercase();
+        match name.as_str() {
+            "i" => Ok(Register::I),
+            "pc" => Ok(Register::Pc),
+            "sp" => Ok(Register::Sp),
+            "dt" => Ok(Register::Delay),
+            "st" => Ok(Register::Sound),
+            _ => match name.strip_prefix('v').and_then(|digit| u8::from_str_radix(digit, 16).ok()) {
+                Some(x) if x < 16 && name.len() == 2 => Ok(Register::V(x)),
+                _ => Err(format!("Unknown register '{}'", name)),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Register::V(x) => write!(f, "v{:x}", x),
+            Register::I => write!(f, "i"),
+            Register::Pc => write!(f, "pc"),
+            Register::Sp => write!(f, "sp"),
+            Register::Delay => write!(f, "dt"),
+            Register::Sound => write!(f, "st"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    const ALL: [(&'static str, Comparison); 6] = [
+        ("==", Comparison::Equal),
+        ("!=", Comparison::NotEqual),
+        ("<=", Comparison::LessOrEqual),
+        (">=", Comparison::GreaterOrEqual),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater),
+    ];
+
+    fn symbol(&self) -> &'static str {
+        Comparison::ALL.iter().find(|(_, comparison)| comparison == self).map_or("", |(symbol, _)| symbol)
+    }
+}
+
+// A register compared to a value, e.g. v3 == 5
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub register: Register,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+impl Condition {
+    pub fn holds(&self, chip8: &Chip8) -> bool {
+        let current = self.register.read(chip8);
+        match self.comparison {
+            Comparison::Equal => current == self.value,
+            Comparison::NotEqual => current != self.value,
+            Comparison::Less => current < self.value,
+            Comparison::LessOrEqual => current <= self.value,
+            Comparison::Greater => current > self.value,
+            Comparison::GreaterOrEqual => current >= self.value,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.register, self.comparison.symbol(), self.value)
+    }
+}
+
+// Stops before the instruction at `address`, or before any instruction when
+// there's no address, but only while the condition (if any) holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub address: Option<u16>,
+    pub condition: Option<Condition>,
+}
+
+impl Breakpoint {
+    fn hit(&self, chip8: &Chip8) -> bool {
+        self.address.is_none_or(|address| address == chip8.pc())
+            && self.condition.is_none_or(|condition| condition.holds(chip8))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Running,
+    Paused,
+    // Instructions left before pausing again
+    Stepping(u32),
+    // Pause once the current frame is over
+    FinishingFrame,
+}
+
+// Runs a Chip8 one instruction at a time so it can be stopped anywhere.
+//
+// A frontend calls run_frame instead of Chip8::run_frame and feeds typed
+// commands to execute. While paused nothing runs, not even the timers.
+// Stopping in the middle of a frame keeps track of how far it got, so
+// carrying on finishes that frame before starting the next
+#[derive(Debug, Clone)]
+pub struct Debugger {
+    mode: Mode,
+    breakpoints: Vec<(usize, Breakpoint)>,
+    watchpoints: Vec<(usize, Watchpoint)>,
+    // Set when the watchpoints changed and the machine needs to hear about it
+    watchpoints_changed: bool,
+    next_id: usize,
+    symbols: Symbols,
+    // Instructions already run in the current frame
+    frame_cycles: u32,
+    // Set when resuming, so a breakpoint on the current PC doesn't stop it straight away
+    resuming: bool,
+    report: Option<String>,
+}
+
+impl Default for Debugger {
+    fn default() -> Debugger {
+        Debugger::new()
+    }
+}
+
+impl Debugger {
+    // Starts out paused, so breakpoints can be set before anything runs
+    pub fn new() -> Debugger {
+        Debugger {
+            mode: Mode::Paused,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            watchpoints_changed: false,
+            next_id: 1,
+            symbols: Symbols::new(),
+            frame_cycles: 0,
+            resuming: false,
+            report: None,
+        }
+    }
+
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.mode == Mode::Paused
+    }
+
+    pub fn pause(&mut self) {
+        self.mode = Mode::Paused;
+    }
+
+    pub fn resume(&mut self) {
+        self.set_mode(Mode::Running);
+    }
+
+    fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.resuming = true;
+    }
+
+    // Returns the breakpoint's number
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.breakpoints.push((id, breakpoint));
+        id
+    }
+
+    // Watchpoints are numbered along with the breakpoints
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.watchpoints.push((id, watchpoint));
+        self.watchpoints_changed = true;
+        id
+    }
+
+    // Removes the breakpoint or watchpoint numbered `id`
+    pub fn remove_breakpoint(&mut self, id: usize) -> bool {
+        let before = self.breakpoints.len() + self.watchpoints.len();
+        self.breakpoints.retain(|(other, _)| *other != id);
+        self.watchpoints.retain(|(other, _)| *other != id);
+        self.watchpoints_changed = true;
+        self.breakpoints.len() + self.watchpoints.len() != before
+    }
+
+    pub fn breakpoints(&self) -> &[(usize, Breakpoint)] {
+        &self.breakpoints
+    }
+
+    pub fn watchpoints(&self) -> &[(usize, Watchpoint)] {
+        &self.watchpoints
+    }
+
+    // Why the debugger last stopped, once. Frontends print it after each frame
+    pub fn take_report(&mut self) -> Option<String> {
+        self.report.take()
+    }
+
+    fn stop(&mut self, chip8: &Chip8, reason: String) {
+        self.mode = Mode::Paused;
+        self.report = Some(format!("{}\n{}", reason, self.registers(chip8)));
+    }
+
+    // Runs what's left of a frame of `cycles` instructions, then ticks the timers.
+    // Stops early on a breakpoint, or when a step is done, and ends the frame
+    // early when Fx0A is waiting for a key
+    pub fn run_frame(&mut self, chip8: &mut Chip8, cycles: u32) -> Result<(), Chip8Error> {
+        if self.mode == Mode::Paused {
+            return Ok(());
+        }
+
+        if self.watchpoints_changed {
+            let watchpoints: Vec<Watchpoint> = self.watchpoints.iter().map(|(_, watchpoint)| *watchpoint).collect();
+            chip8.set_watchpoints(&watchpoints);
+            self.watchpoints_changed = false;
+        }
+
+        while self.frame_cycles < cycles && !chip8.has_exited() {
+            if !self.resuming {
+                let hit = self.breakpoints.iter().find(|(_, breakpoint)| breakpoint.hit(chip8));
+                if let Some((id, _)) = hit {
+                    let reason = format!("Breakpoint {} hit", id);
+                    self.stop(chip8, reason);
+                    return Ok(());
+                }
+            }
+            self.resuming = false;
+
+            let step = chip8.cycle()?;
+            self.frame_cycles += 1;
+
+            // Watchpoints stop the program once the instruction that set them off is done
+            let hits = chip8.take_watch_hits();
+            if !hits.is_empty() {
+                let reason: Vec<String> = hits.iter().map(|hit| hit.to_string()).collect();
+                self.stop(chip8, reason.join("\n"));
+                return Ok(());
+            }
+
+            if let Mode::Stepping(left) = self.mode {
+                if left <= 1 {
+                    self.stop(chip8, "Stepped".to_string());
+                    return Ok(());
+                }
+                self.mode = Mode::Stepping(left - 1);
+            }
+
+            // Like Chip8::run_frame, the rest of the frame would only wait for the key too
+            if step.waiting_for_key {
+                break;
+            }
+        }
+
+        self.frame_cycles = 0;
+        chip8.cycle_timers();
+
+        if self.mode == Mode::FinishingFrame {
+            self.stop(chip8, "End of frame".to_string());
+        }
+        Ok(())
+    }
+
+    fn name(&self, address: u16) -> String {
+        match self.symbols.name(address) {
+            Some(name) => format!("0x{:03X} ({})", address, name),
+            None => format!("0x{:03X}", address),
+        }
+    }
+
+    fn range(&self, watchpoint: &Watchpoint) -> String {
+        let mode: String = [(watchpoint.read, 'r'), (watchpoint.write, 'w'), (watchpoint.execute, 'x')]
+            .iter()
+            .filter_map(|(on, c)| on.then_some(*c))
+            .collect();
+        if watchpoint.start == watchpoint.end {
+            format!("{} ({})", self.name(watchpoint.start), mode)
+        } else {
+            format!("{} to {} ({})", self.name(watchpoint.start), self.name(watchpoint.end), mode)
+        }
+    }
+
+    // Registers, stack, timers and the next instruction
+    pub fn registers(&self, chip8: &Chip8) -> String {
+        let v = chip8.registers();
+        let row = |range: std::ops::Range<usize>| {
+            range.map(|x| format!("V{:X} {:02X}", x, v[x])).collect::<Vec<String>>().join("  ")
+        };
+        let stack: Vec<String> = chip8.stack().iter().rev().map(|address| self.name(*address)).collect();
+
+        format!(
+            "PC {}  I 0x{:03X}  SP {}  DT {}  ST {}\n{}\n{}\nStack: {}\n{}",
+            self.name(chip8.pc()),
+            chip8.index_register(),
+            chip8.stack().len(),
+            chip8.delay_timer(),
+            chip8.sound_timer(),
+            row(0..8),
+            row(8..16),
+            if stack.is_empty() { "empty".to_string() } else { stack.join(", ") },
+            self.disassemble(chip8, chip8.pc(), 1)
+        )
+    }
+
+    // 16 bytes a line, starting on a multiple of 16
+    pub fn hex_dump(&self, chip8: &Chip8, address: u16, len: usize) -> String {
+        let memory = chip8.memory();
+        let start = (address as usize & !0xF).min(memory.len());
+        let end = (address as usize + len.max(1)).min(memory.len());
+
+        let mut lines = Vec::new();
+        for line_start in (start..end).step_by(16) {
+            let bytes = &memory[line_start..(line_start + 16).min(memory.len())];
+            let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            lines.push(format!("{:04X}: {}", line_start, hex.join(" ")));
+        }
+        lines.join("\n")
+    }
+
+    pub fn disassemble(&self, chip8: &Chip8, address: u16, count: usize) -> String {
+        let memory = chip8.memory();
+        let mut address = address as usize;
+        let mut lines = Vec::new();
+
+        for _ in 0..count {
+            if address + 1 >= memory.len() {
+                break;
+            }
+
+            let label = |target: u16| self.symbols.name(target).map(|name| name.to_string());
+            let (text, size) = match decode(&memory[address..]) {
+                Some(instruction) => (instruction.to_text(Syntax::Cowgod, label), instruction.size()),
+                None => ("???".to_string(), 2),
+            };
+            let opcode: Vec<String> = memory[address..address + size].iter().map(|byte| format!("{:02X}", byte)).collect();
+            let marker = if address == chip8.pc() as usize { ">" } else { " " };
+
+            lines.push(format!("{} {:03X}: {:<8}  {}", marker, address, opcode.concat(), text));
+            address += size;
+        }
+        lines.join("\n")
+    }
+
+    // Numbers, or labels from the symbol file
+    fn address(&self, text: &str) -> Result<u16, String> {
+        parse_number(text)
+            .or_else(|| self.symbols.address(text))
+            .ok_or_else(|| format!("Unknown address '{}'", text))
+    }
+
+    fn condition(&self, words: &[&str]) -> Result<Condition, String> {
+        let text = words.concat();
+        let (register, comparison, value) = Comparison::ALL
+            .iter()
+            .find_map(|(symbol, comparison)| {
+                text.split_once(symbol).map(|(register, value)| (register, *comparison, value))
+            })
+            .ok_or_else(|| format!("Bad condition '{}', expected something like 'v3 == 5'", words.join(" ")))?;
+
+        Ok(Condition {
+            register: register.parse()?,
+            comparison,
+            value: self.address(value)?,
+        })
+    }
+
+    // Runs one line typed by the user and returns what to print
+    pub fn execute(&mut self, line: &str, chip8: &Chip8) -> String {
+        match self.command(line, chip8) {
+            Ok(output) => output,
+            Err(error) => error,
+        }
+    }
+
+    fn command(&mut self, line: &str, chip8: &Chip8) -> Result<String, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let count = |index: usize, default: usize| -> Result<usize, String> {
+            match words.get(index) {
+                Some(word) => parse_number(word).map(|n| n as usize).ok_or_else(|| format!("Bad count '{}'", word)),
+                None => Ok(default),
+            }
+        };
+
+        match words.as_slice() {
+            [] => Ok(String::new()),
+            ["help" | "h" | "?"] => Ok(HELP.to_string()),
+            ["break" | "b", "if", condition @ ..] => {
+                let breakpoint = Breakpoint { address: None, condition: Some(self.condition(condition)?) };
+                Ok(format!("Breakpoint {} set", self.add_breakpoint(breakpoint)))
+            },
+            ["break" | "b", address, rest @ ..] => {
+                let address = self.address(address)?;
+                let condition = match rest {
+                    [] => None,
+                    ["if", condition @ ..] => Some(self.condition(condition)?),
+                    _ => return Err("Expected 'break ADDR if CONDITION'".to_string()),
+                };
+                let id = self.add_breakpoint(Breakpoint { address: Some(address), condition });
+                Ok(format!("Breakpoint {} at {}", id, self.name(address)))
+            },
+            ["watch" | "w", mode, start, rest @ ..] if rest.len() <= 1 => {
+                let mut watchpoint = Watchpoint { start: 0, end: 0, read: false, write: false, execute: false };
+                for c in mode.chars() {
+                    match c {
+                        'r' => watchpoint.read = true,
+                        'w' => watchpoint.write = true,
+                        'x' => watchpoint.execute = true,
+                        _ => return Err(format!("Bad watch mode '{}', expected a mix of r, w and x", mode)),
+                    }
+                }
+                watchpoint.start = self.address(start)?;
+                watchpoint.end = match rest.first() {
+                    Some(end) => self.address(end)?,
+                    None => watchpoint.start,
+                };
+                if watchpoint.end < watchpoint.start {
+                    return Err("The end of the range comes before the start".to_string());
+                }
+
+                let id = self.add_watchpoint(watchpoint);
+                Ok(format!("Watchpoint {} on {}", id, self.range(&watchpoint)))
+            },
+            ["delete" | "d", "all"] => {
+                self.breakpoints.clear();
+                self.watchpoints.clear();
+                self.watchpoints_changed = true;
+                Ok("All breakpoints deleted".to_string())
+            },
+            ["delete" | "d", id] => {
+                let id = parse_number(id).ok_or_else(|| format!("Bad breakpoint number '{}'", id))?;
+                if self.remove_breakpoint(id as usize) {
+                    Ok(format!("Breakpoint {} deleted", id))
+                } else {
+                    Err(format!("No breakpoint {}", id))
+                }
+            },
+            ["breakpoints" | "bl"] => {
+                if self.breakpoints.is_empty() && self.watchpoints.is_empty() {
+                    return Ok("No breakpoints".to_string());
+                }
+                let mut lines: Vec<(usize, String)> = self
+                    .breakpoints
+                    .iter()
+                    .map(|(id, breakpoint)| {
+                        let address = breakpoint.address.map_or("anywhere".to_string(), |address| self.name(address));
+                        match breakpoint.condition {
+                            Some(condition) => (*id, format!("{}: {} if {}", id, address, condition)),
+                            None => (*id, format!("{}: {}", id, address)),
+                        }
+                    })
+                    .collect();
+                lines.extend(self.watchpoints.iter().map(|(id, watchpoint)| {
+                    (*id, format!("{}: watch {}", id, self.range(watchpoint)))
+                }));
+                lines.sort();
+                Ok(lines.into_iter().map(|(_, line)| line).collect::<Vec<String>>().join("\n"))
+            },
+            ["step" | "s", ..] => {
+                let steps = count(1, 1)?.max(1);
+                self.set_mode(Mode::Stepping(steps as u32));
+                Ok(String::new())
+            },
+            ["frame" | "f"] => {
+                self.set_mode(Mode::FinishingFrame);
+                Ok(String::new())
+            },
+            ["continue" | "c"] => {
+                self.resume();
+                Ok("Running".to_string())
+            },
+            ["pause" | "p"] => {
+                self.pause();
+                Ok(format!("Paused\n{}", self.registers(chip8)))
+            },
+            ["regs" | "r"] => Ok(self.registers(chip8)),
+            ["mem" | "m", ..] => {
+                let address = match words.get(1).map(|word| word.to_ascii_lowercase()) {
+                    None => chip8.index_register(),
+                    Some(word) if word == "i" => chip8.index_register(),
+                    Some(word) if word == "pc" => chip8.pc(),
+                    Some(_) => self.address(words[1])?,
+                };
+                Ok(self.hex_dump(chip8, address, count(2, 64)?))
+            },
+            ["dis" | "u", ..] => {
+                let address = match words.get(1) {
+                    Some(word) if !word.eq_ignore_ascii_case("pc") => self.address(word)?,
+                    _ => chip8.pc(),
+                };
+                Ok(self.disassemble(chip8, address, count(2, 8)?))
+            },
+            [command, ..] => Err(format!("Unknown command '{}', try 'help'", command)),
+        }
+    }
+}
+
+fn parse_number(text: &str) -> Option<u16> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
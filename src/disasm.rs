@@ -0,0 +1,505 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::chip_8::PROGRAM_START;
+
+// One decoded instruction. x and y are register numbers, addresses are absolute
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    ScrollDown(u8),
+    ScrollUp(u8),
+    Clear,
+    Return,
+    ScrollRight,
+    ScrollLeft,
+    Exit,
+    Lores,
+    Hires,
+    Jump(u16),
+    Call(u16),
+    SkipEqualByte { x: u8, byte: u8 },
+    SkipNotEqualByte { x: u8, byte: u8 },
+    SkipEqual { x: u8, y: u8 },
+    SaveRange { x: u8, y: u8 },
+    LoadRange { x: u8, y: u8 },
+    LoadByte { x: u8, byte: u8 },
+    AddByte { x: u8, byte: u8 },
+    Move { x: u8, y: u8 },
+    Or { x: u8, y: u8 },
+    And { x: u8, y: u8 },
+    Xor { x: u8, y: u8 },
+    Add { x: u8, y: u8 },
+    Sub { x: u8, y: u8 },
+    ShiftRight { x: u8, y: u8 },
+    SubReverse { x: u8, y: u8 },
+    ShiftLeft { x: u8, y: u8 },
+    SkipNotEqual { x: u8, y: u8 },
+    LoadIndex(u16),
+    JumpOffset(u16),
+    Random { x: u8, byte: u8 },
+    Draw { x: u8, y: u8, n: u8 },
+    SkipKey(u8),
+    SkipNotKey(u8),
+    LoadLongIndex(u16),
+    Plane(u8),
+    Audio,
+    GetDelay(u8),
+    WaitKey(u8),
+    SetDelay(u8),
+    SetSound(u8),
+    AddIndex(u8),
+    Font(u8),
+    BigFont(u8),
+    Bcd(u8),
+    Pitch(u8),
+    Store(u8),
+    Load(u8),
+    StoreFlags(u8),
+    LoadFlags(u8),
+}
+
+// Which flavour of assembly to print
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    // The mnemonics from Cowgod's technical reference: LD V0, 0x12
+    Cowgod,
+    // Octo's assembly language: v0 := 0x12
+    Octo,
+}
+
+impl FromStr for Syntax {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Syntax, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "cowgod" => Ok(Syntax::Cowgod),
+            "octo" => Ok(Syntax::Octo),
+            _ => Err(format!("Unknown syntax '{}', expected cowgod or octo", name)),
+        }
+    }
+}
+
+// Decodes the instruction at the start of `bytes`. Returns None for anything
+// that isn't a valid opcode, or when the bytes run out halfway through one
+pub fn decode(bytes: &[u8]) -> Option<Instruction> {
+    use Instruction::*;
+
+    let opcode = u16::from_be_bytes([*bytes.first()?, *bytes.get(1)?]);
+    let x = ((opcode & 0x0F00) >> 8) as u8;
+    let y = ((opcode & 0x00F0) >> 4) as u8;
+    let n = (opcode & 0x000F) as u8;
+    let byte = (opcode & 0x00FF) as u8;
+    let address = opcode & 0x0FFF;
+
+    let instruction = match opcode & 0xF000 {
+        0x0000 => match opcode {
+            0x00C0..=0x00CF => ScrollDown(n),
+            0x00D0..=0x00DF => ScrollUp(n),
+            0x00E0 => Clear,
+            0x00EE => Return,
+            0x00FB => ScrollRight,
+            0x00FC => ScrollLeft,
+            0x00FD => Exit,
+            0x00FE => Lores,
+            0x00FF => Hires,
+            _ => return None,
+        },
+        0x1000 => Jump(address),
+        0x2000 => Call(address),
+        0x3000 => SkipEqualByte { x, byte },
+        0x4000 => SkipNotEqualByte { x, byte },
+        0x5000 => match n {
+            0x0 => SkipEqual { x, y },
+            0x2 => SaveRange { x, y },
+            0x3 => LoadRange { x, y },
+            _ => return None,
+        },
+        0x6000 => LoadByte { x, byte },
+        0x7000 => AddByte { x, byte },
+        0x8000 => match n {
+            0x0 => Move { x, y },
+            0x1 => Or { x, y },
+            0x2 => And { x, y },
+            0x3 => Xor { x, y },
+            0x4 => Add { x, y },
+            0x5 => Sub { x, y },
+            0x6 => ShiftRight { x, y },
+            0x7 => SubReverse { x, y },
+            0xE => ShiftLeft { x, y },
+            _ => return None,
+        },
+        0x9000 if n == 0 => SkipNotEqual { x, y },
+        0xA000 => LoadIndex(address),
+        0xB000 => JumpOffset(address),
+        0xC000 => Random { x, byte },
+        0xD000 => Draw { x, y, n },
+        0xE000 => match byte {
+            0x9E => SkipKey(x),
+            0xA1 => SkipNotKey(x),
+            _ => return None,
+        },
+        0xF000 => match byte {
+            0x00 if x == 0 => LoadLongIndex(u16::from_be_bytes([*bytes.get(2)?, *bytes.get(3)?])),
+            0x01 => Plane(x),
+            0x02 if x == 0 => Audio,
+            0x07 => GetDelay(x),
+            0x0A => WaitKey(x),
+            0x15 => SetDelay(x),
+            0x18 => SetSound(x),
+            0x1E => AddIndex(x),
+            0x29 => Font(x),
+            0x30 => BigFont(x),
+            0x33 => Bcd(x),
+            0x3A => Pitch(x),
+            0x55 => Store(x),
+            0x65 => Load(x),
+            0x75 => StoreFlags(x),
+            0x85 => LoadFlags(x),
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    Some(instruction)
+}
+
+impl Instruction {
+    // Size in bytes, F000 NNNN is the only one that takes four
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::LoadLongIndex(_) => 4,
+            _ => 2,
+        }
+    }
+
+    // Whether this skips over the next instruction when its condition holds
+    pub fn is_skip(&self) -> bool {
+        use Instruction::*;
+        matches!(
+            self,
+            SkipEqualByte { .. } | SkipNotEqualByte { .. } | SkipEqual { .. } | SkipNotEqual { .. }
+                | SkipKey(_) | SkipNotKey(_)
+        )
+    }
+
+    // Prints the instruction in the given syntax. `label` names an address
+    // when it can, anything it returns None for is printed as a number
+    pub fn to_text(&self, syntax: Syntax, label: impl Fn(u16) -> Option<String>) -> String {
+        match syntax {
+            Syntax::Cowgod => self.cowgod(&label),
+            Syntax::Octo => self.octo(&label),
+        }
+    }
+
+    // The address an instruction refers to, if any
+    pub fn address(&self) -> Option<u16> {
+        use Instruction::*;
+        match *self {
+            Jump(address) | Call(address) | LoadIndex(address) | JumpOffset(address) | LoadLongIndex(address) => {
+                Some(address)
+            },
+            _ => None,
+        }
+    }
+
+    fn cowgod(&self, label: &dyn Fn(u16) -> Option<String>) -> String {
+        use Instruction::*;
+        let target = |address: u16| label(address).unwrap_or_else(|| format!("0x{:03X}", address));
+        match *self {
+            ScrollDown(n) => format!("SCD {}", n),
+            ScrollUp(n) => format!("SCU {}", n),
+            Clear => "CLS".to_string(),
+            Return => "RET".to_string(),
+            ScrollRight => "SCR".to_string(),
+            ScrollLeft => "SCL".to_string(),
+            Exit => "EXIT".to_string(),
+            Lores => "LOW".to_string(),
+            Hires => "HIGH".to_string(),
+            Jump(address) => format!("JP {}", target(address)),
+            Call(address) => format!("CALL {}", target(address)),
+            SkipEqualByte { x, byte } => format!("SE V{:X}, 0x{:02X}", x, byte),
+            SkipNotEqualByte { x, byte } => format!("SNE V{:X}, 0x{:02X}", x, byte),
+            SkipEqual { x, y } => format!("SE V{:X}, V{:X}", x, y),
+            SaveRange { x, y } => format!("SAVE V{:X} - V{:X}", x, y),
+            LoadRange { x, y } => format!("LOAD V{:X} - V{:X}", x, y),
+            LoadByte { x, byte } => format!("LD V{:X}, 0x{:02X}", x, byte),
+            AddByte { x, byte } => format!("ADD V{:X}, 0x{:02X}", x, byte),
+            Move { x, y } => format!("LD V{:X}, V{:X}", x, y),
+            Or { x, y } => format!("OR V{:X}, V{:X}", x, y),
+            And { x, y } => format!("AND V{:X}, V{:X}", x, y),
+            Xor { x, y } => format!("XOR V{:X}, V{:X}", x, y),
+            Add { x, y } => format!("ADD V{:X}, V{:X}", x, y),
+            Sub { x, y } => format!("SUB V{:X}, V{:X}", x, y),
+            ShiftRight { x, y } => format!("SHR V{:X}, V{:X}", x, y),
+            SubReverse { x, y } => format!("SUBN V{:X}, V{:X}", x, y),
+            ShiftLeft { x, y } => format!("SHL V{:X}, V{:X}", x, y),
+            SkipNotEqual { x, y } => format!("SNE V{:X}, V{:X}", x, y),
+            LoadIndex(address) => format!("LD I, {}", target(address)),
+            JumpOffset(address) => format!("JP V0, {}", target(address)),
+            Random { x, byte } => format!("RND V{:X}, 0x{:02X}", x, byte),
+            Draw { x, y, n } => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+            SkipKey(x) => format!("SKP V{:X}", x),
+            SkipNotKey(x) => format!("SKNP V{:X}", x),
+            LoadLongIndex(address) => format!("LD I, LONG {}", target(address)),
+            Plane(n) => format!("PLANE {}", n),
+            Audio => "AUDIO".to_string(),
+            GetDelay(x) => format!("LD V{:X}, DT", x),
+            WaitKey(x) => format!("LD V{:X}, K", x),
+            SetDelay(x) => format!("LD DT, V{:X}", x),
+            SetSound(x) => format!("LD ST, V{:X}", x),
+            AddIndex(x) => format!("ADD I, V{:X}", x),
+            Font(x) => format!("LD F, V{:X}", x),
+            BigFont(x) => format!("LD HF, V{:X}", x),
+            Bcd(x) => format!("LD B, V{:X}", x),
+            Pitch(x) => format!("PITCH V{:X}", x),
+            Store(x) => format!("LD [I], V{:X}", x),
+            Load(x) => format!("LD V{:X}, [I]", x),
+            StoreFlags(x) => format!("LD R, V{:X}", x),
+            LoadFlags(x) => format!("LD V{:X}, R", x),
+        }
+    }
+
+    // Octo has no skip instructions, only "if ... then" guarding the next
+    // statement, which runs when the skip *doesn't* happen, so every test is inverted
+    fn octo(&self, label: &dyn Fn(u16) -> Option<String>) -> String {
+        use Instruction::*;
+        let target = |address: u16| label(address).unwrap_or_else(|| format!("0x{:03X}", address));
+        match *self {
+            ScrollDown(n) => format!("scroll-down {}", n),
+            ScrollUp(n) => format!("scroll-up {}", n),
+            Clear => "clear".to_string(),
+            Return => "return".to_string(),
+            ScrollRight => "scroll-right".to_string(),
+            ScrollLeft => "scroll-left".to_string(),
+            Exit => "exit".to_string(),
+            Lores => "lores".to_string(),
+            Hires => "hires".to_string(),
+            Jump(address) => format!("jump {}", target(address)),
+            // A bare label is a call, plain numbers need :call
+            Call(address) => label(address).unwrap_or_else(|| format!(":call 0x{:03X}", address)),
+            SkipEqualByte { x, byte } => format!("if v{:x} != 0x{:02X} then", x, byte),
+            SkipNotEqualByte { x, byte } => format!("if v{:x} == 0x{:02X} then", x, byte),
+            SkipEqual { x, y } => format!("if v{:x} != v{:x} then", x, y),
+            SaveRange { x, y } => format!("save v{:x} - v{:x}", x, y),
+            LoadRange { x, y } => format!("load v{:x} - v{:x}", x, y),
+            LoadByte { x, byte } => format!("v{:x} := 0x{:02X}", x, byte),
+            AddByte { x, byte } => format!("v{:x} += 0x{:02X}", x, byte),
+            Move { x, y } => format!("v{:x} := v{:x}", x, y),
+            Or { x, y } => format!("v{:x} |= v{:x}", x, y),
+            And { x, y } => format!("v{:x} &= v{:x}", x, y),
+            Xor { x, y } => format!("v{:x} ^= v{:x}", x, y),
+            Add { x, y } => format!("v{:x} += v{:x}", x, y),
+            Sub { x, y } => format!("v{:x} -= v{:x}", x, y),
+            ShiftRight { x, y } => format!("v{:x} >>= v{:x}", x, y),
+            SubReverse { x, y } => format!("v{:x} =- v{:x}", x, y),
+            ShiftLeft { x, y } => format!("v{:x} <<= v{:x}", x, y),
+            SkipNotEqual { x, y } => format!("if v{:x} == v{:x} then", x, y),
+            LoadIndex(address) => format!("i := {}", target(address)),
+            JumpOffset(address) => format!("jump0 {}", target(address)),
+            Random { x, byte } => format!("v{:x} := random 0x{:02X}", x, byte),
+            Draw { x, y, n } => format!("sprite v{:x} v{:x} {}", x, y, n),
+            SkipKey(x) => format!("if v{:x} -key then", x),
+            SkipNotKey(x) => format!("if v{:x} key then", x),
+            LoadLongIndex(address) => format!("i := long {}", target(address)),
+            Plane(n) => format!("plane {}", n),
+            Audio => "audio".to_string(),
+            GetDelay(x) => format!("v{:x} := delay", x),
+            WaitKey(x) => format!("v{:x} := key", x),
+            SetDelay(x) => format!("delay := v{:x}", x),
+            SetSound(x) => format!("buzzer := v{:x}", x),
+            AddIndex(x) => format!("i += v{:x}", x),
+            Font(x) => format!("i := hex v{:x}", x),
+            BigFont(x) => format!("i := bighex v{:x}", x),
+            Bcd(x) => format!("bcd v{:x}", x),
+            Pitch(x) => format!("pitch := v{:x}", x),
+            Store(x) => format!("save v{:x}", x),
+            Load(x) => format!("load v{:x}", x),
+            StoreFlags(x) => format!("saveflags v{:x}", x),
+            LoadFlags(x) => format!("loadflags v{:x}", x),
+        }
+    }
+}
+
+// Cowgod syntax with plain addresses
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_text(Syntax::Cowgod, |_| None))
+    }
+}
+
+// A ROM split into code and data.
+//
+// Bytes aren't marked as code by decoding them, since sprites and tables decode
+// as instructions too often. Instead control flow is traced from the entry point:
+// through jumps and calls, past both outcomes of every skip, stopping at returns,
+// exits and anything that doesn't decode. Whatever is never reached is data
+#[derive(Debug, Clone)]
+pub struct Disassembly {
+    rom: Vec<u8>,
+    // Offsets into the ROM where a reachable instruction starts
+    code: BTreeSet<usize>,
+    labels: BTreeMap<u16, String>,
+}
+
+impl Disassembly {
+    pub fn new(rom: &[u8]) -> Disassembly {
+        let mut disassembly = Disassembly { rom: rom.to_vec(), code: BTreeSet::new(), labels: BTreeMap::new() };
+        disassembly.trace();
+        disassembly
+    }
+
+    fn offset(&self, address: u16) -> Option<usize> {
+        let offset = (address as usize).checked_sub(PROGRAM_START)?;
+        if offset < self.rom.len() { Some(offset) } else { None }
+    }
+
+    fn trace(&mut self) {
+        let mut pending = vec![PROGRAM_START as u16];
+        let mut jumps = BTreeSet::new();
+        let mut calls = BTreeSet::new();
+        let mut data = BTreeSet::new();
+
+        while let Some(address) = pending.pop() {
+            let offset = match self.offset(address) {
+                Some(offset) if !self.code.contains(&offset) => offset,
+                _ => continue,
+            };
+            let instruction = match decode(&self.rom[offset..]) {
+                Some(instruction) => instruction,
+                None => continue,
+            };
+            self.code.insert(offset);
+
+            let next = address.wrapping_add(instruction.size() as u16);
+            match instruction {
+                Instruction::Jump(target) => {
+                    jumps.insert(target);
+                    pending.push(target);
+                },
+                Instruction::Call(target) => {
+                    calls.insert(target);
+                    pending.push(target);
+                    pending.push(next);
+                },
+                // Jump tables usually start with a jump, which is as far as tracing can follow
+                Instruction::JumpOffset(target) => {
+                    jumps.insert(target);
+                    pending.push(target);
+                },
+                Instruction::Return | Instruction::Exit => (),
+                Instruction::LoadIndex(target) | Instruction::LoadLongIndex(target) => {
+                    data.insert(target);
+                    pending.push(next);
+                },
+                _ if instruction.is_skip() => {
+                    // Skips step over a whole F000 NNNN
+                    let skipped = self.offset(next).and_then(|offset| decode(&self.rom[offset..]));
+                    pending.push(next);
+                    pending.push(next.wrapping_add(skipped.map_or(2, |skipped| skipped.size() as u16)));
+                },
+                _ => pending.push(next),
+            }
+        }
+
+        // Only addresses in the ROM get a label, the rest stay numbers.
+        // Calls win over jumps, which win over data
+        self.labels.insert(PROGRAM_START as u16, "main".to_string());
+        for (addresses, prefix) in [(calls, "sub"), (jumps, "label"), (data, "data")] {
+            for address in addresses {
+                if self.offset(address).is_some() && !self.labels.contains_key(&address) {
+                    self.labels.insert(address, format!("{}_{:03X}", prefix, address));
+                }
+            }
+        }
+    }
+
+    // Whether a reachable instruction starts at `address`
+    pub fn is_code(&self, address: u16) -> bool {
+        self.offset(address).is_some_and(|offset| self.code.contains(&offset))
+    }
+
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.labels.get(&address).map(|label| label.as_str())
+    }
+
+    pub fn labels(&self) -> &BTreeMap<u16, String> {
+        &self.labels
+    }
+
+    // Every reachable instruction in address order
+    pub fn instructions(&self) -> Vec<(u16, Instruction)> {
+        self.code
+            .iter()
+            .filter_map(|offset| {
+                let instruction = decode(&self.rom[*offset..])?;
+                Some(((PROGRAM_START + offset) as u16, instruction))
+            })
+            .collect()
+    }
+
+    // The whole ROM as a listing. Cowgod listings show each address and its
+    // bytes; Octo ones are plain source that Octo can assemble again
+    pub fn to_text(&self, syntax: Syntax) -> String {
+        let label = |address: u16| self.label(address).map(|label| label.to_string());
+        let mut text = String::new();
+        let mut data: Vec<u8> = Vec::new();
+        let mut data_start = 0;
+        let mut offset = 0;
+
+        while offset < self.rom.len() {
+            let address = (PROGRAM_START + offset) as u16;
+            let instruction = if self.code.contains(&offset) { decode(&self.rom[offset..]) } else { None };
+            let labelled = self.labels.get(&address);
+
+            // Data runs are printed in chunks, broken up by labels and code
+            if !data.is_empty() && (instruction.is_some() || labelled.is_some() || data.len() == 8) {
+                write_data(&mut text, syntax, data_start, &data);
+                data.clear();
+            }
+
+            if let Some(name) = labelled {
+                match syntax {
+                    Syntax::Cowgod => text.push_str(&format!("{}:\n", name)),
+                    Syntax::Octo => text.push_str(&format!(": {}\n", name)),
+                }
+            }
+
+            match instruction {
+                Some(instruction) => {
+                    let bytes = &self.rom[offset..offset + instruction.size()];
+                    let line = instruction.to_text(syntax, label);
+                    match syntax {
+                        Syntax::Cowgod => {
+                            let hex: Vec<String> = bytes.chunks(2).map(|word| format!("{:02X}{:02X}", word[0], word[1])).collect();
+                            text.push_str(&format!("{:03X}: {:<9}  {}\n", address, hex.join(" "), line));
+                        },
+                        Syntax::Octo => text.push_str(&format!("\t{}\n", line)),
+                    }
+                    offset += instruction.size();
+                },
+                None => {
+                    if data.is_empty() {
+                        data_start = address;
+                    }
+                    data.push(self.rom[offset]);
+                    offset += 1;
+                },
+            }
+        }
+
+        if !data.is_empty() {
+            write_data(&mut text, syntax, data_start, &data);
+        }
+
+        text
+    }
+}
+
+fn write_data(text: &mut String, syntax: Syntax, address: u16, data: &[u8]) {
+    let bytes: Vec<String> = data.iter().map(|byte| format!("0x{:02X}", byte)).collect();
+    match syntax {
+        Syntax::Cowgod => text.push_str(&format!("{:03X}: {:<9}  DB {}\n", address, "", bytes.join(", "))),
+        Syntax::Octo => text.push_str(&format!("\t{}\n", bytes.join(" "))),
+    }
+}
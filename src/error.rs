@@ -0,0 +1,72 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+// Everything that can go wrong while loading or running a ROM, or restoring a save state.
+// Each variant carries the PC of the instruction being executed (or 0x200 while loading)
+#[derive(Debug)]
+pub enum Chip8Error {
+    UnknownOpcode { opcode: u16, pc: u16 },
+    StackOverflow { pc: u16 },
+    StackUnderflow { pc: u16 },
+    MemoryOutOfBounds { address: usize, pc: u16 },
+    RomTooLarge { size: usize, max: usize, pc: u16 },
+    Io { error: io::Error, pc: u16 },
+    InvalidState { reason: String, pc: u16 },
+    IncompatibleState { version: u16, expected: u16, pc: u16 },
+}
+
+impl Chip8Error {
+    pub fn pc(&self) -> u16 {
+        match self {
+            Chip8Error::UnknownOpcode { pc, .. }
+            | Chip8Error::StackOverflow { pc }
+            | Chip8Error::StackUnderflow { pc }
+            | Chip8Error::MemoryOutOfBounds { pc, .. }
+            | Chip8Error::RomTooLarge { pc, .. }
+            | Chip8Error::Io { pc, .. }
+            | Chip8Error::InvalidState { pc, .. }
+            | Chip8Error::IncompatibleState { pc, .. } => *pc,
+        }
+    }
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Error::UnknownOpcode { opcode, pc } => {
+                write!(f, "No such opcode: {:#06x} at {:#06x}", opcode, pc)
+            },
+            Chip8Error::StackOverflow { pc } => {
+                write!(f, "Stack overflow at {:#06x}", pc)
+            },
+            Chip8Error::StackUnderflow { pc } => {
+                write!(f, "Stack underflow (return without call) at {:#06x}", pc)
+            },
+            Chip8Error::MemoryOutOfBounds { address, pc } => {
+                write!(f, "Memory access out of bounds at address {:#x} (pc {:#06x})", address, pc)
+            },
+            Chip8Error::RomTooLarge { size, max, .. } => {
+                write!(f, "ROM is {} bytes, but at most {} bytes fit in memory", size, max)
+            },
+            Chip8Error::Io { error, .. } => {
+                write!(f, "Error reading ROM: {}", error)
+            },
+            Chip8Error::InvalidState { reason, .. } => {
+                write!(f, "Invalid save state: {}", reason)
+            },
+            Chip8Error::IncompatibleState { version, expected, .. } => {
+                write!(f, "Save state has version {}, but only version {} can be loaded", version, expected)
+            },
+        }
+    }
+}
+
+impl Error for Chip8Error {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Chip8Error::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::chip_8::Display;
+use crate::image;
+
+// Sprites are drawn with XOR, so games erase and redraw anything that moves and
+// it flickers. These smooth that over the way a real screen would have
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterMode {
+    // Each frame exactly as it is
+    Off,
+    // Pixels light up at once and fade out over a few frames, like a CRT's phosphor
+    Phosphor,
+    // A pixel lit in either of the last two frames is lit
+    Blend,
+}
+
+impl FilterMode {
+    // The next one along, for switching with a key
+    pub fn next(self) -> FilterMode {
+        match self {
+            FilterMode::Off => FilterMode::Phosphor,
+            FilterMode::Phosphor => FilterMode::Blend,
+            FilterMode::Blend => FilterMode::Off,
+        }
+    }
+}
+
+impl FromStr for FilterMode {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<FilterMode, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "off" | "none" => Ok(FilterMode::Off),
+            "phosphor" => Ok(FilterMode::Phosphor),
+            "blend" => Ok(FilterMode::Blend),
+            _ => Err(format!("Unknown filter '{}', expected off, phosphor or blend", name)),
+        }
+    }
+}
+
+impl fmt::Display for FilterMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FilterMode::Off => write!(f, "off"),
+            FilterMode::Phosphor => write!(f, "phosphor"),
+            FilterMode::Blend => write!(f, "blend"),
+        }
+    }
+}
+
+// How much brightness a phosphor pixel keeps from one frame to the next by default
+pub const DEFAULT_DECAY: f32 = 0.5;
+
+// Dimmer than this and a fading pixel is off
+const DARK: f32 = 1.0 / 256.0;
+
+// Remembers the last frames shown so they can be mixed into the next one.
+//
+// push is called once per 60 Hz frame, whatever the display refresh rate, so
+// the fade takes the same time everywhere. Every mode's history is kept up to
+// date all the time, so switching modes doesn't show anything stale
+#[derive(Debug, Clone)]
+pub struct DisplayFilter {
+    mode: FilterMode,
+    decay: f32,
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+    previous: Vec<u8>,
+    // The color each pixel was last lit in and how bright it still is
+    glow: Vec<(u8, f32)>,
+}
+
+impl DisplayFilter {
+    pub fn new(mode: FilterMode, decay: f32) -> DisplayFilter {
+        DisplayFilter {
+            mode,
+            decay: decay.clamp(0.0, 1.0),
+            width: 0,
+            height: 0,
+            pixels: Vec::new(),
+            previous: Vec::new(),
+            glow: Vec::new(),
+        }
+    }
+
+    pub fn mode(&self) -> FilterMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: FilterMode) {
+        self.mode = mode;
+    }
+
+    pub fn decay(&self) -> f32 {
+        self.decay
+    }
+
+    // 0 turns pixels off straight away, values near 1 leave long trails
+    pub fn set_decay(&mut self, decay: f32) {
+        self.decay = decay.clamp(0.0, 1.0);
+    }
+
+    pub fn push(&mut self, display: &Display) {
+        // A change of resolution starts over, old pixels wouldn't line up
+        if (display.width, display.height) != (self.width, self.height) {
+            self.width = display.width;
+            self.height = display.height;
+            self.pixels = display.pixels.to_vec();
+            self.previous = display.pixels.to_vec();
+            self.glow = vec![(0, 0.0); display.pixels.len()];
+        }
+
+        self.previous.copy_from_slice(&self.pixels);
+        self.pixels.copy_from_slice(display.pixels);
+
+        for (glow, pixel) in self.glow.iter_mut().zip(display.pixels) {
+            if *pixel != 0 {
+                *glow = (*pixel, 1.0);
+            } else if glow.1 * self.decay > DARK {
+                glow.1 *= self.decay;
+            } else {
+                *glow = (0, 0.0);
+            }
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // RGB bytes for the last frame pushed, with the filter applied
+    pub fn render(&self, colors: &[[u8; 3]; 4]) -> Vec<u8> {
+        match self.mode {
+            FilterMode::Off => self.to_rgb(&self.pixels, colors),
+            FilterMode::Blend => {
+                let pixels: Vec<u8> = self.pixels.iter().zip(&self.previous).map(|(now, before)| now | before).collect();
+                self.to_rgb(&pixels, colors)
+            },
+            FilterMode::Phosphor => {
+                let background = colors[0];
+                let mut rgb = Vec::with_capacity(self.glow.len() * 3);
+                for (color, brightness) in &self.glow {
+                    let lit = colors[*color as usize & 0b11];
+                    for channel in 0..3 {
+                        let (from, to) = (background[channel] as f32, lit[channel] as f32);
+                        rgb.push((from + (to - from) * brightness).round() as u8);
+                    }
+                }
+                rgb
+            },
+        }
+    }
+
+    fn to_rgb(&self, pixels: &[u8], colors: &[[u8; 3]; 4]) -> Vec<u8> {
+        // Nothing pushed yet
+        if self.width == 0 {
+            return Vec::new();
+        }
+        image::to_rgb(&Display { width: self.width, height: self.height, pixels }, colors, 1)
+    }
+}
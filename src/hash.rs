@@ -0,0 +1,10 @@
+// FNV-1a, enough to tell ROMs or screens apart. Not meant to resist anyone
+// trying to make two of them collide
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xCBF2_9CE4_8422_2325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01B3);
+    }
+    hash
+}
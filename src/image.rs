@@ -0,0 +1,235 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::chip_8::Display;
+use crate::hash::fnv1a;
+
+// Black and white for the first plane, greys for the XO-CHIP ones
+pub const DEFAULT_COLORS: [[u8; 3]; 4] = [
+    [0, 0, 0],
+    [255, 255, 255],
+    [170, 170, 170],
+    [85, 85, 85],
+];
+
+// One character per pixel, one line per row
+pub fn to_ascii(display: &Display) -> String {
+    const CHARS: [char; 4] = ['.', '#', 'o', '@'];
+
+    let mut text = String::with_capacity((display.width + 1) * display.height);
+    for row in display.pixels.chunks(display.width) {
+        text.extend(row.iter().map(|pixel| CHARS[(*pixel & 0b11) as usize]));
+        text.push('\n');
+    }
+    text
+}
+
+// Plain (ASCII) PBM, any lit pixel is black
+pub fn to_pbm(display: &Display) -> Vec<u8> {
+    let mut text = format!("P1\n{} {}\n", display.width, display.height);
+    for row in display.pixels.chunks(display.width) {
+        let bits: Vec<&str> = row.iter().map(|pixel| if *pixel != 0 { "1" } else { "0" }).collect();
+        text.push_str(&bits.join(" "));
+        text.push('\n');
+    }
+    text.into_bytes()
+}
+
+// Hash of the resolution and every pixel, to compare screens without storing them
+pub fn display_hash(display: &Display) -> u64 {
+    let mut bytes = Vec::with_capacity(4 + display.pixels.len());
+    bytes.extend_from_slice(&(display.width as u16).to_be_bytes());
+    bytes.extend_from_slice(&(display.height as u16).to_be_bytes());
+    bytes.extend_from_slice(display.pixels);
+    fnv1a(&bytes)
+}
+
+// RGB bytes for the display, each pixel blown up to a `scale` x `scale` square
+pub fn to_rgb(display: &Display, colors: &[[u8; 3]; 4], scale: usize) -> Vec<u8> {
+    let scale = scale.max(1);
+    let mut rgb = Vec::with_capacity(display.pixels.len() * scale * scale * 3);
+
+    for row in display.pixels.chunks(display.width) {
+        let mut line = Vec::with_capacity(display.width * scale * 3);
+        for pixel in row {
+            for _ in 0..scale {
+                line.extend_from_slice(&colors[(*pixel & 0b11) as usize]);
+            }
+        }
+
+        for _ in 0..scale {
+            rgb.extend_from_slice(&line);
+        }
+    }
+    rgb
+}
+
+// Minimal PNG encoder for 8-bit RGB images. The pixel data goes into
+// uncompressed deflate blocks, which keeps this short; screens are small anyway
+pub fn encode_png(width: usize, height: usize, rgb: &[u8]) -> Vec<u8> {
+    let mut png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits per channel, truecolor, default compression, filter and no interlacing
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    write_chunk(&mut png, b"IHDR", &header);
+
+    // Every scanline starts with its filter type, 0 for none
+    let mut raw = Vec::with_capacity((width * 3 + 1) * height);
+    for line in rgb.chunks(width * 3) {
+        raw.push(0);
+        raw.extend_from_slice(line);
+    }
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut png, b"IEND", &[]);
+
+    png
+}
+
+// Binary PPM (P6), which nearly everything can open
+pub fn encode_ppm(width: usize, height: usize, rgb: &[u8]) -> Vec<u8> {
+    let mut ppm = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    ppm.extend_from_slice(rgb);
+    ppm
+}
+
+// File formats screenshots can be saved in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Ppm,
+}
+
+impl ImageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Ppm => "ppm",
+        }
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<ImageFormat, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "png" => Ok(ImageFormat::Png),
+            "ppm" => Ok(ImageFormat::Ppm),
+            _ => Err(format!("Unknown image format '{}', expected png or ppm", name)),
+        }
+    }
+}
+
+// The display as an image file, each pixel `scale` pixels wide and high
+pub fn screenshot(display: &Display, colors: &[[u8; 3]; 4], scale: usize, format: ImageFormat) -> Vec<u8> {
+    let scale = scale.max(1);
+    let rgb = to_rgb(display, colors, scale);
+    let (width, height) = (display.width * scale, display.height * scale);
+    match format {
+        ImageFormat::Png => encode_png(width, height, &rgb),
+        ImageFormat::Ppm => encode_ppm(width, height, &rgb),
+    }
+}
+
+// Writes a screenshot next to `base`, named after it and the time, e.g.
+// roms/pong-20240131-142501.png for roms/pong.ch8. A second screenshot in
+// the same second gets -2, -3 and so on added. Returns where it went
+pub fn save_screenshot(
+    display: &Display,
+    colors: &[[u8; 3]; 4],
+    scale: usize,
+    format: ImageFormat,
+    base: &Path,
+    time: SystemTime,
+) -> io::Result<PathBuf> {
+    let stem = base.file_stem().map_or("screenshot".into(), |stem| stem.to_string_lossy());
+    let name = format!("{}-{}", stem, timestamp(time));
+
+    let mut path = base.with_file_name(format!("{}.{}", name, format.extension()));
+    let mut count = 2;
+    while path.exists() {
+        path = base.with_file_name(format!("{}-{}.{}", name, count, format.extension()));
+        count += 1;
+    }
+
+    fs::write(&path, screenshot(display, colors, scale, format))?;
+    Ok(path)
+}
+
+// YYYYMMDD-HHMMSS in UTC
+pub fn timestamp(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+    let (days, rest) = (seconds / 86400, seconds % 86400);
+
+    // Days since 1970 to a calendar date, from Howard Hinnant's civil_from_days
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    format!("{:04}{:02}{:02}-{:02}{:02}{:02}", year, month, day, rest / 3600, rest / 60 % 60, rest % 60)
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // Deflate with a 32K window, no preset dictionary
+    let mut out = vec![0x78, 0x01];
+
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
@@ -0,0 +1,45 @@
+//! A Chip-8 interpreter core with the SUPER-CHIP and XO-CHIP extensions.
+//!
+//! The interpreter knows nothing about windows, audio or input devices.
+//! A frontend creates a [`Chip8`], loads a ROM, feeds it key presses and
+//! calls [`Chip8::cycle`] and [`Chip8::cycle_timers`] at the rate it wants
+//! (or lets a [`FrameScheduler`] decide), reading the screen back through
+//! [`Chip8::get_display`]. Each cycle returns a [`Step`] saying whether the
+//! screen or the buzzer changed, so there's no need to redraw every time, and
+//! the timer tick says when it ran the buzzer out.
+
+pub mod asm;
+pub mod capture;
+pub mod chip_8;
+pub mod debugger;
+pub mod disasm;
+pub mod error;
+pub mod filter;
+pub mod image;
+pub mod movie;
+pub mod palette;
+pub mod quirks;
+pub mod random;
+pub mod rewind;
+pub mod scheduler;
+pub mod state;
+pub mod trace;
+pub mod watch;
+
+mod hash;
+
+pub use asm::{Assembly, AsmError, Symbols};
+pub use capture::{Capture, CaptureFormat};
+pub use chip_8::{Chip8, Display, Step, GAME_HEIGHT, GAME_WIDTH, HIRES_HEIGHT, HIRES_WIDTH, MEMORY_SIZE, PROGRAM_START};
+pub use disasm::{Disassembly, Instruction, Syntax};
+pub use debugger::Debugger;
+pub use error::Chip8Error;
+pub use filter::{DisplayFilter, FilterMode};
+pub use movie::{Movie, MoviePlayer, MovieRecorder};
+pub use palette::Palette;
+pub use quirks::{LoadStore, Platform, Quirks};
+pub use random::{RandomSource, XorShiftRng};
+pub use rewind::RewindBuffer;
+pub use scheduler::FrameScheduler;
+pub use trace::{OpcodeClass, TraceFilter, TraceFormat, TraceRecord, Tracer};
+pub use watch::{Access, WatchHit, Watchpoint};
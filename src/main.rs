@@ -1,19 +1,30 @@
-use core::panic;
 use std::env;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
-mod chip_8;
-use chip_8::*;
+use chip8::{Chip8, Debugger, DisplayFilter, FrameScheduler, Movie, MoviePlayer, MovieRecorder, Palette, RewindBuffer, Tracer, GAME_HEIGHT, GAME_WIDTH};
+use chip8::asm::Symbols;
+use chip8::capture::{self, Capture};
+use chip8::image;
+use chip8::palette;
+use console::DebugConsole;
+use slots::SaveSlots;
+use video::Screen;
+
+mod audio;
+mod cli;
+mod console;
+mod slots;
+mod tools;
+mod video;
 use sdl2::event::Event;
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use sdl2::render::Canvas;
-use sdl2::video::Window;
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{Keycode, Mod};
 
-const SCALE: u32 = 10;
-const WINDOW_WIDTH: u32 = (GAME_WIDTH as u32) * SCALE;
-const WINDOW_HEIGHT: u32 = (GAME_HEIGHT as u32) * SCALE;
-const CYCLES_PER_FRAME: usize = 10;
+const WINDOW_TITLE: &str = "Chip-8 Emulator";
 
 fn remap_keys(key: Keycode) -> Option<usize> {
     match key {
@@ -37,53 +48,179 @@ fn remap_keys(key: Keycode) -> Option<usize> {
     }
 }
 
-fn draw_screen(chip8: &Chip8, canvas: &mut Canvas<Window>) {
-    // Clear canvas as black
-    canvas.set_draw_color(Color::RGB(0, 0, 0));
-    canvas.clear();
-
-    let screen_buf = chip8.get_display();
-    // Now set draw color to white, iterate through each point and see if it should be drawn
-    canvas.set_draw_color(Color::RGB(255, 255, 255));
-    for (i, pixel) in screen_buf.iter().enumerate() {
-        if *pixel {
-            // Convert our 1D array's index into a 2D (x,y) position
-            let x = (i % GAME_WIDTH) as u32;
-            let y = (i / GAME_WIDTH) as u32;
+// Adds the frame just run to the recording, giving up on it if that fails
+// After jumping to a snapshot, so keys that were down when it was taken don't stay held
+fn release_keys(chip8: &mut Chip8) {
+    for k in 0..16 {
+        chip8.keypress(k, false);
+    }
+}
 
-            // Draw a rectangle at (x,y), scaled up by our SCALE value
-            let rect = Rect::new((x * SCALE) as i32, (y * SCALE) as i32, SCALE, SCALE);
-            canvas.fill_rect(rect).unwrap();
+fn record_frame(capture: &mut Option<Capture>, chip8: &Chip8) {
+    if let Some(recording) = capture.as_mut() {
+        if let Err(error) = recording.add_frame(&chip8.get_display()) {
+            eprintln!("Recording stopped: {}", error);
+            *capture = None;
         }
     }
-    canvas.present();
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    if args.len() < 2 {
-        panic!("No command line argument present");
+    // Subcommands don't need a window, so they run before SDL starts
+    let result = match args.get(1).map(String::as_str) {
+        Some("disasm") => Some(tools::disasm(&args[2..])),
+        Some("asm") => Some(tools::asm(&args[2..])),
+        _ => None,
+    };
+    if let Some(result) = result {
+        if let Err(message) = result {
+            eprintln!("{}", message);
+            process::exit(2);
+        }
+        return;
+    }
+
+    let options = match cli::parse_args(&args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(2);
+        }
+    };
+
+    // The built-in palettes, then any from the file, then the one given on the command line
+    let mut palettes = Palette::builtin();
+    if let Some(path) = &options.palette_file {
+        match Palette::load(path) {
+            Ok(extra) => palettes.extend(extra),
+            Err(error) => {
+                eprintln!("{}", error);
+                process::exit(2);
+            }
+        }
     }
+    let mut palette_index = match &options.palette {
+        Some(spec) => palette::choose(&mut palettes, spec).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            process::exit(2);
+        }),
+        None => 0,
+    };
 
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
-    let window = video_subsystem
-        .window("Chip-8 Emulator", WINDOW_WIDTH, WINDOW_HEIGHT)
+    // Keep the pixels sharp when the texture is scaled up
+    sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", "nearest");
+
+    let mut window = video_subsystem
+        .window(WINDOW_TITLE, GAME_WIDTH as u32 * options.scale, GAME_HEIGHT as u32 * options.scale)
         .position_centered()
-        .opengl()
+        .resizable()
         .build()
         .unwrap();
+    let _ = window.set_minimum_size(GAME_WIDTH as u32, GAME_HEIGHT as u32);
 
-    let mut canvas = window.into_canvas().present_vsync().build().unwrap();
-    canvas.clear();
-    canvas.present();
+    // A 128x64 texture is no work even for SDL's software renderer, which runs anywhere
+    let canvas = window.into_canvas().software().present_vsync().build().unwrap();
+    let textures = canvas.texture_creator();
+    let mut screen = Screen::new(canvas, &textures, options.scaling).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        process::exit(1);
+    });
 
     let mut event_pump = sdl_context.event_pump().unwrap();
 
-    let mut chip8 = Chip8::initialize();
+    // Carry on without sound rather than refusing to start when there's no audio device
+    let mut beeper = match sdl_context.audio().and_then(|audio| audio::Beeper::new(&audio, options.audio)) {
+        Ok(beeper) => Some(beeper),
+        Err(error) => {
+            eprintln!("Audio disabled: {}", error);
+            None
+        }
+    };
+
+    let rom = match fs::read(&options.rom_path) {
+        Ok(rom) => rom,
+        Err(error) => {
+            eprintln!("Error reading ROM: {}", error);
+            process::exit(1);
+        }
+    };
+
+    // A movie being played back decides the seed and speed, so the run comes out the same
+    let mut player = match &options.play_movie {
+        Some(path) => {
+            let movie = Movie::load(path).unwrap_or_else(|error| {
+                eprintln!("{}", error);
+                process::exit(1);
+            });
+            for warning in movie.check(&rom, options.quirks) {
+                eprintln!("Warning: {}", warning);
+            }
+            Some(MoviePlayer::new(movie))
+        },
+        None => None,
+    };
+    let (seed, instructions_per_second) = match &player {
+        Some(player) => (player.movie().seed, player.movie().instructions_per_second),
+        None => (options.seed.unwrap_or_else(cli::clock_seed), options.instructions_per_second),
+    };
+
+    let mut recorder = options.record_movie.as_ref().map(|_| {
+        MovieRecorder::new(Movie::new(&rom, seed, instructions_per_second, options.quirks))
+    });
+
+    let mut chip8 = Chip8::initialize(options.quirks);
     chip8.load_fontset();
-    chip8.load_game(&args[1]);
+    // A seed from the clock is printed so the run can be reproduced with --seed
+    if player.is_none() && options.seed.is_none() {
+        eprintln!("Random seed: {}", seed);
+    }
+    chip8.seed(seed);
+    if let Err(error) = chip8.load_rom(&rom) {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+
+    if options.trace.is_some() || options.trace_last > 0 {
+        let mut tracer = Tracer::new().filter(options.trace_filter.clone()).keep_last(options.trace_last);
+        if let Some(path) = &options.trace {
+            let file = File::create(path).unwrap_or_else(|error| {
+                eprintln!("Can't create trace file {}: {}", path, error);
+                process::exit(1);
+            });
+            tracer = tracer.write_to(Box::new(BufWriter::new(file)), options.trace_format);
+        }
+        chip8.set_tracer(tracer);
+    }
+
+    // The assembler leaves its labels next to the ROM, pick them up when nobody said otherwise
+    let mut console = options.debug.then(|| {
+        let mut debugger = Debugger::new();
+        let default_symbols = Path::new(&options.rom_path).with_extension("sym");
+        let symbols_path = options.symbols.clone()
+            .or_else(|| default_symbols.exists().then(|| default_symbols.to_string_lossy().to_string()));
+        if let Some(path) = symbols_path {
+            match Symbols::load(&path) {
+                Ok(symbols) => debugger.set_symbols(symbols),
+                Err(error) => eprintln!("{}", error),
+            }
+        }
+        DebugConsole::start(debugger)
+    });
+
+    let mut scheduler = FrameScheduler::new(instructions_per_second);
+    let mut slots = SaveSlots::new(&options.rom_path);
+    let mut rewind = RewindBuffer::new(options.rewind_interval, options.rewind_seconds);
+    let mut rewinding = false;
+    let mut waiting_for_key = false;
+    let mut exit_code = 0;
+
+    let mut filter = DisplayFilter::new(options.filter, options.decay);
+    filter.push(&chip8.get_display());
+    let mut capture: Option<Capture> = None;
 
     'gameloop: loop {
         for evt in event_pump.poll_iter() {
@@ -91,25 +228,228 @@ fn main() {
                 Event::Quit{..} | Event::KeyDown{keycode: Some(Keycode::Escape), ..}=> {
                     break 'gameloop;
                 },
-                Event::KeyDown{keycode: Some(key), ..} => {
+                // F5 saves to the current slot, F7 loads from it and F6 picks the next slot
+                Event::KeyDown{keycode: Some(Keycode::F5), repeat: false, ..} => {
+                    match slots.save(&chip8) {
+                        Ok(()) => println!("Saved state to slot {}", slots.slot()),
+                        Err(error) => eprintln!("{}", error),
+                    }
+                },
+                Event::KeyDown{keycode: Some(Keycode::F6), repeat: false, ..} => {
+                    slots.next_slot();
+                    let title = format!("{} - slot {}", WINDOW_TITLE, slots.slot());
+                    let _ = screen.window_mut().set_title(&title);
+                },
+                // Jumping around in time would make a movie impossible to replay
+                Event::KeyDown{keycode: Some(Keycode::F7 | Keycode::Backspace), repeat: false, ..}
+                    if recorder.is_some() || player.is_some() => {
+                    eprintln!("Loading states and rewinding are disabled while recording or playing a movie");
+                },
+                Event::KeyDown{keycode: Some(Keycode::F7), repeat: false, ..} => {
+                    match slots.load(&mut chip8) {
+                        Ok(()) => {
+                            release_keys(&mut chip8);
+                            println!("Loaded state from slot {}", slots.slot());
+                        },
+                        Err(error) => eprintln!("{}", error),
+                    }
+                },
+                // Holding backspace plays the game backwards
+                Event::KeyDown{keycode: Some(Keycode::Backspace), ..} if recorder.is_none() && player.is_none() => {
+                    rewinding = true;
+                },
+                Event::KeyUp{keycode: Some(Keycode::Backspace), ..} if rewinding => {
+                    rewinding = false;
+                    release_keys(&mut chip8);
+                },
+                Event::KeyDown{keycode: Some(Keycode::F10), repeat: false, ..} => {
+                    screen.set_scaling(screen.scaling().next());
+                    println!("Scaling: {}", screen.scaling());
+                },
+                Event::KeyDown{keycode: Some(Keycode::F11), repeat: false, ..} => {
+                    if let Err(error) = screen.toggle_fullscreen() {
+                        eprintln!("{}", error);
+                    }
+                },
+                // F12 saves a screenshot next to the ROM, with shift at the window's --scale
+                Event::KeyDown{keycode: Some(Keycode::F12), keymod, repeat: false, ..} => {
+                    let scale = if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) { options.scale as usize } else { 1 };
+                    let colors = &palettes[palette_index].colors;
+                    let base = Path::new(&options.rom_path);
+                    match image::save_screenshot(&chip8.get_display(), colors, scale, options.screenshot_format, base, SystemTime::now()) {
+                        Ok(path) => println!("Saved screenshot to {}", path.display()),
+                        Err(error) => eprintln!("Could not save screenshot: {}", error),
+                    }
+                },
+                // F9 starts and stops recording a video next to the ROM, again shift scales it up
+                Event::KeyDown{keycode: Some(Keycode::F9), keymod, repeat: false, ..} => {
+                    match capture.take() {
+                        Some(recording) => match recording.finish() {
+                            Ok(frames) => println!("Recorded {} frames", frames),
+                            Err(error) => eprintln!("Could not finish the recording: {}", error),
+                        },
+                        None => {
+                            let scale = if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) { options.scale as usize } else { 1 };
+                            let path = capture::capture_path(Path::new(&options.rom_path), options.capture_format, SystemTime::now());
+                            match Capture::create(&path, options.capture_format, &palettes[palette_index].colors, scale) {
+                                Ok(recording) => {
+                                    println!("Recording to {}, F9 stops", path.display());
+                                    capture = Some(recording);
+                                },
+                                Err(error) => eprintln!("Could not record to {}: {}", path.display(), error),
+                            }
+                        },
+                    }
+                },
+                Event::KeyDown{keycode: Some(Keycode::P), repeat: false, ..} => {
+                    palette_index = (palette_index + 1) % palettes.len();
+                    println!("Palette: {}", palettes[palette_index].name);
+                },
+                // G switches the flicker filter, [ and ] shorten and lengthen the phosphor fade
+                Event::KeyDown{keycode: Some(Keycode::G), repeat: false, ..} => {
+                    filter.set_mode(filter.mode().next());
+                    println!("Filter: {}", filter.mode());
+                },
+                Event::KeyDown{keycode: Some(key @ (Keycode::LeftBracket | Keycode::RightBracket)), ..} => {
+                    let step = if key == Keycode::LeftBracket { -0.1 } else { 0.1 };
+                    filter.set_decay(filter.decay() + step);
+                    println!("Phosphor decay: {:.1}", filter.decay());
+                },
+                Event::KeyDown{keycode: Some(Keycode::M), repeat: false, ..} => {
+                    if let Some(beeper) = beeper.as_mut() {
+                        beeper.toggle_mute();
+                    }
+                },
+                // The keypad belongs to the movie while one is playing
+                Event::KeyDown{keycode: Some(key), repeat: false, ..} if player.is_none() => {
                     if let Some(k) = remap_keys(key) {
                         chip8.keypress(k, true);
+                        if let Some(recorder) = recorder.as_mut() {
+                            recorder.keypress(k, true);
+                        }
                     }
                 },
-                Event::KeyUp{keycode: Some(key), ..} => {
+                Event::KeyUp{keycode: Some(key), ..} if player.is_none() => {
                     if let Some(k) = remap_keys(key) {
                         chip8.keypress(k, false);
+                        if let Some(recorder) = recorder.as_mut() {
+                            recorder.keypress(k, false);
+                        }
                     }
                 },
                 _ => ()
             }
         }
 
-        for _ in 0..CYCLES_PER_FRAME {
-            chip8.cycle();
+        if let Some(console) = console.as_mut() {
+            console.handle_commands(&chip8);
         }
 
-        chip8.cycle_timers();
-        draw_screen(&chip8, &mut canvas);
+        // The display may refresh at any rate, the game always runs at 60 frames a second
+        let frames = scheduler.frames_due(Instant::now());
+        for _ in 0..frames {
+            if rewinding {
+                rewind.rewind(&mut chip8);
+                filter.push(&chip8.get_display());
+                record_frame(&mut capture, &chip8);
+                continue;
+            }
+
+            // Paused in the debugger, time stands still
+            if console.as_ref().is_some_and(|console| console.is_paused()) {
+                break;
+            }
+
+            rewind.record(&chip8);
+
+            if let Some(movie) = player.as_mut() {
+                movie.apply(&mut chip8);
+            }
+
+            let cycles = scheduler.cycles_for_frame();
+            let result = match console.as_mut() {
+                Some(console) => console.run_frame(&mut chip8, cycles),
+                None => chip8.run_frame(cycles).map(|_| ()),
+            };
+
+            if let Some(recorder) = recorder.as_mut() {
+                recorder.end_frame();
+            }
+
+            if let Err(error) = result {
+                eprintln!("{}", error);
+                if let Some(tracer) = chip8.tracer().filter(|_| options.trace_last > 0) {
+                    eprintln!("Last instructions run:\n{}", tracer.dump());
+                }
+                exit_code = 1;
+                break 'gameloop;
+            }
+
+            if chip8.has_exited() {
+                break 'gameloop;
+            }
+
+            if player.as_ref().is_some_and(|movie| movie.finished()) {
+                println!("Movie finished, the keyboard is yours");
+                player = None;
+            }
+
+            filter.push(&chip8.get_display());
+            record_frame(&mut capture, &chip8);
+        }
+
+        let paused = console.as_ref().is_some_and(|console| console.is_paused());
+        // Stepping in the debugger or loading a state doesn't wait for the next frame to show
+        if paused {
+            filter.push(&chip8.get_display());
+        }
+
+        if let Some(beeper) = beeper.as_mut() {
+            beeper.update(chip8.sound_active() && !rewinding && !paused, chip8.audio_pattern(), chip8.audio_pitch());
+        }
+
+        // Let the player know the game is sitting on Fx0A rather than frozen
+        if chip8.is_waiting_for_key() != waiting_for_key {
+            waiting_for_key = chip8.is_waiting_for_key();
+            let title = if waiting_for_key { format!("{} - press a key", WINDOW_TITLE) } else { WINDOW_TITLE.to_string() };
+            let _ = screen.window_mut().set_title(&title);
+        }
+
+        let rgb = filter.render(&palettes[palette_index].colors);
+        if let Err(error) = screen.draw(&rgb, filter.width(), filter.height()) {
+            eprintln!("{}", error);
+            exit_code = 1;
+            break 'gameloop;
+        }
+
+        // Without vsync nothing else slows the loop down, so wait for the next frame
+        if frames == 0 {
+            thread::sleep(scheduler.until_next_frame().min(Duration::from_millis(1)));
+        }
+    }
+
+    if let Some(recording) = capture {
+        match recording.finish() {
+            Ok(frames) => println!("Recorded {} frames", frames),
+            Err(error) => eprintln!("Could not finish the recording: {}", error),
+        }
+    }
+
+    if let Some(mut tracer) = chip8.take_tracer() {
+        if let Err(error) = tracer.flush() {
+            eprintln!("Error writing trace: {}", error);
+        }
+    }
+
+    // Saved even when the game crashed, that's when it's needed most
+    if let (Some(recorder), Some(path)) = (recorder, &options.record_movie) {
+        match recorder.finish().save(path) {
+            Ok(()) => println!("Movie saved to {}", path),
+            Err(error) => eprintln!("{}", error),
+        }
+    }
+
+    if exit_code != 0 {
+        process::exit(exit_code);
     }
 }
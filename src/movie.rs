@@ -0,0 +1,327 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+
+use crate::chip_8::Chip8;
+use crate::error::Chip8Error;
+use crate::hash::fnv1a;
+use crate::quirks::{LoadStore, Quirks};
+use crate::scheduler::FrameScheduler;
+
+const MOVIE_HEADER: &str = "chip8-movie 1";
+
+// One key going down or up, applied right before frame `frame` runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+    pub frame: u64,
+    pub key: usize,
+    pub pressed: bool,
+}
+
+// Everything needed to replay a session frame by frame: the inputs plus what
+// decides how the machine reacts to them.
+//
+// Stored as text, one line per header field followed by one line per event:
+//
+//     chip8-movie 1
+//     rom 5d3a0c3e7f1b2a94
+//     seed 1234
+//     ips 600
+//     quirks logic_resets_vf=0 shift_ignores_vy=1 ...
+//     frames 3600
+//     12 5 down
+//     30 5 up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: u64,
+    pub seed: u64,
+    pub instructions_per_second: u32,
+    pub quirks: Quirks,
+    pub frames: u64,
+    pub events: Vec<InputEvent>,
+}
+
+#[derive(Debug)]
+pub struct MovieError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "Invalid movie: {}", self.message)
+        } else {
+            write!(f, "Invalid movie, line {}: {}", self.line, self.message)
+        }
+    }
+}
+
+impl Error for MovieError {}
+
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    fnv1a(rom)
+}
+
+// Parses event lines in the movie format ("frame key down|up"), skipping blank
+// lines and # comments. Lines come numbered, for the errors
+fn parse_events<'a>(lines: impl Iterator<Item = (usize, &'a str)>) -> Result<Vec<InputEvent>, MovieError> {
+    let error = |line: usize, message: &str| MovieError { line, message: message.to_string() };
+    let mut events = Vec::new();
+
+    for (line, text) in lines {
+        let text = text.trim();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        if fields.len() != 3 {
+            return Err(error(line, "expected 'frame key down|up'"));
+        }
+
+        let frame = fields[0].parse().map_err(|_| error(line, "bad frame number"))?;
+        let key = usize::from_str_radix(fields[1], 16).ok().filter(|key| *key < 16)
+            .ok_or_else(|| error(line, "bad key, expected 0 to F"))?;
+        let pressed = match fields[2] {
+            "down" => true,
+            "up" => false,
+            _ => return Err(error(line, "expected down or up")),
+        };
+
+        if events.last().is_some_and(|last: &InputEvent| last.frame > frame) {
+            return Err(error(line, "events are out of order"));
+        }
+        events.push(InputEvent { frame, key, pressed });
+    }
+
+    Ok(events)
+}
+
+// A bare list of key events in the movie format, without the header.
+// Handy for scripting input by hand
+pub fn parse_input_script(text: &str) -> Result<Vec<InputEvent>, MovieError> {
+    parse_events(text.lines().enumerate().map(|(i, line)| (i + 1, line)))
+}
+
+// Each quirk as it's written in the header, flags as 0 or 1
+fn quirk_values(quirks: &Quirks) -> [(&'static str, String); 5] {
+    let flag = |value: bool| (value as u8).to_string();
+    [
+        ("logic_resets_vf", flag(quirks.logic_resets_vf)),
+        ("shift_ignores_vy", flag(quirks.shift_ignores_vy)),
+        ("load_store", quirks.load_store.to_string()),
+        ("jump_uses_vx", flag(quirks.jump_uses_vx)),
+        ("clip_sprites", flag(quirks.clip_sprites)),
+    ]
+}
+
+impl Movie {
+    pub fn new(rom: &[u8], seed: u64, instructions_per_second: u32, quirks: Quirks) -> Movie {
+        Movie {
+            rom_hash: rom_hash(rom),
+            seed,
+            instructions_per_second,
+            quirks,
+            frames: 0,
+            events: Vec::new(),
+        }
+    }
+
+    // Differences between the recording and how it's about to be played back.
+    // The movie still plays, but probably won't do the same thing
+    pub fn check(&self, rom: &[u8], quirks: Quirks) -> Vec<String> {
+        let mut warnings = Vec::new();
+
+        if rom_hash(rom) != self.rom_hash {
+            warnings.push(format!(
+                "ROM hash {:016x} doesn't match the recorded {:016x}",
+                rom_hash(rom),
+                self.rom_hash
+            ));
+        }
+
+        for ((name, recorded), (_, current)) in quirk_values(&self.quirks).iter().zip(quirk_values(&quirks)) {
+            if *recorded != current {
+                warnings.push(format!("Quirk {} is {}, but was {} when recording", name, current, recorded));
+            }
+        }
+
+        warnings
+    }
+
+    pub fn to_text(&self) -> String {
+        let quirks: Vec<String> = quirk_values(&self.quirks)
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+
+        let mut text = format!(
+            "{}\nrom {:016x}\nseed {}\nips {}\nquirks {}\nframes {}\n",
+            MOVIE_HEADER,
+            self.rom_hash,
+            self.seed,
+            self.instructions_per_second,
+            quirks.join(" "),
+            self.frames
+        );
+
+        for event in &self.events {
+            let state = if event.pressed { "down" } else { "up" };
+            text.push_str(&format!("{} {:X} {}\n", event.frame, event.key, state));
+        }
+
+        text
+    }
+
+    pub fn from_text(text: &str) -> Result<Movie, MovieError> {
+        let mut lines = text.lines().enumerate().map(|(i, line)| (i + 1, line.trim()));
+        let error = |line: usize, message: &str| MovieError { line, message: message.to_string() };
+
+        match lines.next() {
+            Some((_, MOVIE_HEADER)) => (),
+            Some((line, _)) => return Err(error(line, "not a chip8 movie, or an unsupported version")),
+            None => return Err(error(0, "file is empty")),
+        }
+
+        let mut header = |name: &str| -> Result<(usize, String), MovieError> {
+            match lines.next() {
+                Some((line, text)) => match text.strip_prefix(name).and_then(|rest| rest.strip_prefix(' ')) {
+                    Some(value) => Ok((line, value.to_string())),
+                    None => Err(error(line, &format!("expected '{}'", name))),
+                },
+                None => Err(error(0, &format!("missing '{}'", name))),
+            }
+        };
+
+        let (line, value) = header("rom")?;
+        let rom_hash = u64::from_str_radix(&value, 16).map_err(|_| error(line, "bad ROM hash"))?;
+        let (line, value) = header("seed")?;
+        let seed = value.parse().map_err(|_| error(line, "bad seed"))?;
+        let (line, value) = header("ips")?;
+        let instructions_per_second = value.parse().map_err(|_| error(line, "bad instructions per second"))?;
+
+        let (line, value) = header("quirks")?;
+        let mut quirks = Quirks::default();
+        for flag in value.split_whitespace() {
+            let (name, value) = flag.split_once('=').ok_or_else(|| error(line, "bad quirk"))?;
+            let bad_value = || error(line, &format!("bad value for quirk {}", name));
+            let flag = || match value {
+                "0" => Ok(false),
+                "1" => Ok(true),
+                _ => Err(bad_value()),
+            };
+
+            match name {
+                "logic_resets_vf" => quirks.logic_resets_vf = flag()?,
+                "shift_ignores_vy" => quirks.shift_ignores_vy = flag()?,
+                "load_store" => quirks.load_store = value.parse().map_err(|_| bad_value())?,
+                // Older movies only knew whether I moved past the last register or stayed put
+                "load_store_increments_i" => {
+                    quirks.load_store = if flag()? { LoadStore::IncrementByXPlus1 } else { LoadStore::Unchanged };
+                },
+                "jump_uses_vx" => quirks.jump_uses_vx = flag()?,
+                "clip_sprites" => quirks.clip_sprites = flag()?,
+                _ => return Err(error(line, &format!("unknown quirk {}", name))),
+            }
+        }
+
+        let (line, value) = header("frames")?;
+        let frames = value.parse().map_err(|_| error(line, "bad frame count"))?;
+
+        let events = parse_events(lines)?;
+
+        Ok(Movie { rom_hash, seed, instructions_per_second, quirks, frames, events })
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_text()).map_err(|error| format!("Could not write {}: {}", path, error))
+    }
+
+    pub fn load(path: &str) -> Result<Movie, String> {
+        let text = fs::read_to_string(path).map_err(|error| format!("Could not read {}: {}", path, error))?;
+        Movie::from_text(&text).map_err(|error| format!("{}: {}", path, error))
+    }
+
+    // Plays the whole movie on `chip8` without any frontend.
+    // The machine should be freshly loaded with the ROM; its seed is set from the movie
+    pub fn play(&self, chip8: &mut Chip8) -> Result<(), Chip8Error> {
+        chip8.seed(self.seed);
+
+        let mut player = MoviePlayer::new(self.clone());
+        let mut scheduler = FrameScheduler::new(self.instructions_per_second);
+
+        while !player.finished() && !chip8.has_exited() {
+            player.apply(chip8);
+            chip8.run_frame(scheduler.cycles_for_frame())?;
+        }
+
+        Ok(())
+    }
+}
+
+// Builds a movie while a session runs. Key changes are tagged with the frame
+// that is about to run, so call end_frame after running each one
+#[derive(Debug)]
+pub struct MovieRecorder {
+    movie: Movie,
+}
+
+impl MovieRecorder {
+    pub fn new(movie: Movie) -> MovieRecorder {
+        MovieRecorder { movie }
+    }
+
+    pub fn keypress(&mut self, key: usize, pressed: bool) {
+        self.movie.events.push(InputEvent { frame: self.movie.frames, key, pressed });
+    }
+
+    pub fn end_frame(&mut self) {
+        self.movie.frames += 1;
+    }
+
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+// Feeds a recorded movie's key changes back in, one frame at a time
+#[derive(Debug)]
+pub struct MoviePlayer {
+    movie: Movie,
+    frame: u64,
+    next_event: usize,
+}
+
+impl MoviePlayer {
+    pub fn new(movie: Movie) -> MoviePlayer {
+        MoviePlayer { movie, frame: 0, next_event: 0 }
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn finished(&self) -> bool {
+        self.frame >= self.movie.frames
+    }
+
+    // Presses and releases the keys recorded for the current frame, then moves to the next.
+    // Call right before running each frame
+    pub fn apply(&mut self, chip8: &mut Chip8) {
+        while let Some(event) = self.movie.events.get(self.next_event) {
+            if event.frame > self.frame {
+                break;
+            }
+
+            chip8.keypress(event.key, event.pressed);
+            self.next_event += 1;
+        }
+
+        self.frame += 1;
+    }
+}
@@ -0,0 +1,101 @@
+use std::fs;
+
+use crate::image::DEFAULT_COLORS;
+
+// Four colors, in the order of the pixel values in a Display: background,
+// plane 1, plane 2 and both planes. Plain CHIP-8 only ever uses the first two
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    pub name: String,
+    pub colors: [[u8; 3]; 4],
+}
+
+const BUILTIN: [(&str, [[u8; 3]; 4]); 5] = [
+    ("mono", DEFAULT_COLORS),
+    // Green phosphor, like an old monochrome monitor
+    ("green", [[8, 24, 8], [51, 255, 102], [26, 150, 64], [20, 90, 36]]),
+    ("amber", [[24, 14, 0], [255, 176, 0], [180, 110, 0], [100, 60, 0]]),
+    // The colors Octo starts with
+    ("octo", [[0x99, 0x66, 0x00], [0xFF, 0xCC, 0x00], [0xFF, 0x66, 0x00], [0x66, 0x22, 0x00]]),
+    ("contrast", [[0, 0, 0], [255, 255, 255], [255, 255, 0], [0, 255, 255]]),
+];
+
+impl Palette {
+    // The palettes that come with the interpreter, the default first
+    pub fn builtin() -> Vec<Palette> {
+        BUILTIN.iter().map(|(name, colors)| Palette { name: name.to_string(), colors: *colors }).collect()
+    }
+
+    pub fn named(name: &str) -> Option<Palette> {
+        Palette::builtin().into_iter().find(|palette| palette.name.eq_ignore_ascii_case(name))
+    }
+
+    // Two or four hex colors separated by spaces or commas, e.g. "#000000 #33FF66".
+    // With only two, both planes are drawn in the foreground color
+    pub fn parse(name: &str, text: &str) -> Result<Palette, String> {
+        let colors = text
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|word| !word.is_empty())
+            .map(parse_color)
+            .collect::<Result<Vec<[u8; 3]>, String>>()?;
+
+        let colors = match colors[..] {
+            [background, foreground] => [background, foreground, foreground, foreground],
+            [background, plane1, plane2, both] => [background, plane1, plane2, both],
+            _ => return Err(format!("A palette needs 2 or 4 colors, got {} in '{}'", colors.len(), text)),
+        };
+        Ok(Palette { name: name.to_string(), colors })
+    }
+
+    // Palette files have one palette a line, a name and its colors:
+    //
+    //   ; comments start with a semicolon
+    //   gameboy = #0F380F #9BBC0F #306230 #8BAC0F
+    pub fn parse_file(text: &str) -> Result<Vec<Palette>, String> {
+        let mut palettes = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+
+            let (name, colors) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected 'name = colors'", number + 1))?;
+            let palette = Palette::parse(name.trim(), colors).map_err(|error| format!("line {}: {}", number + 1, error))?;
+            palettes.push(palette);
+        }
+        Ok(palettes)
+    }
+
+    pub fn load(path: &str) -> Result<Vec<Palette>, String> {
+        let text = fs::read_to_string(path).map_err(|error| format!("Can't read {}: {}", path, error))?;
+        Palette::parse_file(&text).map_err(|error| format!("{}: {}", path, error))
+    }
+}
+
+// Finds the palette `spec` refers to, by name or as a list of colors, and returns
+// its index. A list of colors is added to the end of `palettes` as "custom"
+pub fn choose(palettes: &mut Vec<Palette>, spec: &str) -> Result<usize, String> {
+    if let Some(index) = palettes.iter().position(|palette| palette.name.eq_ignore_ascii_case(spec)) {
+        return Ok(index);
+    }
+    if !spec.contains(|c: char| c == '#' || c == ',' || c.is_whitespace()) {
+        let names: Vec<&str> = palettes.iter().map(|palette| palette.name.as_str()).collect();
+        return Err(format!("Unknown palette '{}', expected one of {} or a list of colors", spec, names.join(", ")));
+    }
+
+    palettes.push(Palette::parse("custom", spec)?);
+    Ok(palettes.len() - 1)
+}
+
+// "#33FF66" or "33ff66"
+pub fn parse_color(text: &str) -> Result<[u8; 3], String> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    let value = match hex.len() {
+        6 if hex.chars().all(|c| c.is_ascii_hexdigit()) => u32::from_str_radix(hex, 16).ok(),
+        _ => None,
+    };
+    let value = value.ok_or_else(|| format!("Bad color '{}', expected six hex digits like #33FF66", text))?;
+    Ok([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
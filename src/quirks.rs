@@ -0,0 +1,189 @@
+use std::fmt;
+use std::str::FromStr;
+
+// Opcodes whose behavior differs between the interpreters that shipped over the years.
+// Each flag picks one reading; the presets below match what ROMs written for
+// a given platform expect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    // 8xy1, 8xy2, 8xy3 reset VF to 0 (COSMAC VIP)
+    pub logic_resets_vf: bool,
+    // 8xy6, 8xyE shift Vx in place and ignore Vy (CHIP-48 and later)
+    pub shift_ignores_vy: bool,
+    // Where Fx55, Fx65 leave I afterwards
+    pub load_store: LoadStore,
+    // Bnnn is read as Bxnn and jumps to xnn + Vx (CHIP-48, SUPER-CHIP)
+    pub jump_uses_vx: bool,
+    // Dxyn clips sprites at the screen edge instead of wrapping them around
+    pub clip_sprites: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadStore {
+    // I stays where it was (SUPER-CHIP and most modern interpreters)
+    Unchanged,
+    // I moves on to the last register read or written (CHIP-48)
+    IncrementByX,
+    // I moves on past the last register (COSMAC VIP, XO-CHIP)
+    IncrementByXPlus1,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    Vip,
+    Chip48,
+    SuperChip,
+    XoChip,
+    Modern,
+}
+
+impl Quirks {
+    pub fn vip() -> Quirks {
+        Quirks {
+            logic_resets_vf: true,
+            shift_ignores_vy: false,
+            load_store: LoadStore::IncrementByXPlus1,
+            jump_uses_vx: false,
+            clip_sprites: true,
+        }
+    }
+
+    pub fn chip48() -> Quirks {
+        Quirks {
+            logic_resets_vf: false,
+            shift_ignores_vy: true,
+            load_store: LoadStore::IncrementByX,
+            jump_uses_vx: true,
+            clip_sprites: true,
+        }
+    }
+
+    pub fn superchip() -> Quirks {
+        Quirks {
+            logic_resets_vf: false,
+            shift_ignores_vy: true,
+            load_store: LoadStore::Unchanged,
+            jump_uses_vx: true,
+            clip_sprites: true,
+        }
+    }
+
+    pub fn xochip() -> Quirks {
+        Quirks {
+            logic_resets_vf: false,
+            shift_ignores_vy: false,
+            load_store: LoadStore::IncrementByXPlus1,
+            jump_uses_vx: false,
+            clip_sprites: false,
+        }
+    }
+
+    // What most modern interpreters (and this one, historically) do
+    pub fn modern() -> Quirks {
+        Quirks {
+            logic_resets_vf: false,
+            shift_ignores_vy: true,
+            load_store: LoadStore::Unchanged,
+            jump_uses_vx: false,
+            clip_sprites: false,
+        }
+    }
+
+    pub fn for_platform(platform: Platform) -> Quirks {
+        match platform {
+            Platform::Vip => Quirks::vip(),
+            Platform::Chip48 => Quirks::chip48(),
+            Platform::SuperChip => Quirks::superchip(),
+            Platform::XoChip => Quirks::xochip(),
+            Platform::Modern => Quirks::modern(),
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks::modern()
+    }
+}
+
+impl LoadStore {
+    pub const ALL: [LoadStore; 3] = [LoadStore::Unchanged, LoadStore::IncrementByX, LoadStore::IncrementByXPlus1];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LoadStore::Unchanged => "unchanged",
+            LoadStore::IncrementByX => "x",
+            LoadStore::IncrementByXPlus1 => "x+1",
+        }
+    }
+
+    // How far Fx55/Fx65 move I after going through V0 to Vx
+    pub fn increment(&self, x: u16) -> u16 {
+        match self {
+            LoadStore::Unchanged => 0,
+            LoadStore::IncrementByX => x,
+            LoadStore::IncrementByXPlus1 => x + 1,
+        }
+    }
+}
+
+impl fmt::Display for LoadStore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for LoadStore {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LoadStore, String> {
+        match LoadStore::ALL.iter().find(|load_store| load_store.name() == s) {
+            Some(load_store) => Ok(*load_store),
+            None => Err(format!("Unknown load/store quirk '{}', expected unchanged, x or x+1", s)),
+        }
+    }
+}
+
+impl Platform {
+    pub const ALL: [Platform; 5] = [
+        Platform::Vip,
+        Platform::Chip48,
+        Platform::SuperChip,
+        Platform::XoChip,
+        Platform::Modern,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Platform::Vip => "vip",
+            Platform::Chip48 => "chip48",
+            Platform::SuperChip => "superchip",
+            Platform::XoChip => "xochip",
+            Platform::Modern => "modern",
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Platform, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "vip" | "chip8" | "chip-8" => Ok(Platform::Vip),
+            "chip48" | "chip-48" => Ok(Platform::Chip48),
+            "superchip" | "super-chip" | "schip" => Ok(Platform::SuperChip),
+            "xochip" | "xo-chip" => Ok(Platform::XoChip),
+            "modern" => Ok(Platform::Modern),
+            _ => {
+                let names: Vec<&str> = Platform::ALL.iter().map(|p| p.name()).collect();
+                Err(format!("Unknown platform '{}', expected one of: {}", s, names.join(", ")))
+            }
+        }
+    }
+}
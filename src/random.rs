@@ -0,0 +1,59 @@
+use std::fmt;
+
+// Where Cxkk gets its random numbers from.
+// The whole generator state has to fit in a u64, so it can go into save states
+// and a restored machine carries on with the same sequence
+pub trait RandomSource: fmt::Debug + Send {
+    fn next_byte(&mut self) -> u8;
+    fn state(&self) -> u64;
+    fn set_state(&mut self, state: u64);
+}
+
+// Seed used when the caller doesn't pick one
+pub const DEFAULT_SEED: u64 = 0x00C8_1DEA;
+
+// xorshift64*, small and fast, and plenty random for games
+#[derive(Debug, Clone)]
+pub struct XorShiftRng {
+    state: u64,
+}
+
+impl XorShiftRng {
+    pub fn new(seed: u64) -> XorShiftRng {
+        // Run the seed through splitmix64 so that similar seeds give unrelated
+        // sequences. xorshift gets stuck on zero, so avoid it
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+
+        XorShiftRng { state: if z == 0 { DEFAULT_SEED } else { z } }
+    }
+}
+
+impl Default for XorShiftRng {
+    fn default() -> XorShiftRng {
+        XorShiftRng::new(DEFAULT_SEED)
+    }
+}
+
+impl RandomSource for XorShiftRng {
+    fn next_byte(&mut self) -> u8 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+
+        // The high bits are the good ones
+        (x.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+    }
+
+    fn state(&self) -> u64 {
+        self.state
+    }
+
+    fn set_state(&mut self, state: u64) {
+        self.state = if state == 0 { DEFAULT_SEED } else { state };
+    }
+}
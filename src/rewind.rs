@@ -0,0 +1,210 @@
+use std::collections::VecDeque;
+
+use crate::chip_8::Chip8;
+use crate::scheduler::TIMER_HZ;
+use crate::state::StateSink;
+
+// Keeps recent save states so the game can be played backwards.
+//
+// Only the newest snapshot is stored in full. Every older one is kept as a
+// delta that turns the snapshot after it back into itself: the two states
+// XORed together and run-length encoded. Most of memory doesn't change from
+// one frame to the next, so the XOR is almost all zeroes and packs down to
+// a handful of bytes. New snapshots are diffed against the newest one while
+// they're written, updating it in place rather than copying the machine first
+#[derive(Debug, Clone)]
+pub struct RewindBuffer {
+    interval: u32,
+    capacity: usize,
+    frames_since_record: u32,
+    newest: Option<Vec<u8>>,
+    // deltas[i] turns snapshot i + 1 into snapshot i, the oldest is at the front
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl RewindBuffer {
+    // Records a snapshot every `interval` frames, going back up to `seconds` seconds
+    pub fn new(interval: u32, seconds: u32) -> RewindBuffer {
+        let interval = interval.max(1);
+
+        RewindBuffer {
+            interval,
+            capacity: (seconds as u64 * TIMER_HZ as u64 / interval as u64).try_into().unwrap_or(usize::MAX),
+            frames_since_record: 0,
+            newest: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    // Number of snapshots that can currently be rewound to
+    pub fn len(&self) -> usize {
+        match self.newest {
+            Some(_) => self.deltas.len() + 1,
+            None => 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+        self.frames_since_record = 0;
+    }
+
+    // Bytes held by the snapshots, to keep an eye on the compression
+    pub fn memory_usage(&self) -> usize {
+        let newest = self.newest.as_ref().map_or(0, |state| state.len());
+        newest + self.deltas.iter().map(|delta| delta.len()).sum::<usize>()
+    }
+
+    // Call once per frame, every `interval`-th call takes a snapshot
+    pub fn record(&mut self, chip8: &Chip8) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.frames_since_record += 1;
+        if self.frames_since_record < self.interval {
+            return;
+        }
+        self.frames_since_record = 0;
+
+        match self.newest.as_mut() {
+            Some(newest) => match chip8.write_state(DeltaWriter::new(newest)).finish() {
+                Some(delta) => self.deltas.push_back(delta),
+                None => {
+                    // Can't diff states of different layouts, start over from this one
+                    self.deltas.clear();
+                    *newest = chip8.save_state();
+                },
+            },
+            None => self.newest = Some(chip8.save_state()),
+        }
+
+        while self.len() > self.capacity {
+            self.deltas.pop_front();
+        }
+    }
+
+    // Restores the newest snapshot and drops it, so the next call goes further back.
+    // The oldest snapshot is kept, so holding rewind stops there.
+    // Returns false when there is nothing to rewind to
+    pub fn rewind(&mut self, chip8: &mut Chip8) -> bool {
+        let newest = match self.newest.as_mut() {
+            Some(newest) => newest,
+            None => return false,
+        };
+
+        if chip8.load_state(newest).is_err() {
+            self.clear();
+            return false;
+        }
+
+        if let Some(delta) = self.deltas.pop_back() {
+            apply_delta(newest, &delta);
+        }
+        self.frames_since_record = 0;
+        true
+    }
+}
+
+// Overwrites the previous snapshot with the one being written, building the delta
+// that undoes it on the way. The delta is a sequence of runs: a varint count of
+// unchanged bytes, a varint count of changed bytes, then the changed bytes XORed
+// with the old ones
+struct DeltaWriter<'a> {
+    state: &'a mut [u8],
+    position: usize,
+    delta: Vec<u8>,
+    // The run being built
+    unchanged: usize,
+    changed: Vec<u8>,
+}
+
+impl DeltaWriter<'_> {
+    fn new(state: &mut [u8]) -> DeltaWriter<'_> {
+        DeltaWriter { state, position: 0, delta: Vec::new(), unchanged: 0, changed: Vec::new() }
+    }
+
+    fn end_run(&mut self) {
+        write_varint(&mut self.delta, self.unchanged);
+        write_varint(&mut self.delta, self.changed.len());
+        self.delta.append(&mut self.changed);
+        self.unchanged = 0;
+    }
+
+    // None when the new snapshot wasn't the same size as the old one, which is
+    // then left half overwritten
+    fn finish(mut self) -> Option<Vec<u8>> {
+        if self.position != self.state.len() {
+            return None;
+        }
+        if self.unchanged > 0 || !self.changed.is_empty() {
+            self.end_run();
+        }
+        Some(self.delta)
+    }
+}
+
+impl StateSink for DeltaWriter<'_> {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            match self.state.get_mut(self.position) {
+                Some(old) if *old == byte => {
+                    if !self.changed.is_empty() {
+                        self.end_run();
+                    }
+                    self.unchanged += 1;
+                },
+                Some(old) => {
+                    self.changed.push(*old ^ byte);
+                    *old = byte;
+                },
+                None => (),
+            }
+            self.position += 1;
+        }
+    }
+}
+
+fn apply_delta(state: &mut [u8], delta: &[u8]) {
+    let mut position = 0;
+    let mut i = 0;
+
+    while i < delta.len() {
+        position += read_varint(delta, &mut i);
+        let changed = read_varint(delta, &mut i);
+
+        for byte in &delta[i..i + changed] {
+            state[position] ^= byte;
+            position += 1;
+        }
+        i += changed;
+    }
+}
+
+fn write_varint(buffer: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        buffer.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+fn read_varint(buffer: &[u8], i: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+
+    loop {
+        let byte = buffer[*i];
+        *i += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
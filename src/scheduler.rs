@@ -0,0 +1,83 @@
+use std::time::{Duration, Instant};
+
+// The delay and sound timers always count down at 60 Hz
+pub const TIMER_HZ: u32 = 60;
+
+// 10 instructions per frame, which is what most ROMs are tuned for
+pub const DEFAULT_INSTRUCTIONS_PER_SECOND: u32 = 600;
+
+// If the host stalls for longer than this many frames, the rest is dropped
+// instead of running the game at full tilt until it catches up
+pub const DEFAULT_MAX_CATCH_UP: u32 = 6;
+
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+
+// Decides how many 60 Hz frames to run from wall-clock time, independent of
+// how often the frontend gets to draw.
+//
+// Time is kept in nanoseconds multiplied by TIMER_HZ, so a frame is exactly
+// NANOS_PER_SECOND of it and no rounding error accumulates
+#[derive(Debug, Clone)]
+pub struct FrameScheduler {
+    instructions_per_second: u32,
+    max_catch_up: u32,
+    last: Option<Instant>,
+    lag: u128,
+    cycle_remainder: u32,
+}
+
+impl FrameScheduler {
+    pub fn new(instructions_per_second: u32) -> FrameScheduler {
+        FrameScheduler {
+            instructions_per_second,
+            max_catch_up: DEFAULT_MAX_CATCH_UP,
+            last: None,
+            lag: 0,
+            cycle_remainder: 0,
+        }
+    }
+
+    pub fn max_catch_up(mut self, frames: u32) -> FrameScheduler {
+        self.max_catch_up = frames.max(1);
+        self
+    }
+
+    pub fn instructions_per_second(&self) -> u32 {
+        self.instructions_per_second
+    }
+
+    // Number of frames that became due since the last call.
+    // The first call only starts the clock
+    pub fn frames_due(&mut self, now: Instant) -> u32 {
+        let last = match self.last.replace(now) {
+            Some(last) => last,
+            None => return 0,
+        };
+
+        self.lag += now.saturating_duration_since(last).as_nanos() * TIMER_HZ as u128;
+
+        let due = self.lag / NANOS_PER_SECOND;
+        self.lag %= NANOS_PER_SECOND;
+
+        if due > self.max_catch_up as u128 {
+            self.max_catch_up
+        } else {
+            due as u32
+        }
+    }
+
+    // Time left until the next frame is due
+    pub fn until_next_frame(&self) -> Duration {
+        let remaining = (NANOS_PER_SECOND - self.lag) / TIMER_HZ as u128;
+        Duration::from_nanos(remaining as u64)
+    }
+
+    // How many instructions to run in the next frame. When the rate isn't a
+    // multiple of 60 the leftover is carried over, so over a second the total is exact
+    pub fn cycles_for_frame(&mut self) -> u32 {
+        self.cycle_remainder += self.instructions_per_second;
+        let cycles = self.cycle_remainder / TIMER_HZ;
+        self.cycle_remainder %= TIMER_HZ;
+        cycles
+    }
+}
@@ -0,0 +1,44 @@
+use std::fs;
+
+use chip8::Chip8;
+
+pub const SLOT_COUNT: u8 = 10;
+
+// Numbered save state files kept next to the ROM, e.g. pong.ch8.state3
+pub struct SaveSlots {
+    rom_path: String,
+    slot: u8,
+}
+
+impl SaveSlots {
+    pub fn new(rom_path: &str) -> SaveSlots {
+        SaveSlots {
+            rom_path: rom_path.to_string(),
+            slot: 0,
+        }
+    }
+
+    pub fn slot(&self) -> u8 {
+        self.slot
+    }
+
+    pub fn next_slot(&mut self) {
+        self.slot = (self.slot + 1) % SLOT_COUNT;
+    }
+
+    fn path(&self) -> String {
+        format!("{}.state{}", self.rom_path, self.slot)
+    }
+
+    pub fn save(&self, chip8: &Chip8) -> Result<(), String> {
+        fs::write(self.path(), chip8.save_state())
+            .map_err(|error| format!("Could not write {}: {}", self.path(), error))
+    }
+
+    pub fn load(&self, chip8: &mut Chip8) -> Result<(), String> {
+        let data = fs::read(self.path())
+            .map_err(|error| format!("Could not read {}: {}", self.path(), error))?;
+
+        chip8.load_state(&data).map_err(|error| error.to_string())
+    }
+}
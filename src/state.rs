@@ -0,0 +1,130 @@
+use crate::error::Chip8Error;
+
+// Save states start with this, followed by the format version
+pub const STATE_MAGIC: &[u8; 4] = b"C8ST";
+
+// Bump whenever the layout written by Chip8::save_state changes
+pub const STATE_VERSION: u16 = 3;
+
+// Where a StateWriter sends the bytes. A Vec just collects them, the rewind
+// buffer compares them with its previous snapshot as they arrive
+pub(crate) trait StateSink {
+    fn write(&mut self, bytes: &[u8]);
+}
+
+impl StateSink for Vec<u8> {
+    fn write(&mut self, bytes: &[u8]) {
+        self.extend_from_slice(bytes);
+    }
+}
+
+// Little-endian writer for the save state format
+pub(crate) struct StateWriter<S: StateSink> {
+    sink: S,
+}
+
+impl<S: StateSink> StateWriter<S> {
+    pub(crate) fn new(sink: S) -> StateWriter<S> {
+        let mut writer = StateWriter { sink };
+        writer.bytes(STATE_MAGIC);
+        writer.u16(STATE_VERSION);
+        writer
+    }
+
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.sink.write(&[value]);
+    }
+
+    pub(crate) fn u16(&mut self, value: u16) {
+        self.sink.write(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.sink.write(&value.to_le_bytes());
+    }
+
+    pub(crate) fn bool(&mut self, value: bool) {
+        self.sink.write(&[value as u8]);
+    }
+
+    pub(crate) fn bytes(&mut self, values: &[u8]) {
+        self.sink.write(values);
+    }
+
+    pub(crate) fn finish(self) -> S {
+        self.sink
+    }
+}
+
+// Reads back what StateWriter wrote, failing cleanly on anything truncated or malformed.
+// `pc` is the PC of the machine being restored, for the errors
+pub(crate) struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    pc: u16,
+}
+
+impl<'a> StateReader<'a> {
+    pub(crate) fn new(data: &'a [u8], pc: u16) -> Result<StateReader<'a>, Chip8Error> {
+        let mut reader = StateReader { data, position: 0, pc };
+
+        if reader.bytes(STATE_MAGIC.len())? != STATE_MAGIC {
+            return Err(reader.invalid("not a save state"));
+        }
+
+        let version = reader.u16()?;
+        if version != STATE_VERSION {
+            return Err(Chip8Error::IncompatibleState { version, expected: STATE_VERSION, pc });
+        }
+
+        Ok(reader)
+    }
+
+    pub(crate) fn invalid(&self, reason: &str) -> Chip8Error {
+        Chip8Error::InvalidState { reason: reason.to_string(), pc: self.pc }
+    }
+
+    pub(crate) fn bytes(&mut self, len: usize) -> Result<&'a [u8], Chip8Error> {
+        if self.data.len() - self.position < len {
+            return Err(self.invalid("save state is truncated"));
+        }
+
+        let bytes = &self.data[self.position..self.position + len];
+        self.position += len;
+        Ok(bytes)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, Chip8Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, Chip8Error> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, Chip8Error> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool, Chip8Error> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(self.invalid("bad boolean value")),
+        }
+    }
+
+    pub(crate) fn array<const N: usize>(&mut self) -> Result<[u8; N], Chip8Error> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+
+    pub(crate) fn finish(self) -> Result<(), Chip8Error> {
+        if self.position != self.data.len() {
+            return Err(self.invalid("unexpected data after the end of the save state"));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+// Subcommands that work on ROM files without running them, so no window is opened
+use std::fs;
+use std::path::Path;
+use std::slice::Iter;
+
+use chip8::asm;
+use chip8::{Disassembly, Syntax};
+
+fn value<'a>(iter: &mut Iter<'a, String>, option: &str, usage: &str) -> Result<&'a String, String> {
+    iter.next().ok_or_else(|| format!("{} needs a value\n{}", option, usage))
+}
+
+fn write_output(output: &Option<String>, text: &str) -> Result<(), String> {
+    match output {
+        Some(path) => fs::write(path, text).map_err(|error| format!("Could not write {}: {}", path, error)),
+        None => {
+            print!("{}", text);
+            Ok(())
+        },
+    }
+}
+
+const DISASM_USAGE: &str = "Usage: chip8 disasm [options] path-to-file\n\
+    \n\
+    Options:\n  \
+      --syntax NAME   cowgod (default) or octo\n  \
+      --output FILE   where to write the listing (default: standard output)";
+
+// chip8 disasm: prints a listing of a ROM
+pub fn disasm(args: &[String]) -> Result<(), String> {
+    let mut syntax = Syntax::Cowgod;
+    let mut output = None;
+    let mut rom_path = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--syntax" => syntax = value(&mut iter, arg, DISASM_USAGE)?.parse()?,
+            "--output" => output = Some(value(&mut iter, arg, DISASM_USAGE)?.clone()),
+            "-h" | "--help" => return Err(DISASM_USAGE.to_string()),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option '{}'\n{}", arg, DISASM_USAGE)),
+            _ => rom_path = Some(arg.clone()),
+        }
+    }
+
+    let rom_path = rom_path.ok_or_else(|| DISASM_USAGE.to_string())?;
+    let rom = fs::read(&rom_path).map_err(|error| format!("Could not read {}: {}", rom_path, error))?;
+
+    write_output(&output, &Disassembly::new(&rom).to_text(syntax))
+}
+
+const ASM_USAGE: &str = "Usage: chip8 asm [options] path-to-source\n\
+    \n\
+    Options:\n  \
+      --output FILE   where to write the ROM (default: the source with a .ch8 extension)\n  \
+      --symbols FILE  where to write the labels (default: the ROM with a .sym extension)";
+
+// chip8 asm: turns source into a ROM plus a symbol file for the debugger
+pub fn asm(args: &[String]) -> Result<(), String> {
+    let mut output = None;
+    let mut symbols = None;
+    let mut source_path = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--output" => output = Some(value(&mut iter, arg, ASM_USAGE)?.clone()),
+            "--symbols" => symbols = Some(value(&mut iter, arg, ASM_USAGE)?.clone()),
+            "-h" | "--help" => return Err(ASM_USAGE.to_string()),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option '{}'\n{}", arg, ASM_USAGE)),
+            _ => source_path = Some(arg.clone()),
+        }
+    }
+
+    let source_path = source_path.ok_or_else(|| ASM_USAGE.to_string())?;
+    let output = output.unwrap_or_else(|| Path::new(&source_path).with_extension("ch8").to_string_lossy().to_string());
+    let symbols = symbols.unwrap_or_else(|| Path::new(&output).with_extension("sym").to_string_lossy().to_string());
+
+    let assembly = asm::assemble_file(&source_path).map_err(|error| error.to_string())?;
+    fs::write(&output, &assembly.rom).map_err(|error| format!("Could not write {}: {}", output, error))?;
+    assembly.symbols.save(&symbols)?;
+
+    println!("Wrote {} bytes to {}", assembly.rom.len(), output);
+    Ok(())
+}
@@ -0,0 +1,268 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use crate::debugger::Register;
+use crate::disasm::Instruction;
+
+// Broad groups of opcodes, for tracing only the kind of instruction being debugged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpcodeClass {
+    // Jumps, calls, returns, skips and exit
+    Flow,
+    // 6xkk, 7xkk and the 8xy_ register operations
+    Arithmetic,
+    // Setting I and moving bytes between memory and the registers
+    Memory,
+    // Drawing, clearing, scrolling, resolution and plane changes
+    Display,
+    // Key skips and waiting for a key
+    Input,
+    // The delay and sound timers and XO-CHIP audio
+    Timers,
+    // Cxkk
+    Random,
+}
+
+impl OpcodeClass {
+    pub fn of(instruction: &Instruction) -> OpcodeClass {
+        use Instruction::*;
+        match instruction {
+            Return | Exit | Jump(_) | Call(_) | JumpOffset(_) | SkipEqualByte { .. } | SkipNotEqualByte { .. }
+                | SkipEqual { .. } | SkipNotEqual { .. } => OpcodeClass::Flow,
+            LoadByte { .. } | AddByte { .. } | Move { .. } | Or { .. } | And { .. } | Xor { .. } | Add { .. }
+                | Sub { .. } | ShiftRight { .. } | SubReverse { .. } | ShiftLeft { .. } => OpcodeClass::Arithmetic,
+            SaveRange { .. } | LoadRange { .. } | LoadIndex(_) | LoadLongIndex(_) | AddIndex(_) | Font(_)
+                | BigFont(_) | Bcd(_) | Store(_) | Load(_) | StoreFlags(_) | LoadFlags(_) => OpcodeClass::Memory,
+            ScrollDown(_) | ScrollUp(_) | Clear | ScrollRight | ScrollLeft | Lores | Hires | Draw { .. }
+                | Plane(_) => OpcodeClass::Display,
+            SkipKey(_) | SkipNotKey(_) | WaitKey(_) => OpcodeClass::Input,
+            GetDelay(_) | SetDelay(_) | SetSound(_) | Audio | Pitch(_) => OpcodeClass::Timers,
+            Random { .. } => OpcodeClass::Random,
+        }
+    }
+}
+
+impl FromStr for OpcodeClass {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<OpcodeClass, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "flow" => Ok(OpcodeClass::Flow),
+            "arithmetic" | "alu" => Ok(OpcodeClass::Arithmetic),
+            "memory" => Ok(OpcodeClass::Memory),
+            "display" => Ok(OpcodeClass::Display),
+            "input" => Ok(OpcodeClass::Input),
+            "timers" => Ok(OpcodeClass::Timers),
+            "random" => Ok(OpcodeClass::Random),
+            _ => Err(format!(
+                "Unknown opcode class '{}', expected flow, arithmetic, memory, display, input, timers or random",
+                name
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    // One readable line per instruction
+    Text,
+    // One JSON object per line, for feeding to other tools
+    JsonLines,
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<TraceFormat, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "text" => Ok(TraceFormat::Text),
+            "json" | "jsonl" => Ok(TraceFormat::JsonLines),
+            _ => Err(format!("Unknown trace format '{}', expected text or json", name)),
+        }
+    }
+}
+
+// Which instructions get written out. The range is inclusive and an empty
+// list of classes lets every class through
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceFilter {
+    pub start: u16,
+    pub end: u16,
+    pub classes: Vec<OpcodeClass>,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, pc: u16, instruction: Option<&Instruction>) -> bool {
+        if pc < self.start || pc > self.end {
+            return false;
+        }
+        match instruction {
+            _ if self.classes.is_empty() => true,
+            Some(instruction) => self.classes.contains(&OpcodeClass::of(instruction)),
+            None => false,
+        }
+    }
+}
+
+impl Default for TraceFilter {
+    fn default() -> TraceFilter {
+        TraceFilter { start: 0, end: u16::MAX, classes: Vec::new() }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Change {
+    pub register: Register,
+    pub old: u16,
+    pub new: u16,
+}
+
+// One executed instruction. `instruction` is None for opcodes that don't decode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub pc: u16,
+    pub opcode: u16,
+    pub instruction: Option<Instruction>,
+    pub changes: Vec<Change>,
+}
+
+impl TraceRecord {
+    fn mnemonic(&self) -> String {
+        self.instruction.map_or_else(|| "???".to_string(), |instruction| instruction.to_string())
+    }
+
+    // {"pc":514,"opcode":"6005","mnemonic":"LD V0, 0x05","changes":[{"register":"v0","old":0,"new":5}]}
+    // Mnemonics never contain quotes or backslashes, so nothing needs escaping
+    pub fn to_json(&self) -> String {
+        let changes: Vec<String> = self
+            .changes
+            .iter()
+            .map(|change| format!("{{\"register\":\"{}\",\"old\":{},\"new\":{}}}", change.register, change.old, change.new))
+            .collect();
+        format!(
+            "{{\"pc\":{},\"opcode\":\"{:04X}\",\"mnemonic\":\"{}\",\"changes\":[{}]}}",
+            self.pc,
+            self.opcode,
+            self.mnemonic(),
+            changes.join(",")
+        )
+    }
+}
+
+// 204: F033  LD B, V0          i 0x300 -> 0x303
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let changes: Vec<String> = self
+            .changes
+            .iter()
+            .map(|change| match change.register {
+                Register::I => format!("i 0x{:03X} -> 0x{:03X}", change.old, change.new),
+                register => format!("{} 0x{:02X} -> 0x{:02X}", register, change.old, change.new),
+            })
+            .collect();
+        let line = format!("{:03X}: {:04X}  {:<18} {}", self.pc, self.opcode, self.mnemonic(), changes.join(", "));
+        write!(f, "{}", line.trim_end())
+    }
+}
+
+// Records the instructions a Chip8 runs once it's handed to Chip8::set_tracer.
+//
+// Matching instructions are written to the output as they run, and the last few
+// are kept in memory regardless of the filter, so that after a crash there's a
+// record of what led up to it. With neither, tracing costs next to nothing
+pub struct Tracer {
+    output: Option<(Box<dyn Write + Send>, TraceFormat)>,
+    filter: TraceFilter,
+    history: VecDeque<TraceRecord>,
+    capacity: usize,
+    // The first write that failed. Nothing more is written after it
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    pub fn new() -> Tracer {
+        Tracer {
+            output: None,
+            filter: TraceFilter::default(),
+            history: VecDeque::new(),
+            capacity: 0,
+            error: None,
+        }
+    }
+
+    // Writes every instruction the filter lets through. Pass a BufWriter for files
+    pub fn write_to(mut self, output: Box<dyn Write + Send>, format: TraceFormat) -> Tracer {
+        self.output = Some((output, format));
+        self
+    }
+
+    pub fn filter(mut self, filter: TraceFilter) -> Tracer {
+        self.filter = filter;
+        self
+    }
+
+    // Keeps the last `count` instructions, see history
+    pub fn keep_last(mut self, count: usize) -> Tracer {
+        self.capacity = count;
+        self.history = VecDeque::with_capacity(count);
+        self
+    }
+
+    pub fn record(&mut self, record: TraceRecord) {
+        if let Some((output, format)) = self.output.as_mut() {
+            if self.error.is_none() && self.filter.matches(record.pc, record.instruction.as_ref()) {
+                let result = match format {
+                    TraceFormat::Text => writeln!(output, "{}", record),
+                    TraceFormat::JsonLines => writeln!(output, "{}", record.to_json()),
+                };
+                self.error = result.err();
+            }
+        }
+
+        if self.capacity > 0 {
+            if self.history.len() == self.capacity {
+                self.history.pop_front();
+            }
+            self.history.push_back(record);
+        }
+    }
+
+    // The last instructions run, oldest first
+    pub fn history(&self) -> impl Iterator<Item = &TraceRecord> {
+        self.history.iter()
+    }
+
+    // The history as text, one instruction a line
+    pub fn dump(&self) -> String {
+        self.history.iter().map(|record| record.to_string()).collect::<Vec<String>>().join("\n")
+    }
+
+    // Flushes the output, reporting the first write that failed if one did
+    pub fn flush(&mut self) -> io::Result<()> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        match self.output.as_mut() {
+            Some((output, _)) => output.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Default for Tracer {
+    fn default() -> Tracer {
+        Tracer::new()
+    }
+}
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Tracer")
+            .field("format", &self.output.as_ref().map(|(_, format)| format))
+            .field("filter", &self.filter)
+            .field("capacity", &self.capacity)
+            .finish()
+    }
+}
@@ -0,0 +1,183 @@
+// The game window. Each frame the colored-in screen is copied into a streaming
+// texture the size of the hi-res screen, and SDL stretches it over the window
+use std::fmt;
+use std::str::FromStr;
+
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture, TextureCreator};
+use sdl2::video::{FullscreenType, Window, WindowContext};
+
+use chip8::{HIRES_HEIGHT, HIRES_WIDTH};
+
+// Whatever the picture doesn't cover
+const BORDER: Color = Color::RGB(0, 0, 0);
+
+// How the picture is sized to the window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scaling {
+    // The largest whole number that fits, so every pixel is the same size
+    Integer,
+    // As large as fits, keeping the picture's shape
+    Fit,
+    // The whole window, whatever shape it is
+    Stretch,
+}
+
+impl Scaling {
+    // The next one along, for switching with a key
+    pub fn next(self) -> Scaling {
+        match self {
+            Scaling::Integer => Scaling::Fit,
+            Scaling::Fit => Scaling::Stretch,
+            Scaling::Stretch => Scaling::Integer,
+        }
+    }
+}
+
+impl FromStr for Scaling {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Scaling, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "integer" => Ok(Scaling::Integer),
+            "fit" => Ok(Scaling::Fit),
+            "stretch" => Ok(Scaling::Stretch),
+            _ => Err(format!("Unknown scaling '{}', expected integer, fit or stretch", s)),
+        }
+    }
+}
+
+impl fmt::Display for Scaling {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Scaling::Integer => write!(f, "integer"),
+            Scaling::Fit => write!(f, "fit"),
+            Scaling::Stretch => write!(f, "stretch"),
+        }
+    }
+}
+
+pub struct Screen<'a> {
+    canvas: Canvas<Window>,
+    // Lo-res frames only use the top left quarter
+    texture: Texture<'a>,
+    scaling: Scaling,
+}
+
+impl<'a> Screen<'a> {
+    pub fn new(canvas: Canvas<Window>, textures: &'a TextureCreator<WindowContext>, scaling: Scaling) -> Result<Screen<'a>, String> {
+        let texture = textures
+            .create_texture_streaming(PixelFormatEnum::RGB24, HIRES_WIDTH as u32, HIRES_HEIGHT as u32)
+            .map_err(|error| error.to_string())?;
+        Ok(Screen { canvas, texture, scaling })
+    }
+
+    pub fn scaling(&self) -> Scaling {
+        self.scaling
+    }
+
+    pub fn set_scaling(&mut self, scaling: Scaling) {
+        self.scaling = scaling;
+    }
+
+    pub fn window_mut(&mut self) -> &mut Window {
+        self.canvas.window_mut()
+    }
+
+    // Switches between the window and borderless fullscreen at the desktop resolution
+    pub fn toggle_fullscreen(&mut self) -> Result<(), String> {
+        let window = self.canvas.window_mut();
+        let mode = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+        window.set_fullscreen(mode)
+    }
+
+    // Shows a `width` by `height` picture, given as RGB bytes row by row
+    pub fn draw(&mut self, rgb: &[u8], width: usize, height: usize) -> Result<(), String> {
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+
+        let area = Rect::new(0, 0, width as u32, height as u32);
+        self.texture.with_lock(area, |buffer, pitch| {
+            for (y, row) in rgb.chunks(width * 3).enumerate() {
+                buffer[y * pitch..y * pitch + row.len()].copy_from_slice(row);
+            }
+        })?;
+
+        let (window_width, window_height) = self.canvas.output_size()?;
+        let target = viewport(self.scaling, window_width, window_height, area.width(), area.height());
+
+        self.canvas.set_draw_color(BORDER);
+        self.canvas.clear();
+        self.canvas.copy(&self.texture, area, target)?;
+        self.canvas.present();
+        Ok(())
+    }
+}
+
+// Where a `width` by `height` picture goes in the window, centered with bars
+// around whatever it doesn't cover. A window too small for integer scaling gets
+// the picture shrunk to fit instead, still keeping its shape
+fn viewport(scaling: Scaling, window_width: u32, window_height: u32, width: u32, height: u32) -> Rect {
+    let scale = (window_width / width).min(window_height / height);
+    let (scaled_width, scaled_height) = match scaling {
+        Scaling::Integer if scale > 0 => (width * scale, height * scale),
+        Scaling::Stretch => (window_width, window_height),
+        _ => {
+            let scaled_width = window_width.min(window_height * width / height).max(1);
+            (scaled_width, (scaled_width * height / width).max(1))
+        },
+    };
+
+    Rect::new(
+        (window_width.saturating_sub(scaled_width) / 2) as i32,
+        (window_height.saturating_sub(scaled_height) / 2) as i32,
+        scaled_width,
+        scaled_height,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integer_scaling_keeps_pixels_square() {
+        // 10 times fits across and 12 times down, so 10 it is, with bars all round
+        assert_eq!(viewport(Scaling::Integer, 700, 400, 64, 32), Rect::new(30, 40, 640, 320));
+        // Hi-res frames come out the same size
+        assert_eq!(viewport(Scaling::Integer, 700, 400, 128, 64), Rect::new(30, 40, 640, 320));
+        assert_eq!(viewport(Scaling::Integer, 640, 320, 64, 32), Rect::new(0, 0, 640, 320));
+    }
+
+    #[test]
+    fn small_windows_shrink_the_picture() {
+        assert_eq!(viewport(Scaling::Integer, 100, 40, 128, 64), Rect::new(10, 0, 80, 40));
+        assert_eq!(viewport(Scaling::Integer, 0, 0, 64, 32), Rect::new(0, 0, 1, 1));
+    }
+
+    #[test]
+    fn fit_fills_one_side() {
+        assert_eq!(viewport(Scaling::Fit, 700, 400, 64, 32), Rect::new(0, 25, 700, 350));
+        assert_eq!(viewport(Scaling::Fit, 700, 300, 128, 64), Rect::new(50, 0, 600, 300));
+    }
+
+    #[test]
+    fn stretch_fills_the_window() {
+        assert_eq!(viewport(Scaling::Stretch, 700, 400, 64, 32), Rect::new(0, 0, 700, 400));
+        assert_eq!(viewport(Scaling::Stretch, 700, 400, 128, 64), Rect::new(0, 0, 700, 400));
+    }
+
+    #[test]
+    fn scaling_names() {
+        for scaling in [Scaling::Integer, Scaling::Fit, Scaling::Stretch] {
+            assert_eq!(scaling.to_string().parse::<Scaling>(), Ok(scaling));
+        }
+        assert_eq!(Scaling::Stretch.next(), Scaling::Integer);
+        assert!("zoom".parse::<Scaling>().is_err());
+    }
+}
@@ -0,0 +1,63 @@
+use std::fmt;
+
+// How an instruction touched memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    // Fetched as (part of) an instruction
+    Execute,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Access::Read => write!(f, "Read of"),
+            Access::Write => write!(f, "Write to"),
+            Access::Execute => write!(f, "Execution of"),
+        }
+    }
+}
+
+// An address range, both ends included, and which kinds of access to it get recorded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Watchpoint {
+    pub fn matches(&self, access: Access, address: usize) -> bool {
+        let wanted = match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+            Access::Execute => self.execute,
+        };
+        wanted && (self.start as usize..=self.end as usize).contains(&address)
+    }
+}
+
+// One access to a watched address: which instruction did it, and the byte
+// before and after. Reads and executes leave the byte as it was
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    pub access: Access,
+    pub address: u16,
+    pub pc: u16,
+    pub opcode: u16,
+    pub old: u8,
+    pub new: u8,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} 0x{:03X} by {:04X} at 0x{:03X}", self.access, self.address, self.opcode, self.pc)?;
+        match self.access {
+            Access::Write => write!(f, ": 0x{:02X} -> 0x{:02X}", self.old, self.new),
+            _ => write!(f, ": 0x{:02X}", self.old),
+        }
+    }
+}
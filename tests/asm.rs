@@ -0,0 +1,217 @@
+// Assembling source into ROMs
+mod common;
+
+use chip8::asm::{assemble, Symbols};
+use chip8::{Disassembly, Quirks, Syntax};
+use common::{run_rom, screen};
+
+fn error(source: &str) -> (usize, usize, String) {
+    let error = assemble(source).unwrap_err();
+    (error.line, error.column, error.message)
+}
+
+#[test]
+fn every_instruction() {
+    let source = "
+        CLS
+        RET
+        SCD 3
+        SCU 2
+        SCR
+        SCL
+        EXIT
+        LOW
+        HIGH
+        JP 0x234
+        JP V0, 0x234
+        CALL 0x456
+        SE V1, 0x12
+        SE V1, V2
+        SNE V1, 0x12
+        SNE V1, V2
+        SAVE V1 - V3
+        LOAD V3 - V1
+        LD V1, 0x12
+        LD V1, V2
+        LD I, 0x345
+        LD I, LONG 0xBEEF
+        LD V1, DT
+        LD V1, K
+        LD DT, V1
+        LD ST, V1
+        LD F, V1
+        LD HF, V1
+        LD B, V1
+        LD [I], V1
+        LD V1, [I]
+        LD R, V1
+        LD V1, R
+        ADD V1, 0x12
+        ADD V1, V2
+        ADD I, V1
+        OR V1, V2
+        AND V1, V2
+        XOR V1, V2
+        SUB V1, V2
+        SHR V1
+        SUBN V1, V2
+        SHL V1, V2
+        RND V1, 0x0F
+        DRW V1, V2, 5
+        SKP V1
+        SKNP V1
+        PLANE 3
+        AUDIO
+        PITCH V1
+    ";
+
+    let expected: Vec<u8> = [
+        0x00E0u16, 0x00EE, 0x00C3, 0x00D2, 0x00FB, 0x00FC, 0x00FD, 0x00FE, 0x00FF, 0x1234, 0xB234, 0x2456,
+        0x3112, 0x5120, 0x4112, 0x9120, 0x5132, 0x5313, 0x6112, 0x8120, 0xA345, 0xF000, 0xBEEF, 0xF107,
+        0xF10A, 0xF115, 0xF118, 0xF129, 0xF130, 0xF133, 0xF155, 0xF165, 0xF175, 0xF185, 0x7112, 0x8124,
+        0xF11E, 0x8121, 0x8122, 0x8123, 0x8125, 0x8116, 0x8127, 0x812E, 0xC10F, 0xD125, 0xE19E, 0xE1A1,
+        0xF301, 0xF002, 0xF13A,
+    ]
+    .iter()
+    .flat_map(|word| word.to_be_bytes())
+    .collect();
+
+    assert_eq!(assemble(source).unwrap().rom, expected);
+}
+
+#[test]
+fn labels_constants_and_expressions() {
+    let source = "
+        WIDTH = 64
+        MIDDLE = WIDTH / 2 - (glyph_end - glyph) * 2  ; constants can use later labels
+    main:
+        LD V0, MIDDLE
+        LD V1, -1 & 0x1F
+        LD I, glyph
+        DRW V0, V1, glyph_end - glyph
+    loop: JP loop
+    glyph:
+        db 0b11110000, 0x90, 'A' - 'A', 1 << 4 | 0x80
+    glyph_end:
+        dw 0x1234, main
+        db \"Hi\"
+    ";
+    let assembly = assemble(source).unwrap();
+
+    assert_eq!(
+        assembly.rom,
+        vec![
+            0x60, 0x18, 0x61, 0x1F, 0xA2, 0x0A, 0xD0, 0x14, 0x12, 0x08, 0xF0, 0x90, 0x00, 0x90, 0x12, 0x34,
+            0x02, 0x00, b'H', b'i',
+        ]
+    );
+    assert_eq!(assembly.symbols.address("loop"), Some(0x208));
+    assert_eq!(assembly.symbols.name(0x20A), Some("glyph"));
+    // Constants aren't addresses
+    assert_eq!(assembly.symbols.address("WIDTH"), None);
+}
+
+#[test]
+fn macros() {
+    let source = "
+    macro at x, y
+        LD V0, x
+        LD V1, y
+    endm
+
+    macro glyph digit, x, y
+        at x, y
+        LD V2, digit
+        LD F, V2
+        DRW V0, V1, 5
+    endm
+
+    start: glyph 1, 3, 2
+    ";
+    let assembly = assemble(source).unwrap();
+
+    assert_eq!(assembly.symbols.address("start"), Some(0x200));
+    let chip8 = run_rom(Quirks::modern(), &assembly.rom, 5);
+    assert_eq!(
+        screen(&chip8, 3, 2, 8, 5),
+        vec!["..#.....", ".##.....", "..#.....", "..#.....", ".###...."]
+    );
+}
+
+#[test]
+fn assembles_what_the_disassembler_understands() {
+    let source = "
+    main:
+        CALL draw
+        LD I, LONG 0x300
+    loop: SE V0, 0
+        JP loop
+    draw:
+        DRW V0, V1, 5
+        RET
+    ";
+    let rom = assemble(source).unwrap().rom;
+    let instructions: Vec<String> = Disassembly::new(&rom)
+        .instructions()
+        .iter()
+        .map(|(_, instruction)| instruction.to_text(Syntax::Cowgod, |_| None))
+        .collect();
+
+    assert_eq!(
+        instructions,
+        vec!["CALL 0x20A", "LD I, LONG 0x300", "SE V0, 0x00", "JP 0x206", "DRW V0, V1, 5", "RET"]
+    );
+}
+
+#[test]
+fn errors_have_positions() {
+    assert_eq!(error("  CLS\n  FOO V1"), (2, 3, "unknown instruction 'FOO'".to_string()));
+    assert_eq!(error("LD V1, nowhere"), (1, 8, "unknown symbol 'nowhere'".to_string()));
+    assert_eq!(error("LD V1, 256"), (1, 8, "256 doesn't fit in 8 bits".to_string()));
+    assert_eq!(error("JP 0x1000"), (1, 4, "4096 doesn't fit in 12 bits".to_string()));
+    assert_eq!(error("DRW V0, V1"), (1, 1, "bad operands for DRW".to_string()));
+    assert_eq!(error("LD V1, (2 + 3"), (1, 8, "unclosed parenthesis".to_string()));
+    assert_eq!(error("LD V1, 2 / 0"), (1, 10, "division by zero".to_string()));
+    assert_eq!(error("a:\na:"), (2, 1, "'a' is already defined".to_string()));
+    assert_eq!(error("V3: CLS"), (1, 1, "'V3' is a register name".to_string()));
+    assert_eq!(error("A = B1\nB1 = A"), (2, 6, "'A' is defined in terms of itself".to_string()));
+    assert_eq!(error("db 1,, 2"), (1, 5, "missing operand".to_string()));
+    assert_eq!(error("db \"open"), (1, 4, "unterminated string".to_string()));
+    assert_eq!(error("LD V1, 12ab"), (1, 8, "bad number '12ab'".to_string()));
+    assert_eq!(error("macro m\nCLS"), (1, 1, "macro m has no endm".to_string()));
+    assert_eq!(error("macro m a\nendm\nm 1, 2"), (3, 1, "expected 1 macro arguments, got 2".to_string()));
+
+    // Errors in macros point at the macro body
+    assert_eq!(error("macro m a\n  LD V0, a\nendm\nm nowhere"), (2, 10, "unknown symbol 'nowhere'".to_string()));
+}
+
+#[test]
+fn includes_are_relative_to_the_including_file() {
+    let directory = std::env::temp_dir().join(format!("chip8-asm-{}", std::process::id()));
+    std::fs::create_dir_all(directory.join("lib")).unwrap();
+    std::fs::write(directory.join("main.asm"), "include \"lib/sprites.asm\"\nLD I, sprite\n").unwrap();
+    std::fs::write(directory.join("lib/sprites.asm"), "JP over\nsprite: db 0xFF\nover:\n").unwrap();
+
+    let path = directory.join("main.asm");
+    let assembly = chip8::asm::assemble_file(path.to_str().unwrap()).unwrap();
+    assert_eq!(assembly.rom, vec![0x12, 0x03, 0xFF, 0xA2, 0x02]);
+
+    std::fs::write(directory.join("main.asm"), "CLS\ninclude \"missing.asm\"\n").unwrap();
+    let error = chip8::asm::assemble_file(path.to_str().unwrap()).unwrap_err();
+    assert_eq!((error.line, error.column), (2, 1));
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn symbol_files() {
+    let mut symbols = Symbols::new();
+    symbols.insert("main", 0x200);
+    symbols.insert("draw", 0x2A4);
+
+    let text = symbols.to_text();
+    assert_eq!(text, "0200 main\n02A4 draw\n");
+    assert_eq!(Symbols::from_text(&text), Ok(symbols));
+    assert!(Symbols::from_text("0200main").is_err());
+    assert!(Symbols::from_text("zz main").is_err());
+}
@@ -0,0 +1,198 @@
+// Recording: GIF, Y4M and PPM sequence output, frame timing and file naming
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use chip8::capture::{self, Capture, CaptureFormat};
+use chip8::image::DEFAULT_COLORS;
+use chip8::Display;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("chip8-capture-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// A 4x2 screen with the given pixel lit
+fn frame(lit: usize) -> [u8; 8] {
+    let mut pixels = [0; 8];
+    pixels[lit] = 1;
+    pixels
+}
+
+fn record(path: &Path, format: CaptureFormat, scale: usize, frames: &[[u8; 8]]) -> u64 {
+    let mut recording = Capture::create(path, format, &DEFAULT_COLORS, scale).unwrap();
+    for pixels in frames {
+        recording.add_frame(&Display { width: 4, height: 2, pixels }).unwrap();
+    }
+    recording.finish().unwrap()
+}
+
+// The delay and pixels of every frame in a GIF written by Capture
+fn decode_gif(gif: &[u8]) -> Vec<(u16, Vec<u8>)> {
+    assert_eq!(&gif[..6], b"GIF89a");
+    // Header, 4 color table and NETSCAPE2.0 extension
+    let mut at = 13 + 12 + 19;
+    let mut frames = Vec::new();
+    while gif[at] == 0x21 {
+        let delay = u16::from_le_bytes([gif[at + 4], gif[at + 5]]);
+        at += 8;
+        assert_eq!(gif[at], 0x2C);
+        at += 10;
+        let min_code_size = gif[at];
+        at += 1;
+        let mut data = Vec::new();
+        while gif[at] != 0 {
+            let length = gif[at] as usize;
+            data.extend_from_slice(&gif[at + 1..at + 1 + length]);
+            at += 1 + length;
+        }
+        at += 1;
+        frames.push((delay, lzw_decode(&data, min_code_size)));
+    }
+    assert_eq!(&gif[at..], &[0x3B]);
+    frames
+}
+
+fn lzw_decode(data: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear = 1usize << min_code_size;
+    let mut table: Vec<Vec<u8>> = Vec::new();
+    let mut width = min_code_size as usize + 1;
+    let mut previous: Option<Vec<u8>> = None;
+    let mut out = Vec::new();
+    let mut bit = 0;
+
+    while bit + width <= data.len() * 8 {
+        let code = (0..width).fold(0, |code, i| code | (((data[(bit + i) / 8] >> ((bit + i) % 8)) & 1) as usize) << i);
+        bit += width;
+        if code == clear {
+            table = (0..clear).map(|value| vec![value as u8]).collect();
+            table.extend([vec![], vec![]]);
+            width = min_code_size as usize + 1;
+            previous = None;
+            continue;
+        }
+        if code == clear + 1 {
+            break;
+        }
+
+        let entry = match (table.get(code), &previous) {
+            (Some(entry), _) => entry.clone(),
+            (None, Some(previous)) => [previous.clone(), vec![previous[0]]].concat(),
+            (None, None) => panic!("code {} before anything was decoded", code),
+        };
+        if let Some(previous) = previous {
+            table.push([previous, vec![entry[0]]].concat());
+            if table.len() == 1 << width && width < 12 {
+                width += 1;
+            }
+        }
+        out.extend_from_slice(&entry);
+        previous = Some(entry);
+    }
+    out
+}
+
+#[test]
+fn gif_frames_follow_the_60_hz_timer() {
+    let dir = temp_dir("gif");
+    let path = dir.join("run.gif");
+    // Three frames of one picture, one of a second, two of a third
+    let frames = [frame(0), frame(0), frame(0), frame(1), frame(2), frame(2)];
+    assert_eq!(record(&path, CaptureFormat::Gif, 1, &frames), 6);
+
+    let gif = fs::read(&path).unwrap();
+    // 4x2 logical screen
+    assert_eq!(&gif[6..10], &[4, 0, 2, 0]);
+    // A single frame lasts 1.67 hundredths, too short for a GIF, so it's dropped
+    // and the next picture takes over its time
+    assert_eq!(decode_gif(&gif), vec![(5, frame(0).to_vec()), (5, frame(2).to_vec())]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn scaled_gif_frames() {
+    let dir = temp_dir("scaled");
+    let path = dir.join("run.gif");
+    record(&path, CaptureFormat::Gif, 2, &[frame(1); 4]);
+
+    let gif = fs::read(&path).unwrap();
+    assert_eq!(&gif[6..10], &[8, 0, 4, 0]);
+    let frames = decode_gif(&gif);
+    assert_eq!(frames.len(), 1);
+    let mut expected = vec![0; 32];
+    for index in [2, 3, 10, 11] {
+        expected[index] = 1;
+    }
+    assert_eq!(frames[0], (6, expected));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn long_gif_frames_survive_the_table_filling_up() {
+    let dir = temp_dir("long");
+    let path = dir.join("noise.gif");
+    // Enough varied pixels to fill the 4096 entry LZW table several times over
+    let pixels: Vec<u8> = (0..128 * 64u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 29) as u8 & 0b11).collect();
+    let mut recording = Capture::create(&path, CaptureFormat::Gif, &DEFAULT_COLORS, 1).unwrap();
+    for _ in 0..3 {
+        recording.add_frame(&Display { width: 128, height: 64, pixels: &pixels }).unwrap();
+    }
+    recording.finish().unwrap();
+
+    assert_eq!(decode_gif(&fs::read(&path).unwrap()), vec![(5, pixels)]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn y4m_has_a_frame_per_tick() {
+    let dir = temp_dir("y4m");
+    let path = dir.join("run.y4m");
+    record(&path, CaptureFormat::Y4m, 1, &[frame(0), frame(0), frame(1)]);
+
+    let video = fs::read(&path).unwrap();
+    let header = b"YUV4MPEG2 W4 H2 F60:1 Ip A1:1 C444\n";
+    assert_eq!(&video[..header.len()], header);
+    let frame_size = 6 + 3 * 8;
+    assert_eq!(video.len(), header.len() + 3 * frame_size);
+
+    let last = &video[header.len() + 2 * frame_size..];
+    assert_eq!(&last[..6], b"FRAME\n");
+    // Luma for black and white, in the limited video range
+    assert_eq!(&last[6..14], &[16, 235, 16, 16, 16, 16, 16, 16]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn ppm_sequences_number_their_frames() {
+    let dir = temp_dir("ppm");
+    let frames = dir.join("frames");
+    record(&frames, CaptureFormat::PpmSequence, 1, &[frame(0), frame(3)]);
+
+    let mut names: Vec<String> = fs::read_dir(&frames).unwrap().map(|entry| entry.unwrap().file_name().into_string().unwrap()).collect();
+    names.sort();
+    assert_eq!(names, ["frame-000000.ppm", "frame-000001.ppm"]);
+    assert!(fs::read(frames.join("frame-000001.ppm")).unwrap().starts_with(b"P6\n4 2\n255\n"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn capture_formats_and_paths() {
+    assert_eq!("GIF".parse::<CaptureFormat>(), Ok(CaptureFormat::Gif));
+    assert_eq!("ppm".parse::<CaptureFormat>(), Ok(CaptureFormat::PpmSequence));
+    assert!("mp4".parse::<CaptureFormat>().is_err());
+
+    assert_eq!(CaptureFormat::from_path(Path::new("out/run.Y4M")), CaptureFormat::Y4m);
+    assert_eq!(CaptureFormat::from_path(Path::new("out/frames")), CaptureFormat::PpmSequence);
+
+    let time = UNIX_EPOCH + Duration::from_secs(951_827_696);
+    let rom = Path::new("roms/pong.ch8");
+    assert_eq!(capture::capture_path(rom, CaptureFormat::Gif, time), Path::new("roms/pong-20000229-123456.gif"));
+    assert_eq!(capture::capture_path(rom, CaptureFormat::PpmSequence, time), Path::new("roms/pong-20000229-123456"));
+}
@@ -0,0 +1,54 @@
+// Helpers shared by the conformance tests.
+// Test ROMs are written inline as opcode words with the `rom!` macro, so there
+// is nothing to download and every test shows exactly what it runs.
+#![allow(dead_code)]
+
+use chip8::{Chip8, Quirks};
+
+// rom![0x600A, 0xF029] gives the big-endian bytes of those opcodes
+#[macro_export]
+macro_rules! rom {
+    ($($word:expr),* $(,)?) => {{
+        let words: &[u16] = &[$($word),*];
+        words.iter().flat_map(|word: &u16| word.to_be_bytes()).collect::<Vec<u8>>()
+    }};
+}
+
+pub fn machine(quirks: Quirks, rom: &[u8]) -> Chip8 {
+    let mut chip8 = Chip8::initialize(quirks);
+    chip8.load_fontset();
+    chip8.load_rom(rom).unwrap();
+    chip8
+}
+
+// Runs `cycles` instructions, panicking on any interpreter error
+pub fn run(chip8: &mut Chip8, cycles: usize) {
+    for _ in 0..cycles {
+        chip8.cycle().unwrap();
+    }
+}
+
+pub fn run_rom(quirks: Quirks, rom: &[u8], cycles: usize) -> Chip8 {
+    let mut chip8 = machine(quirks, rom);
+    run(&mut chip8, cycles);
+    chip8
+}
+
+// A rectangle of the screen as ASCII rows, '#' for lit pixels in plane 1,
+// 'o' for plane 2 and '@' for both
+pub fn screen(chip8: &Chip8, x: usize, y: usize, width: usize, height: usize) -> Vec<String> {
+    let display = chip8.get_display();
+    let chars = ['.', '#', 'o', '@'];
+
+    (y..y + height)
+        .map(|row| {
+            (x..x + width)
+                .map(|column| chars[display.pixels[row * display.width + column] as usize])
+                .collect()
+        })
+        .collect()
+}
+
+pub fn lit_pixels(chip8: &Chip8) -> usize {
+    chip8.get_display().pixels.iter().filter(|pixel| **pixel != 0).count()
+}
@@ -0,0 +1,214 @@
+// Breakpoints, stepping and inspection
+mod common;
+
+use chip8::asm::Symbols;
+use chip8::debugger::{Breakpoint, Comparison, Condition, Register};
+use chip8::{Access, Chip8, Debugger, Quirks, WatchHit, Watchpoint};
+use common::machine;
+
+// V0 counts up forever
+fn counter() -> Chip8 {
+    machine(Quirks::modern(), &rom![0x6000, 0x7001, 0x1202])
+}
+
+#[test]
+fn starts_paused_and_runs_nothing() {
+    let mut chip8 = counter();
+    let mut debugger = Debugger::new();
+
+    assert!(debugger.is_paused());
+    debugger.run_frame(&mut chip8, 10).unwrap();
+    assert_eq!(chip8.pc(), 0x200);
+}
+
+#[test]
+fn stops_at_breakpoints() {
+    let mut chip8 = counter();
+    let mut debugger = Debugger::new();
+    debugger.execute("break 0x204", &chip8);
+    debugger.execute("continue", &chip8);
+
+    debugger.run_frame(&mut chip8, 10).unwrap();
+    assert!(debugger.is_paused());
+    assert_eq!(chip8.pc(), 0x204);
+    assert!(debugger.take_report().unwrap().starts_with("Breakpoint 1 hit"));
+
+    // Continuing doesn't stop on the same breakpoint straight away
+    debugger.execute("continue", &chip8);
+    debugger.run_frame(&mut chip8, 10).unwrap();
+    assert_eq!(chip8.pc(), 0x204);
+    assert_eq!(chip8.registers()[0], 2);
+}
+
+#[test]
+fn conditional_breakpoints() {
+    let mut chip8 = counter();
+    let mut debugger = Debugger::new();
+    debugger.add_breakpoint(Breakpoint {
+        address: Some(0x202),
+        condition: Some(Condition { register: Register::V(0), comparison: Comparison::Equal, value: 5 }),
+    });
+    debugger.resume();
+
+    debugger.run_frame(&mut chip8, 100).unwrap();
+    assert_eq!(chip8.pc(), 0x202);
+    assert_eq!(chip8.registers()[0], 5);
+
+    // Anywhere, once the condition holds
+    let mut chip8 = counter();
+    let mut debugger = Debugger::new();
+    assert_eq!(debugger.execute("break if v0 >= 0x10", &chip8), "Breakpoint 1 set");
+    debugger.resume();
+    debugger.run_frame(&mut chip8, 100).unwrap();
+    assert_eq!(chip8.registers()[0], 0x10);
+}
+
+#[test]
+fn stepping() {
+    let mut chip8 = counter();
+    let mut debugger = Debugger::new();
+
+    debugger.execute("step", &chip8);
+    debugger.run_frame(&mut chip8, 10).unwrap();
+    assert_eq!(chip8.pc(), 0x202);
+    assert!(debugger.is_paused());
+
+    debugger.execute("step 3", &chip8);
+    debugger.run_frame(&mut chip8, 10).unwrap();
+    assert_eq!(chip8.pc(), 0x204);
+    assert_eq!(chip8.registers()[0], 2);
+}
+
+#[test]
+fn frames_resume_where_they_stopped() {
+    let mut chip8 = machine(Quirks::modern(), &rom![0x6005, 0xF015, 0x1204]);
+    let mut debugger = Debugger::new();
+
+    // Two instructions in, then finish the frame: 8 more instructions and a timer tick
+    debugger.execute("step 2", &chip8);
+    debugger.run_frame(&mut chip8, 10).unwrap();
+    assert_eq!(chip8.delay_timer(), 5);
+
+    debugger.execute("frame", &chip8);
+    debugger.run_frame(&mut chip8, 10).unwrap();
+    assert!(debugger.is_paused());
+    assert_eq!(chip8.delay_timer(), 4);
+    assert_eq!(debugger.take_report().map(|report| report.starts_with("End of frame")), Some(true));
+}
+
+#[test]
+fn breakpoint_management() {
+    let chip8 = counter();
+    let mut debugger = Debugger::new();
+    let mut symbols = Symbols::new();
+    symbols.insert("loop", 0x202);
+    debugger.set_symbols(symbols);
+
+    assert_eq!(debugger.execute("break loop", &chip8), "Breakpoint 1 at 0x202 (loop)");
+    assert_eq!(debugger.execute("b 0x200 if v3 != 1", &chip8), "Breakpoint 2 at 0x200");
+    assert_eq!(debugger.execute("breakpoints", &chip8), "1: 0x202 (loop)\n2: 0x200 if v3 != 1");
+    assert_eq!(debugger.execute("delete 1", &chip8), "Breakpoint 1 deleted");
+    assert_eq!(debugger.execute("delete 1", &chip8), "No breakpoint 1");
+    assert_eq!(debugger.execute("delete all", &chip8), "All breakpoints deleted");
+    assert_eq!(debugger.execute("break nowhere", &chip8), "Unknown address 'nowhere'");
+    assert!(debugger.execute("break if v0 ~ 1", &chip8).starts_with("Bad condition"));
+    assert!(debugger.execute("frobnicate", &chip8).starts_with("Unknown command"));
+}
+
+#[test]
+fn inspection() {
+    let mut chip8 = machine(Quirks::modern(), &rom![0x6A42, 0xA202, 0x2208, 0x0000, 0x00EE]);
+    let mut debugger = Debugger::new();
+    debugger.execute("step 3", &chip8);
+    debugger.run_frame(&mut chip8, 10).unwrap();
+
+    let registers = debugger.execute("regs", &chip8);
+    assert!(registers.starts_with("PC 0x208  I 0x202  SP 1  DT 0  ST 0\n"));
+    assert!(registers.contains("VA 42"));
+    assert!(registers.contains("Stack: 0x206\n"));
+    assert!(registers.ends_with("> 208: 00EE      RET"));
+
+    assert_eq!(debugger.execute("mem i 16", &chip8), "0200: 6A 42 A2 02 22 08 00 00 00 EE 00 00 00 00 00 00\n0210: 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00");
+    assert_eq!(
+        debugger.execute("dis 0x200 3", &chip8),
+        "  200: 6A42      LD VA, 0x42\n  202: A202      LD I, 0x202\n  204: 2208      CALL 0x208"
+    );
+}
+
+fn watch(start: u16, end: u16, mode: &str) -> Watchpoint {
+    Watchpoint {
+        start,
+        end,
+        read: mode.contains('r'),
+        write: mode.contains('w'),
+        execute: mode.contains('x'),
+    }
+}
+
+#[test]
+fn watchpoints_record_reads_writes_and_fetches() {
+    // BCD of 254 into 0x300..0x302, then draw one row from 0x301
+    let mut chip8 = machine(Quirks::modern(), &rom![0x60FE, 0xA300, 0xF033, 0xA301, 0xD011]);
+    chip8.set_watchpoints(&[watch(0x301, 0x302, "w"), watch(0x301, 0x301, "r"), watch(0x206, 0x206, "x")]);
+    common::run(&mut chip8, 5);
+
+    let hits = chip8.take_watch_hits();
+    assert_eq!(
+        hits,
+        vec![
+            WatchHit { access: Access::Write, address: 0x301, pc: 0x204, opcode: 0xF033, old: 0, new: 5 },
+            WatchHit { access: Access::Write, address: 0x302, pc: 0x204, opcode: 0xF033, old: 0, new: 4 },
+            WatchHit { access: Access::Execute, address: 0x206, pc: 0x206, opcode: 0xA301, old: 0xA3, new: 0xA3 },
+            WatchHit { access: Access::Read, address: 0x301, pc: 0x208, opcode: 0xD011, old: 5, new: 5 },
+        ]
+    );
+    assert!(chip8.take_watch_hits().is_empty());
+    assert_eq!(hits[0].to_string(), "Write to 0x301 by F033 at 0x204: 0x00 -> 0x05");
+}
+
+#[test]
+fn watchpoints_catch_self_modifying_code() {
+    // Overwrites the instruction at 0x208 with 0x6142 (V1 := 0x42), then runs it
+    let rom = rom![0x6061, 0xA208, 0xF055, 0x1208, 0x0000];
+    let mut chip8 = machine(Quirks::modern(), &rom);
+    chip8.set_watchpoints(&[watch(0x208, 0x209, "wx")]);
+    common::run(&mut chip8, 5);
+
+    let hits: Vec<(Access, u16)> = chip8.take_watch_hits().iter().map(|hit| (hit.access, hit.address)).collect();
+    assert_eq!(hits, vec![(Access::Write, 0x208), (Access::Execute, 0x208), (Access::Execute, 0x209)]);
+}
+
+#[test]
+fn debugger_stops_on_watchpoints() {
+    let mut chip8 = machine(Quirks::modern(), &rom![0x6007, 0xA300, 0xF055, 0x1206]);
+    let mut debugger = Debugger::new();
+    assert_eq!(debugger.execute("watch w 0x300 0x30F", &chip8), "Watchpoint 1 on 0x300 to 0x30F (w)");
+    assert_eq!(debugger.execute("breakpoints", &chip8), "1: watch 0x300 to 0x30F (w)");
+    debugger.execute("continue", &chip8);
+
+    debugger.run_frame(&mut chip8, 10).unwrap();
+    assert!(debugger.is_paused());
+    assert_eq!(chip8.pc(), 0x206);
+    let report = debugger.take_report().unwrap();
+    assert!(report.starts_with("Write to 0x300 by F055 at 0x204: 0x00 -> 0x07\n"), "{}", report);
+
+    assert!(debugger.execute("watch q 0x300", &chip8).starts_with("Bad watch mode"));
+    assert_eq!(debugger.execute("delete 1", &chip8), "Breakpoint 1 deleted");
+}
+
+#[test]
+fn key_waits_end_the_frame() {
+    let mut chip8 = machine(Quirks::modern(), &rom![0x6005, 0xF015, 0xF30A]);
+    let mut debugger = Debugger::new();
+    debugger.execute("break 0x204", &chip8);
+    debugger.execute("continue", &chip8);
+    debugger.run_frame(&mut chip8, 10).unwrap();
+    assert!(debugger.take_report().unwrap().starts_with("Breakpoint 1 hit"));
+
+    // Fx0A runs once and the frame is over, rather than stopping on it again
+    debugger.execute("continue", &chip8);
+    debugger.run_frame(&mut chip8, 10).unwrap();
+    assert!(!debugger.is_paused());
+    assert!(chip8.is_waiting_for_key());
+    assert_eq!(chip8.delay_timer(), 4);
+}
@@ -0,0 +1,103 @@
+// Decoding and disassembling ROMs
+mod common;
+
+use chip8::disasm::decode;
+use chip8::{Disassembly, Instruction, Syntax};
+
+#[test]
+fn decodes_every_instruction_class() {
+    let cases: &[(u16, Instruction)] = &[
+        (0x00C4, Instruction::ScrollDown(4)),
+        (0x00E0, Instruction::Clear),
+        (0x00EE, Instruction::Return),
+        (0x1ABC, Instruction::Jump(0xABC)),
+        (0x2ABC, Instruction::Call(0xABC)),
+        (0x3A12, Instruction::SkipEqualByte { x: 0xA, byte: 0x12 }),
+        (0x5AB0, Instruction::SkipEqual { x: 0xA, y: 0xB }),
+        (0x5AB2, Instruction::SaveRange { x: 0xA, y: 0xB }),
+        (0x8AB6, Instruction::ShiftRight { x: 0xA, y: 0xB }),
+        (0x9AB0, Instruction::SkipNotEqual { x: 0xA, y: 0xB }),
+        (0xB123, Instruction::JumpOffset(0x123)),
+        (0xDAB0, Instruction::Draw { x: 0xA, y: 0xB, n: 0 }),
+        (0xEAA1, Instruction::SkipNotKey(0xA)),
+        (0xF201, Instruction::Plane(2)),
+        (0xFA33, Instruction::Bcd(0xA)),
+        (0xFA85, Instruction::LoadFlags(0xA)),
+    ];
+
+    for (opcode, instruction) in cases {
+        assert_eq!(decode(&opcode.to_be_bytes()), Some(*instruction), "{:04X}", opcode);
+    }
+}
+
+#[test]
+fn rejects_invalid_opcodes() {
+    for opcode in [0x0123u16, 0x5AB1, 0x8AB8, 0x9AB1, 0xEA00, 0xFA00, 0xF102, 0xFAFF] {
+        assert_eq!(decode(&opcode.to_be_bytes()), None, "{:04X}", opcode);
+    }
+    // F000 needs the address after it
+    assert_eq!(decode(&[0xF0, 0x00, 0x12]), None);
+    assert_eq!(decode(&[0xF0, 0x00, 0x12, 0x34]), Some(Instruction::LoadLongIndex(0x1234)));
+}
+
+#[test]
+fn syntaxes() {
+    let instruction = Instruction::AddByte { x: 3, byte: 0x10 };
+    assert_eq!(instruction.to_text(Syntax::Cowgod, |_| None), "ADD V3, 0x10");
+    assert_eq!(instruction.to_text(Syntax::Octo, |_| None), "v3 += 0x10");
+
+    // Octo skips read as the condition for running the next line
+    let skip = Instruction::SkipEqualByte { x: 0, byte: 1 };
+    assert_eq!(skip.to_text(Syntax::Octo, |_| None), "if v0 != 0x01 then");
+
+    let call = Instruction::Call(0x300);
+    assert_eq!(call.to_text(Syntax::Octo, |_| Some("draw".to_string())), "draw");
+    assert_eq!(call.to_text(Syntax::Octo, |_| None), ":call 0x300");
+    assert_eq!(call.to_string(), "CALL 0x300");
+}
+
+#[test]
+fn traces_code_and_leaves_data_alone() {
+    let mut rom = rom![
+        0x2208, // 200: call 208
+        0xA20C, // 202: i := 20C
+        0x3000, // 204: skip if v0 == 0
+        0x1204, // 206: loop
+        0xD015, // 208: sprite
+        0x00EE, // 20A: return
+    ];
+    // 20C: sprite data, which happens to decode as instructions
+    rom.extend_from_slice(&[0x60, 0x90, 0x90, 0x60]);
+
+    let disassembly = Disassembly::new(&rom);
+    for address in [0x200, 0x202, 0x204, 0x206, 0x208, 0x20A] {
+        assert!(disassembly.is_code(address), "{:03X}", address);
+    }
+    assert!(!disassembly.is_code(0x20C));
+    assert!(!disassembly.is_code(0x20E));
+
+    assert_eq!(disassembly.label(0x200), Some("main"));
+    assert_eq!(disassembly.label(0x208), Some("sub_208"));
+    assert_eq!(disassembly.label(0x204), Some("label_204"));
+    assert_eq!(disassembly.label(0x20C), Some("data_20C"));
+
+    let octo = disassembly.to_text(Syntax::Octo);
+    assert_eq!(
+        octo,
+        ": main\n\tsub_208\n\ti := data_20C\n: label_204\n\tif v0 != 0x00 then\n\tjump label_204\n\
+         : sub_208\n\tsprite v0 v1 5\n\treturn\n: data_20C\n\t0x60 0x90 0x90 0x60\n"
+    );
+
+    let cowgod = disassembly.to_text(Syntax::Cowgod);
+    assert!(cowgod.starts_with("main:\n200: 2208       CALL sub_208\n"));
+    assert!(cowgod.contains("20C:            DB 0x60, 0x90, 0x90, 0x60\n"));
+}
+
+#[test]
+fn skips_over_long_index() {
+    let rom = rom![0x3000, 0xF000, 0x0300, 0x00FD];
+    let disassembly = Disassembly::new(&rom);
+    assert!(disassembly.is_code(0x202));
+    assert!(disassembly.is_code(0x206));
+    assert!(!disassembly.is_code(0x204));
+}
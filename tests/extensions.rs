@@ -0,0 +1,161 @@
+// Conformance tests for the SUPER-CHIP and XO-CHIP extensions
+mod common;
+
+use chip8::{Quirks, HIRES_HEIGHT, HIRES_WIDTH};
+use common::{lit_pixels, machine, run, run_rom, screen};
+
+fn superchip() -> Quirks {
+    Quirks::superchip()
+}
+
+fn xochip() -> Quirks {
+    Quirks::xochip()
+}
+
+#[test]
+fn hires_and_lores_00ff_00fe() {
+    let mut chip8 = machine(superchip(), &rom![0x00FF, 0x00FE]);
+
+    run(&mut chip8, 1);
+    assert!(chip8.is_hires());
+    let display = chip8.get_display();
+    assert_eq!((display.width, display.height), (HIRES_WIDTH, HIRES_HEIGHT));
+
+    run(&mut chip8, 1);
+    assert!(!chip8.is_hires());
+    assert_eq!(chip8.get_display().width, 64);
+}
+
+#[test]
+fn switching_resolution_clears_the_screen() {
+    let chip8 = run_rom(superchip(), &rom![0xA000, 0xD005, 0x00FF], 3);
+    assert_eq!(lit_pixels(&chip8), 0);
+}
+
+#[test]
+fn scroll_down_00cn() {
+    // A single pixel at (0, 0), the sprite is the 0x80 byte after the code
+    let chip8 = run_rom(superchip(), &rom![0x00FF, 0xA20A, 0xD011, 0x00C3, 0x1208, 0x8000], 4);
+    assert_eq!(screen(&chip8, 0, 0, 1, 4), vec![".", ".", ".", "#"]);
+}
+
+#[test]
+fn scroll_up_00dn() {
+    // Drawn on row 3, moved up to row 1
+    let chip8 = run_rom(xochip(), &rom![0x6103, 0xA20A, 0xD011, 0x00D2, 0x1208, 0x8000], 4);
+    assert_eq!(screen(&chip8, 0, 0, 1, 4), vec![".", "#", ".", "."]);
+}
+
+#[test]
+fn scroll_right_and_left_00fb_00fc() {
+    let mut chip8 = machine(superchip(), &rom![0x00FF, 0xA20C, 0xD011, 0x00FB, 0x00FC, 0x00FC, 0x8000]);
+
+    run(&mut chip8, 4);
+    assert_eq!(screen(&chip8, 0, 0, 6, 1), vec!["....#."]);
+
+    run(&mut chip8, 1);
+    assert_eq!(screen(&chip8, 0, 0, 6, 1), vec!["#....."]);
+
+    // Whatever scrolls off the edge is gone
+    run(&mut chip8, 1);
+    assert_eq!(lit_pixels(&chip8), 0);
+}
+
+#[test]
+fn exit_00fd() {
+    let mut chip8 = machine(superchip(), &rom![0x00FD, 0x6001]);
+    assert!(chip8.cycle().unwrap().exited);
+    // Once exited, cycling does nothing but say so
+    let step = chip8.cycle().unwrap();
+    assert!(step.exited);
+    assert_eq!(step.pc, 0x202);
+    assert!(chip8.has_exited());
+    assert_eq!(chip8.registers()[0], 0);
+}
+
+#[test]
+fn big_sprite_dxy0() {
+    // 16x16 sprite of alternating full and empty rows, right after the code
+    let mut rom = rom![0x00FF, 0xA206, 0xD000];
+    for row in 0..16 {
+        let byte = if row % 2 == 0 { 0xFF } else { 0x00 };
+        rom.extend_from_slice(&[byte, byte]);
+    }
+    let chip8 = run_rom(superchip(), &rom, 3);
+
+    assert_eq!(lit_pixels(&chip8), 8 * 16);
+    assert_eq!(screen(&chip8, 0, 0, 17, 2), vec!["################.", "................."]);
+}
+
+#[test]
+fn big_font_fx30() {
+    // Draw the big "8": 10 rows, top and bottom are solid
+    let chip8 = run_rom(superchip(), &rom![0x6008, 0xF030, 0x6100, 0xD11A], 4);
+    assert_eq!(chip8.index_register(), 0x50 + 8 * 10);
+    assert_eq!(screen(&chip8, 0, 0, 8, 1), screen(&chip8, 0, 9, 8, 1));
+    assert!(lit_pixels(&chip8) > 0);
+}
+
+#[test]
+fn user_flags_fx75_fx85() {
+    let rom = rom![0x6011, 0x6122, 0x6233, 0xF275, 0x6000, 0x6100, 0x6200, 0xF185];
+    let chip8 = run_rom(superchip(), &rom, 8);
+    assert_eq!(&chip8.registers()[..3], &[0x11, 0x22, 0x00]);
+
+    // XO-CHIP has 16 of them
+    let chip8 = run_rom(xochip(), &rom![0x6F42, 0xFF75, 0x6F00, 0xFF85], 4);
+    assert_eq!(chip8.registers()[0xF], 0x42);
+}
+
+#[test]
+fn long_index_f000() {
+    let chip8 = run_rom(xochip(), &rom![0xF000, 0xBEEF, 0x6001], 2);
+    assert_eq!(chip8.index_register(), 0xBEEF);
+    assert_eq!(chip8.registers()[0], 1);
+    assert_eq!(chip8.pc(), 0x206);
+}
+
+#[test]
+fn skips_step_over_f000() {
+    // 3xkk skips the whole four byte instruction
+    let chip8 = run_rom(xochip(), &rom![0x3000, 0xF000, 0x1234, 0x6001], 2);
+    assert_eq!(chip8.index_register(), 0);
+    assert_eq!(chip8.registers()[0], 1);
+}
+
+#[test]
+fn register_ranges_5xy2_5xy3() {
+    let rom = rom![0x6111, 0x6222, 0x6333, 0xA300, 0x5132, 0x5313];
+    let chip8 = run_rom(xochip(), &rom, 6);
+
+    // Stored in order, read back in reverse, I untouched
+    assert_eq!(&chip8.memory()[0x300..0x303], &[0x11, 0x22, 0x33]);
+    assert_eq!(&chip8.registers()[1..4], &[0x33, 0x22, 0x11]);
+    assert_eq!(chip8.index_register(), 0x300);
+}
+
+#[test]
+fn drawing_planes_fn01() {
+    // Plane 2 only, then both planes further right, from the "0" glyph (F0 90 ...)
+    let chip8 = run_rom(xochip(), &rom![0xA000, 0xF201, 0xD001, 0x6008, 0xF301, 0xD011], 6);
+    assert_eq!(screen(&chip8, 0, 0, 4, 1), vec!["oooo"]);
+    // With both planes the second plane's sprite follows the first's
+    assert_eq!(screen(&chip8, 8, 0, 4, 1), vec!["@##@"]);
+}
+
+#[test]
+fn clear_only_selected_planes() {
+    let chip8 = run_rom(xochip(), &rom![0xA000, 0xF301, 0xD011, 0xF101, 0x00E0], 5);
+    assert_eq!(screen(&chip8, 0, 0, 4, 1), vec!["o..o"]);
+}
+
+#[test]
+fn audio_pattern_and_pitch_f002_fx3a() {
+    let expected: Vec<u8> = (0..16).collect();
+    let mut rom = rom![0xA208, 0xF002, 0x6080, 0xF03A];
+    rom.extend_from_slice(&expected);
+    let chip8 = run_rom(xochip(), &rom, 4);
+
+    assert_eq!(chip8.audio_pattern(), &expected[..]);
+    assert_eq!(chip8.audio_pitch(), 0x80);
+}
@@ -0,0 +1,75 @@
+// Flicker filters: phosphor fade and blending the last two frames
+use chip8::{Display, DisplayFilter, FilterMode};
+
+const COLORS: [[u8; 3]; 4] = [[0, 0, 0], [200, 100, 0], [0, 0, 200], [50, 50, 50]];
+
+fn frame(pixels: &[u8]) -> Display<'_> {
+    Display { width: pixels.len(), height: 1, pixels }
+}
+
+#[test]
+fn off_shows_the_frame_as_is() {
+    let mut filter = DisplayFilter::new(FilterMode::Off, 0.5);
+    assert!(filter.render(&COLORS).is_empty());
+
+    filter.push(&frame(&[1, 0]));
+    filter.push(&frame(&[0, 2]));
+    assert_eq!(filter.render(&COLORS), [0, 0, 0, 0, 0, 200]);
+    assert_eq!((filter.width(), filter.height()), (2, 1));
+}
+
+#[test]
+fn blend_ors_the_last_two_frames() {
+    let mut filter = DisplayFilter::new(FilterMode::Blend, 0.5);
+    filter.push(&frame(&[1, 0, 0]));
+    filter.push(&frame(&[0, 2, 0]));
+    assert_eq!(filter.render(&COLORS), [200, 100, 0, 0, 0, 200, 0, 0, 0]);
+
+    // Gone after two frames
+    filter.push(&frame(&[0, 2, 0]));
+    assert_eq!(filter.render(&COLORS), [0, 0, 0, 0, 0, 200, 0, 0, 0]);
+}
+
+#[test]
+fn phosphor_fades_out() {
+    let mut filter = DisplayFilter::new(FilterMode::Phosphor, 0.5);
+    filter.push(&frame(&[1]));
+    assert_eq!(filter.render(&COLORS), [200, 100, 0]);
+    filter.push(&frame(&[0]));
+    assert_eq!(filter.render(&COLORS), [100, 50, 0]);
+    filter.push(&frame(&[0]));
+    assert_eq!(filter.render(&COLORS), [50, 25, 0]);
+
+    // Relit pixels come back at full brightness
+    filter.push(&frame(&[1]));
+    assert_eq!(filter.render(&COLORS), [200, 100, 0]);
+
+    // No decay at all turns them straight off
+    filter.set_decay(0.0);
+    filter.push(&frame(&[0]));
+    assert_eq!(filter.render(&COLORS), [0, 0, 0]);
+}
+
+#[test]
+fn switching_modes_keeps_the_history() {
+    let mut filter = DisplayFilter::new(FilterMode::Off, 0.5);
+    filter.push(&frame(&[1, 0]));
+    filter.push(&frame(&[0, 1]));
+
+    filter.set_mode(filter.mode().next());
+    assert_eq!(filter.mode(), FilterMode::Phosphor);
+    assert_eq!(filter.render(&COLORS), [100, 50, 0, 200, 100, 0]);
+
+    filter.set_mode(filter.mode().next());
+    assert_eq!(filter.render(&COLORS), [200, 100, 0, 200, 100, 0]);
+    assert_eq!(filter.mode().next(), FilterMode::Off);
+}
+
+#[test]
+fn a_new_resolution_starts_over() {
+    let mut filter = DisplayFilter::new(FilterMode::Blend, 0.5);
+    filter.push(&frame(&[1, 1]));
+    filter.push(&frame(&[0, 0, 0, 0]));
+    assert_eq!(filter.render(&COLORS), [0; 12]);
+    assert_eq!("Phosphor".parse::<FilterMode>(), Ok(FilterMode::Phosphor));
+}
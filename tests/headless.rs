@@ -0,0 +1,98 @@
+// The headless runner, driven the way a CI job would run it
+mod common;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use chip8::image;
+use chip8::Quirks;
+use common::run_rom;
+
+// Draws the digit in V0 at the top left and stops there
+fn digit_rom(digit: u16) -> Vec<u8> {
+    rom![0x6000 | digit, 0xF029, 0xD005, 0x1206]
+}
+
+// Runs chip8-headless on `rom` with `args`, plus any extra files the test needs.
+// Returns how it exited and what it wrote to its output file
+fn headless(name: &str, rom: &[u8], args: &[&str], files: &[(&str, &str)]) -> (Output, String) {
+    let dir = env::temp_dir().join(format!("chip8-headless-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let rom_path: PathBuf = dir.join("test.ch8");
+    fs::write(&rom_path, rom).unwrap();
+    for (file, text) in files {
+        fs::write(dir.join(file), text).unwrap();
+    }
+
+    let output = Command::new(env!("CARGO_BIN_EXE_chip8-headless"))
+        .current_dir(&dir)
+        .args(args)
+        .args(["--output", "screen.txt"])
+        .arg(&rom_path)
+        .output()
+        .unwrap();
+    let screen = fs::read_to_string(dir.join("screen.txt")).unwrap_or_default();
+
+    fs::remove_dir_all(&dir).unwrap();
+    (output, screen)
+}
+
+#[test]
+fn ascii_screens() {
+    let (output, text) = headless("ascii", &digit_rom(0), &["--frames", "1"], &[]);
+    assert!(output.status.success());
+
+    let rows: Vec<&str> = text.lines().collect();
+    assert_eq!(rows.len(), 32);
+    assert!(rows.iter().all(|row| row.len() == 64));
+    assert_eq!(rows[..6].iter().map(|row| &row[..5]).collect::<Vec<_>>(), ["####.", "#..#.", "#..#.", "#..#.", "####.", "....."]);
+}
+
+#[test]
+fn screen_hashes() {
+    let (output, hash) = headless("hash", &digit_rom(7), &["--frames", "2", "--format", "hash"], &[]);
+    assert!(output.status.success());
+
+    let chip8 = run_rom(Quirks::modern(), &digit_rom(7), 3);
+    let expected = format!("{:016x}\n", image::display_hash(&chip8.get_display()));
+    assert_eq!(hash, expected);
+
+    let (_, other) = headless("hash-other", &digit_rom(8), &["--frames", "2", "--format", "hash"], &[]);
+    assert_ne!(other, expected);
+}
+
+#[test]
+fn interpreter_errors_exit_with_1() {
+    let (output, _) = headless("error", &rom![0x6001, 0x5001], &[], &[]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("No such opcode: 0x5001 at 0x0202"));
+
+    let (output, _) = headless("usage", &[], &["--frames", "many"], &[]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn cycle_budgets_need_instructions_to_run() {
+    let (output, _) = headless("no-ips", &digit_rom(0), &["--cycles", "10", "--ips", "0"], &[]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("--cycles needs more than 0 instructions per second"));
+}
+
+#[test]
+fn key_waits_dont_use_up_the_cycle_budget() {
+    // Waits for a key, then draws it
+    let rom = rom![0xF30A, 0xF329, 0xD005, 0x1206];
+    let input = [("keys.txt", "1 5 down\n2 5 up\n")];
+
+    // One cycle in each of the two frames spent waiting, then three to read the key and draw it
+    let (output, text) = headless("cycles", &rom, &["--cycles", "5", "--input", "keys.txt"], &input);
+    assert!(output.status.success());
+    assert_eq!(text.lines().map(|row| &row[..4]).take(5).collect::<Vec<_>>(), ["####", "#...", "####", "...#", "####"]);
+
+    // Without any input the wait can't end, so the run stops instead of hanging
+    let (output, text) = headless("stuck", &rom, &["--cycles", "1000"], &[]);
+    assert!(output.status.success());
+    assert!(text.lines().all(|row| !row.contains('#')));
+}
@@ -0,0 +1,169 @@
+// Movies: a recorded session replays frame for frame, and the text format holds up
+mod common;
+
+use chip8::movie::{self, InputEvent};
+use chip8::{FrameScheduler, Movie, MoviePlayer, MovieRecorder, Quirks};
+use common::machine;
+
+const SEED: u64 = 99;
+const IPS: u32 = 700;
+
+// Moves a random digit right while key 5 is held, so the screen depends on the
+// exact frames the key changed on and on the random numbers drawn
+fn game() -> Vec<u8> {
+    rom![
+        0x6505, // LD V5, 5
+        0xE5A1, // SKNP V5
+        0x7101, // ADD V1, 1
+        0xC20F, // RND V2, 0x0F
+        0xF229, // LD F, V2
+        0x00E0, // CLS
+        0xD125, // DRW V1, V2, 5
+        0x1202, // JP 0x202
+    ]
+}
+
+// Plays 120 frames pressing and releasing key 5 a few times, recording as it goes.
+// Returns the movie and the machine's state after frame 60 and at the end
+fn record_session() -> (Movie, Vec<u8>, Vec<u8>) {
+    let mut chip8 = machine(Quirks::modern(), &game());
+    chip8.seed(SEED);
+    let mut scheduler = FrameScheduler::new(IPS);
+    let mut recorder = MovieRecorder::new(Movie::new(&game(), SEED, IPS, Quirks::modern()));
+    let mut middle = Vec::new();
+
+    for frame in 0..120 {
+        for (at, pressed) in [(10, true), (40, false), (55, true), (90, false)] {
+            if frame == at {
+                chip8.keypress(5, pressed);
+                recorder.keypress(5, pressed);
+            }
+        }
+        chip8.run_frame(scheduler.cycles_for_frame()).unwrap();
+        recorder.end_frame();
+        if frame == 59 {
+            middle = chip8.save_state();
+        }
+    }
+
+    (recorder.finish(), middle, chip8.save_state())
+}
+
+#[test]
+fn movies_replay_frame_for_frame() {
+    let (movie, middle, end) = record_session();
+    assert_eq!(movie.frames, 120);
+    assert_eq!(movie.events.len(), 4);
+
+    // Step by step, the 60th frame comes out the same
+    let mut chip8 = machine(Quirks::modern(), &game());
+    chip8.seed(movie.seed);
+    let mut scheduler = FrameScheduler::new(movie.instructions_per_second);
+    let mut player = MoviePlayer::new(movie.clone());
+    for _ in 0..60 {
+        player.apply(&mut chip8);
+        chip8.run_frame(scheduler.cycles_for_frame()).unwrap();
+    }
+    assert_eq!(chip8.save_state(), middle);
+
+    // And so does the end, played through a saved copy of the movie
+    let movie = Movie::from_text(&movie.to_text()).unwrap();
+    let mut chip8 = machine(Quirks::modern(), &game());
+    movie.play(&mut chip8).unwrap();
+    assert_eq!(chip8.save_state(), end);
+}
+
+#[test]
+fn replays_with_other_input_differ() {
+    let (mut movie, _, end) = record_session();
+    movie.events[1].frame += 1;
+
+    let mut chip8 = machine(Quirks::modern(), &game());
+    movie.play(&mut chip8).unwrap();
+    assert_ne!(chip8.save_state(), end);
+}
+
+#[test]
+fn movie_text_round_trips() {
+    let mut movie = Movie::new(&game(), 1234, 600, Quirks::vip());
+    movie.frames = 300;
+    movie.events = vec![
+        InputEvent { frame: 12, key: 0x5, pressed: true },
+        InputEvent { frame: 30, key: 0x5, pressed: false },
+        InputEvent { frame: 30, key: 0xF, pressed: true },
+    ];
+
+    let text = movie.to_text();
+    assert!(text.starts_with("chip8-movie 1\n"));
+    assert!(text.ends_with("12 5 down\n30 5 up\n30 F down\n"));
+    assert_eq!(Movie::from_text(&text).unwrap(), movie);
+
+    // Movies from before the load/store quirk had three settings still read
+    let old = text.replace("load_store=x+1", "load_store_increments_i=1");
+    assert_eq!(Movie::from_text(&old).unwrap(), movie);
+    let mut chip48 = movie.clone();
+    chip48.quirks = Quirks::chip48();
+    assert_eq!(Movie::from_text(&chip48.to_text()).unwrap(), chip48);
+
+    let error = Movie::from_text(&text.replace("ips 600", "ips fast")).unwrap_err();
+    assert_eq!(error.line, 4);
+    assert!(Movie::from_text("").is_err());
+    assert!(Movie::from_text("chip8-movie 2\n").is_err());
+}
+
+#[test]
+fn check_warns_about_a_different_rom_or_quirks() {
+    let movie = Movie::new(&game(), SEED, IPS, Quirks::modern());
+    assert!(movie.check(&game(), Quirks::modern()).is_empty());
+
+    let warnings = movie.check(&rom![0x1200], Quirks::modern());
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].starts_with("ROM hash"));
+
+    let mut quirks = Quirks::modern();
+    quirks.clip_sprites = !quirks.clip_sprites;
+    let warnings = movie.check(&game(), quirks);
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].contains("clip_sprites"));
+}
+
+#[test]
+fn input_scripts() {
+    let events = movie::parse_input_script("# press 5 for a second\n\n60 5 down\n120 5 up\n").unwrap();
+    assert_eq!(events, [
+        InputEvent { frame: 60, key: 5, pressed: true },
+        InputEvent { frame: 120, key: 5, pressed: false },
+    ]);
+
+    for (script, line) in [
+        ("60 5\n", 1),
+        ("sixty 5 down\n", 1),
+        ("60 G down\n", 1),
+        ("60 10 down\n", 1),
+        ("60 5 pressed\n", 1),
+        ("60 5 down\n30 5 up\n", 2),
+    ] {
+        let error = movie::parse_input_script(script).unwrap_err();
+        assert_eq!(error.line, line, "{:?}", script);
+    }
+}
+
+#[test]
+fn recorder_tags_keys_with_the_frame_about_to_run() {
+    let mut recorder = MovieRecorder::new(Movie::new(&game(), SEED, IPS, Quirks::modern()));
+    recorder.keypress(1, true);
+    recorder.end_frame();
+    recorder.end_frame();
+    recorder.keypress(1, false);
+    let movie = recorder.finish();
+
+    assert_eq!(movie.frames, 2);
+    assert_eq!(movie.events[1], InputEvent { frame: 2, key: 1, pressed: false });
+
+    let mut chip8 = machine(Quirks::modern(), &game());
+    let mut player = MoviePlayer::new(movie);
+    player.apply(&mut chip8);
+    assert!(chip8.keys()[1]);
+    player.apply(&mut chip8);
+    assert!(player.finished());
+}
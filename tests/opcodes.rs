@@ -0,0 +1,394 @@
+// Conformance tests for the original CHIP-8 instruction set
+mod common;
+
+use chip8::{Chip8, Chip8Error, Quirks, Step};
+use common::{lit_pixels, machine, run, run_rom, screen};
+
+fn modern() -> Quirks {
+    Quirks::modern()
+}
+
+#[test]
+fn clear_screen_00e0() {
+    // Draw the "0" glyph, then clear
+    let mut chip8 = run_rom(modern(), &rom![0xA000, 0xD005, 0x00E0], 2);
+    assert!(lit_pixels(&chip8) > 0);
+
+    run(&mut chip8, 1);
+    assert_eq!(lit_pixels(&chip8), 0);
+}
+
+#[test]
+fn call_and_return_2nnn_00ee() {
+    let rom = rom![
+        0x2206, // 200: call 206
+        0x6101, // 202: V1 = 1
+        0x1204, // 204: loop
+        0x6002, // 206: V0 = 2
+        0x00EE, // 208: return
+    ];
+    let mut chip8 = machine(modern(), &rom);
+
+    run(&mut chip8, 1);
+    assert_eq!(chip8.pc(), 0x206);
+    assert_eq!(chip8.stack(), &[0x202]);
+
+    run(&mut chip8, 3);
+    assert_eq!(chip8.stack(), &[] as &[u16]);
+    assert_eq!(chip8.registers()[0], 2);
+    assert_eq!(chip8.registers()[1], 1);
+    assert_eq!(chip8.pc(), 0x204);
+}
+
+#[test]
+fn jump_1nnn() {
+    let chip8 = run_rom(modern(), &rom![0x1ABC], 1);
+    assert_eq!(chip8.pc(), 0xABC);
+}
+
+#[test]
+fn skip_if_equal_3xkk() {
+    assert_eq!(run_rom(modern(), &rom![0x6012, 0x3012], 2).pc(), 0x206);
+    assert_eq!(run_rom(modern(), &rom![0x6012, 0x3013], 2).pc(), 0x204);
+}
+
+#[test]
+fn skip_if_not_equal_4xkk() {
+    assert_eq!(run_rom(modern(), &rom![0x6012, 0x4012], 2).pc(), 0x204);
+    assert_eq!(run_rom(modern(), &rom![0x6012, 0x4013], 2).pc(), 0x206);
+}
+
+#[test]
+fn skip_if_registers_equal_5xy0() {
+    // Vy must come from the third nibble, not from the whole low byte
+    assert_eq!(run_rom(modern(), &rom![0x6005, 0x6305, 0x5030], 3).pc(), 0x208);
+    assert_eq!(run_rom(modern(), &rom![0x6005, 0x6306, 0x5030], 3).pc(), 0x206);
+}
+
+#[test]
+fn skip_if_registers_differ_9xy0() {
+    assert_eq!(run_rom(modern(), &rom![0x6005, 0x6305, 0x9030], 3).pc(), 0x206);
+    assert_eq!(run_rom(modern(), &rom![0x6005, 0x6306, 0x9030], 3).pc(), 0x208);
+}
+
+#[test]
+fn register_skips_take_y_from_the_third_nibble() {
+    // V1 and V2 match but V0 doesn't, so a Vy read from the low byte would compare V1 with V0
+    assert_eq!(run_rom(modern(), &rom![0x6107, 0x6207, 0x5120], 3).pc(), 0x208);
+    assert_eq!(run_rom(modern(), &rom![0x6107, 0x6207, 0x9120], 3).pc(), 0x206);
+}
+
+#[test]
+fn load_and_add_6xkk_7xkk() {
+    let chip8 = run_rom(modern(), &rom![0x6AFE, 0x7A03, 0x6F00], 3);
+    // 7xkk wraps around and never touches VF
+    assert_eq!(chip8.registers()[0xA], 0x01);
+    assert_eq!(chip8.registers()[0xF], 0);
+}
+
+#[test]
+fn register_copy_8xy0() {
+    let chip8 = run_rom(modern(), &rom![0x6142, 0x8010], 2);
+    assert_eq!(chip8.registers()[0], 0x42);
+}
+
+#[test]
+fn logic_8xy1_8xy2_8xy3() {
+    let or = run_rom(modern(), &rom![0x600C, 0x610A, 0x8011], 3);
+    assert_eq!(or.registers()[0], 0x0E);
+
+    let and = run_rom(modern(), &rom![0x600C, 0x610A, 0x8012], 3);
+    assert_eq!(and.registers()[0], 0x08);
+
+    let xor = run_rom(modern(), &rom![0x600C, 0x610A, 0x8013], 3);
+    assert_eq!(xor.registers()[0], 0x06);
+}
+
+#[test]
+fn add_with_carry_8xy4() {
+    let chip8 = run_rom(modern(), &rom![0x60F0, 0x6120, 0x8014], 3);
+    assert_eq!(chip8.registers()[0], 0x10);
+    assert_eq!(chip8.registers()[0xF], 1);
+
+    let chip8 = run_rom(modern(), &rom![0x6010, 0x6120, 0x8014], 3);
+    assert_eq!(chip8.registers()[0], 0x30);
+    assert_eq!(chip8.registers()[0xF], 0);
+}
+
+#[test]
+fn subtract_8xy5_sets_vf_to_not_borrow() {
+    let chip8 = run_rom(modern(), &rom![0x6030, 0x6110, 0x8015], 3);
+    assert_eq!(chip8.registers()[0], 0x20);
+    assert_eq!(chip8.registers()[0xF], 1);
+
+    let chip8 = run_rom(modern(), &rom![0x6010, 0x6130, 0x8015], 3);
+    assert_eq!(chip8.registers()[0], 0xE0);
+    assert_eq!(chip8.registers()[0xF], 0);
+}
+
+#[test]
+fn reverse_subtract_8xy7() {
+    let chip8 = run_rom(modern(), &rom![0x6010, 0x6130, 0x8017], 3);
+    assert_eq!(chip8.registers()[0], 0x20);
+    assert_eq!(chip8.registers()[0xF], 1);
+
+    let chip8 = run_rom(modern(), &rom![0x6030, 0x6110, 0x8017], 3);
+    assert_eq!(chip8.registers()[0], 0xE0);
+    assert_eq!(chip8.registers()[0xF], 0);
+}
+
+#[test]
+fn shifts_8xy6_8xye() {
+    let right = run_rom(modern(), &rom![0x6005, 0x8006], 2);
+    assert_eq!(right.registers()[0], 0x02);
+    assert_eq!(right.registers()[0xF], 1);
+
+    let left = run_rom(modern(), &rom![0x6081, 0x800E], 2);
+    assert_eq!(left.registers()[0], 0x02);
+    assert_eq!(left.registers()[0xF], 1);
+}
+
+#[test]
+fn flag_result_wins_when_vf_is_the_target() {
+    let chip8 = run_rom(modern(), &rom![0x6FF0, 0x6120, 0x8F14], 3);
+    assert_eq!(chip8.registers()[0xF], 1);
+}
+
+#[test]
+fn set_index_annn() {
+    let chip8 = run_rom(modern(), &rom![0xA123], 1);
+    assert_eq!(chip8.index_register(), 0x123);
+}
+
+#[test]
+fn jump_with_offset_bnnn() {
+    let chip8 = run_rom(modern(), &rom![0x6004, 0xB300], 2);
+    assert_eq!(chip8.pc(), 0x304);
+}
+
+#[test]
+fn random_cxkk_is_masked_and_seeded() {
+    let rom = rom![0xC0FF, 0xC10F, 0xC200];
+
+    let mut first = machine(modern(), &rom);
+    first.seed(99);
+    run(&mut first, 3);
+
+    let mut second = machine(modern(), &rom);
+    second.seed(99);
+    run(&mut second, 3);
+
+    assert_eq!(first.registers(), second.registers());
+    assert!(first.registers()[1] <= 0x0F);
+    assert_eq!(first.registers()[2], 0);
+}
+
+#[test]
+fn draw_dxyn_draws_and_detects_collision() {
+    // Draw the "1" glyph at (3, 2)
+    let chip8 = run_rom(modern(), &rom![0x6001, 0xF029, 0x6103, 0x6202, 0xD125], 5);
+    assert_eq!(
+        screen(&chip8, 3, 2, 8, 5),
+        vec!["..#.....", ".##.....", "..#.....", "..#.....", ".###...."]
+    );
+    assert_eq!(chip8.registers()[0xF], 0);
+
+    // Drawing it again erases it and reports the collision
+    let chip8 = run_rom(modern(), &rom![0x6001, 0xF029, 0x6103, 0x6202, 0xD125, 0xD125], 6);
+    assert_eq!(lit_pixels(&chip8), 0);
+    assert_eq!(chip8.registers()[0xF], 1);
+}
+
+#[test]
+fn key_skips_ex9e_exa1() {
+    let mut chip8 = machine(modern(), &rom![0x6005, 0xE09E]);
+    chip8.keypress(5, true);
+    run(&mut chip8, 2);
+    assert_eq!(chip8.pc(), 0x206);
+
+    let mut chip8 = machine(modern(), &rom![0x6005, 0xE0A1]);
+    chip8.keypress(5, true);
+    run(&mut chip8, 2);
+    assert_eq!(chip8.pc(), 0x204);
+
+    let chip8 = run_rom(modern(), &rom![0x6005, 0xE0A1], 2);
+    assert_eq!(chip8.pc(), 0x206);
+}
+
+#[test]
+fn wait_for_key_fx0a() {
+    let mut chip8 = machine(modern(), &rom![0xF30A, 0x6401]);
+    let step = chip8.cycle().unwrap();
+    assert!(step.waiting_for_key);
+    assert!(chip8.is_waiting_for_key());
+    assert_eq!(chip8.pc(), 0x200);
+
+    // Pressing isn't enough, the key has to come back up
+    chip8.keypress(0xB, true);
+    run(&mut chip8, 3);
+    assert_eq!(chip8.pc(), 0x200);
+
+    chip8.keypress(0xB, false);
+    let step = chip8.cycle().unwrap();
+    assert!(!step.waiting_for_key);
+    assert!(!chip8.is_waiting_for_key());
+    assert_eq!((chip8.pc(), chip8.registers()[3]), (0x202, 0xB));
+}
+
+#[test]
+fn fx0a_ignores_keys_held_before_the_wait() {
+    let mut chip8 = machine(modern(), &rom![0xF30A]);
+    chip8.keypress(0x5, true);
+    run(&mut chip8, 1);
+    chip8.keypress(0x5, false);
+    run(&mut chip8, 1);
+    assert!(chip8.is_waiting_for_key());
+
+    // The first key to go down during the wait is the one that counts
+    chip8.keypress(0x2, true);
+    chip8.keypress(0x5, true);
+    chip8.keypress(0x5, false);
+    chip8.keypress(0x2, false);
+    run(&mut chip8, 1);
+    assert_eq!((chip8.pc(), chip8.registers()[3]), (0x202, 0x2));
+}
+
+#[test]
+fn fx0a_wait_survives_save_states() {
+    let mut chip8 = machine(modern(), &rom![0xF30A]);
+    run(&mut chip8, 1);
+    chip8.keypress(0x9, true);
+    let state = chip8.save_state();
+
+    let mut restored = machine(modern(), &[]);
+    restored.load_state(&state).unwrap();
+    assert!(restored.is_waiting_for_key());
+    restored.keypress(0x9, false);
+    run(&mut restored, 1);
+    assert_eq!((restored.pc(), restored.registers()[3]), (0x202, 0x9));
+}
+
+#[test]
+fn fx0a_keeps_the_timers_running() {
+    let mut chip8 = machine(modern(), &rom![0x6003, 0xF015, 0xF00A]);
+    for _ in 0..3 {
+        chip8.run_frame(10).unwrap();
+    }
+    assert_eq!(chip8.delay_timer(), 0);
+    assert_eq!(chip8.pc(), 0x204);
+}
+
+#[test]
+fn steps_report_what_changed() {
+    let mut chip8 = machine(modern(), &rom![0x6005, 0x00E0, 0xD015, 0xF018]);
+
+    let step = chip8.cycle().unwrap();
+    assert_eq!(step, Step { pc: 0x200, opcode: 0x6005, ..Step::default() });
+    assert!(chip8.cycle().unwrap().display_changed);
+    assert!(chip8.cycle().unwrap().display_changed);
+
+    let step = chip8.cycle().unwrap();
+    assert!(step.sound_changed && !step.display_changed);
+}
+
+#[test]
+fn timers_report_the_buzzer_running_out() {
+    let mut chip8 = machine(modern(), &rom![0x6002, 0xF018]);
+    run(&mut chip8, 2);
+
+    assert!(!chip8.cycle_timers());
+    assert!(chip8.sound_active());
+    assert!(chip8.cycle_timers());
+    assert!(!chip8.sound_active());
+    assert!(!chip8.cycle_timers());
+}
+
+#[test]
+fn timers_fx07_fx15_fx18() {
+    let mut chip8 = machine(modern(), &rom![0x6005, 0xF015, 0xF018, 0xF107]);
+    run(&mut chip8, 3);
+    assert_eq!(chip8.delay_timer(), 5);
+    assert_eq!(chip8.sound_timer(), 5);
+    assert!(chip8.sound_active());
+
+    for _ in 0..2 {
+        chip8.cycle_timers();
+    }
+    run(&mut chip8, 1);
+    assert_eq!(chip8.registers()[1], 3);
+
+    for _ in 0..3 {
+        chip8.cycle_timers();
+    }
+    assert!(!chip8.sound_active());
+}
+
+#[test]
+fn add_to_index_fx1e() {
+    let chip8 = run_rom(modern(), &rom![0xA100, 0x6020, 0xF01E], 3);
+    assert_eq!(chip8.index_register(), 0x120);
+}
+
+#[test]
+fn font_sprite_fx29() {
+    let chip8 = run_rom(modern(), &rom![0x600A, 0xF029], 2);
+    assert_eq!(chip8.index_register(), 0xA * 5);
+}
+
+#[test]
+fn bcd_fx33() {
+    let chip8 = run_rom(modern(), &rom![0x60FE, 0xA300, 0xF033], 3);
+    assert_eq!(&chip8.memory()[0x300..0x303], &[2, 5, 4]);
+}
+
+#[test]
+fn store_and_load_registers_fx55_fx65() {
+    let rom = rom![0x6011, 0x6122, 0x6233, 0xA300, 0xF255, 0x6000, 0x6100, 0x6200, 0xF165];
+    let chip8 = run_rom(modern(), &rom, 9);
+
+    assert_eq!(&chip8.memory()[0x300..0x304], &[0x11, 0x22, 0x33, 0x00]);
+    assert_eq!(&chip8.registers()[..3], &[0x11, 0x22, 0x00]);
+}
+
+#[test]
+fn unknown_opcode_is_an_error() {
+    let mut chip8 = machine(modern(), &rom![0x6000, 0x5001]);
+    run(&mut chip8, 1);
+
+    match chip8.cycle() {
+        Err(Chip8Error::UnknownOpcode { opcode, pc }) => {
+            assert_eq!(opcode, 0x5001);
+            assert_eq!(pc, 0x202);
+        },
+        other => panic!("expected an unknown opcode, got {:?}", other),
+    }
+}
+
+#[test]
+fn stack_errors() {
+    let mut chip8 = machine(modern(), &rom![0x00EE]);
+    assert!(matches!(chip8.cycle(), Err(Chip8Error::StackUnderflow { pc: 0x200 })));
+
+    // Calling itself forever runs out of stack on the 17th call
+    let mut chip8 = machine(modern(), &rom![0x2200]);
+    run(&mut chip8, 16);
+    assert!(matches!(chip8.cycle(), Err(Chip8Error::StackOverflow { pc: 0x200 })));
+}
+
+#[test]
+fn out_of_bounds_memory_is_an_error() {
+    // Point I at the very last byte, then store two registers there
+    let mut chip8 = machine(modern(), &rom![0xF000, 0xFFFF, 0xF155]);
+    run(&mut chip8, 1);
+    assert!(matches!(
+        chip8.cycle(),
+        Err(Chip8Error::MemoryOutOfBounds { address: 0x10000, pc: 0x204 })
+    ));
+}
+
+#[test]
+fn rom_too_large() {
+    let mut chip8 = Chip8::initialize(modern());
+    let rom = vec![0; chip8::MEMORY_SIZE];
+    assert!(matches!(chip8.load_rom(&rom), Err(Chip8Error::RomTooLarge { .. })));
+}
@@ -0,0 +1,55 @@
+// Named palettes, custom colors and palette files
+use chip8::image::DEFAULT_COLORS;
+use chip8::palette::{self, parse_color};
+use chip8::Palette;
+
+#[test]
+fn builtin_palettes() {
+    let names: Vec<String> = Palette::builtin().into_iter().map(|palette| palette.name).collect();
+    assert_eq!(names, ["mono", "green", "amber", "octo", "contrast"]);
+    assert_eq!(Palette::builtin()[0].colors, DEFAULT_COLORS);
+    assert_eq!(Palette::named("OCTO").unwrap().colors[1], [0xFF, 0xCC, 0x00]);
+    assert!(Palette::named("sepia").is_none());
+}
+
+#[test]
+fn parses_colors() {
+    assert_eq!(parse_color("#33FF66"), Ok([0x33, 0xFF, 0x66]));
+    assert_eq!(parse_color("0a0b0c"), Ok([0x0A, 0x0B, 0x0C]));
+    assert!(parse_color("#FFF").is_err());
+    assert!(parse_color("+FFFFF").is_err());
+
+    // Two colors leave both planes in the foreground color
+    let palette = Palette::parse("custom", "#000000, #33FF66").unwrap();
+    assert_eq!(palette.colors, [[0, 0, 0], [0x33, 0xFF, 0x66], [0x33, 0xFF, 0x66], [0x33, 0xFF, 0x66]]);
+    assert!(Palette::parse("custom", "#000000 #111111 #222222").is_err());
+}
+
+#[test]
+fn palette_files() {
+    let text = "\
+; handheld greens
+gameboy = #0F380F #9BBC0F #306230 #8BAC0F
+
+paper = FFFFFF 000000
+";
+    let palettes = Palette::parse_file(text).unwrap();
+    assert_eq!(palettes.len(), 2);
+    assert_eq!(palettes[0].name, "gameboy");
+    assert_eq!(palettes[1].colors[3], [0, 0, 0]);
+
+    assert_eq!(Palette::parse_file("paper #FFFFFF #000000").unwrap_err(), "line 1: expected 'name = colors'");
+    assert!(Palette::parse_file("\npaper = #FFFFFF").unwrap_err().starts_with("line 2: "));
+}
+
+#[test]
+fn choosing_a_palette() {
+    let mut palettes = Palette::builtin();
+    assert_eq!(palette::choose(&mut palettes, "Amber"), Ok(2));
+
+    let index = palette::choose(&mut palettes, "#101010,#F0F0F0").unwrap();
+    assert_eq!(index, 5);
+    assert_eq!(palettes[index].name, "custom");
+
+    assert!(palette::choose(&mut palettes, "sepia").unwrap_err().starts_with("Unknown palette 'sepia'"));
+}
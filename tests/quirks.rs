@@ -0,0 +1,124 @@
+// Every quirk under every platform profile
+mod common;
+
+use chip8::{LoadStore, Platform, Quirks};
+use common::{lit_pixels, run_rom, screen};
+
+#[test]
+fn profiles() {
+    let expected = [
+        // platform, vf reset, shift ignores vy, load/store moves I, jump uses vx, clip
+        (Platform::Vip, true, false, LoadStore::IncrementByXPlus1, false, true),
+        (Platform::Chip48, false, true, LoadStore::IncrementByX, true, true),
+        (Platform::SuperChip, false, true, LoadStore::Unchanged, true, true),
+        (Platform::XoChip, false, false, LoadStore::IncrementByXPlus1, false, false),
+        (Platform::Modern, false, true, LoadStore::Unchanged, false, false),
+    ];
+
+    for (platform, vf_reset, shift, load_store, jump, clip) in expected {
+        let quirks = Quirks::for_platform(platform);
+        assert_eq!(quirks.logic_resets_vf, vf_reset, "{}", platform);
+        assert_eq!(quirks.shift_ignores_vy, shift, "{}", platform);
+        assert_eq!(quirks.load_store, load_store, "{}", platform);
+        assert_eq!(quirks.jump_uses_vx, jump, "{}", platform);
+        assert_eq!(quirks.clip_sprites, clip, "{}", platform);
+    }
+}
+
+#[test]
+fn platform_names_round_trip() {
+    for platform in Platform::ALL {
+        assert_eq!(platform.name().parse::<Platform>(), Ok(platform));
+    }
+    assert!("pdp-11".parse::<Platform>().is_err());
+
+    for load_store in LoadStore::ALL {
+        assert_eq!(load_store.name().parse::<LoadStore>(), Ok(load_store));
+    }
+}
+
+#[test]
+fn logic_resets_vf() {
+    for platform in Platform::ALL {
+        let quirks = Quirks::for_platform(platform);
+        for opcode in [0x8011, 0x8012, 0x8013] {
+            let chip8 = run_rom(quirks, &rom![0x600C, 0x610A, 0x6F07, opcode], 4);
+            let expected = if quirks.logic_resets_vf { 0 } else { 7 };
+            assert_eq!(chip8.registers()[0xF], expected, "{} {:04x}", platform, opcode);
+        }
+    }
+}
+
+#[test]
+fn shift_source_register() {
+    for platform in Platform::ALL {
+        let quirks = Quirks::for_platform(platform);
+
+        // V0 = 0x10, V1 = 0x03
+        let right = run_rom(quirks, &rom![0x6010, 0x6103, 0x8016], 3);
+        let left = run_rom(quirks, &rom![0x6010, 0x6103, 0x801E], 3);
+
+        if quirks.shift_ignores_vy {
+            assert_eq!((right.registers()[0], right.registers()[0xF]), (0x08, 0), "{}", platform);
+            assert_eq!((left.registers()[0], left.registers()[0xF]), (0x20, 0), "{}", platform);
+        } else {
+            assert_eq!((right.registers()[0], right.registers()[0xF]), (0x01, 1), "{}", platform);
+            assert_eq!((left.registers()[0], left.registers()[0xF]), (0x06, 0), "{}", platform);
+        }
+    }
+}
+
+#[test]
+fn load_store_index_increment() {
+    for platform in Platform::ALL {
+        let quirks = Quirks::for_platform(platform);
+        let expected = match quirks.load_store {
+            LoadStore::Unchanged => 0x300,
+            LoadStore::IncrementByX => 0x302,
+            LoadStore::IncrementByXPlus1 => 0x303,
+        };
+
+        let store = run_rom(quirks, &rom![0xA300, 0xF255], 2);
+        assert_eq!(store.index_register(), expected, "{}", platform);
+
+        let load = run_rom(quirks, &rom![0xA300, 0xF265], 2);
+        assert_eq!(load.index_register(), expected, "{}", platform);
+    }
+}
+
+#[test]
+fn jump_with_offset_register() {
+    for platform in Platform::ALL {
+        let quirks = Quirks::for_platform(platform);
+        // V0 = 1, V3 = 0x10, then B320
+        let chip8 = run_rom(quirks, &rom![0x6001, 0x6310, 0xB320], 3);
+        let expected = if quirks.jump_uses_vx { 0x330 } else { 0x321 };
+        assert_eq!(chip8.pc(), expected, "{}", platform);
+    }
+}
+
+#[test]
+fn sprite_clipping_and_wrapping() {
+    for platform in Platform::ALL {
+        let quirks = Quirks::for_platform(platform);
+        // Draw the "0" glyph at (62, 30) so it hangs off the right and bottom edges
+        let chip8 = run_rom(quirks, &rom![0x603E, 0x611E, 0xA000, 0xD015], 4);
+
+        if quirks.clip_sprites {
+            assert_eq!(screen(&chip8, 62, 30, 2, 2), vec!["##", "#."], "{}", platform);
+            assert_eq!(lit_pixels(&chip8), 3, "{}", platform);
+        } else {
+            assert_eq!(screen(&chip8, 0, 0, 2, 3), vec![".#", ".#", "##"], "{}", platform);
+            assert_eq!(lit_pixels(&chip8), 14, "{}", platform);
+        }
+    }
+}
+
+#[test]
+fn sprite_start_position_always_wraps() {
+    for platform in Platform::ALL {
+        // (66, 33) is (2, 1) once wrapped, whether or not the rest gets clipped
+        let chip8 = run_rom(Quirks::for_platform(platform), &rom![0x6042, 0x6121, 0xA000, 0xD011], 4);
+        assert_eq!(screen(&chip8, 2, 1, 4, 1), vec!["####"], "{}", platform);
+    }
+}
@@ -0,0 +1,116 @@
+// Rewinding: snapshots come back newest first and byte for byte, within the time kept
+mod common;
+
+use chip8::{Chip8, Quirks, RewindBuffer};
+use common::machine;
+
+// Counts frames in V0, stores the count in memory and draws it, so every frame differs
+fn counting_machine() -> Chip8 {
+    machine(Quirks::modern(), &rom![
+        0x7001, // ADD V0, 1
+        0xA400, // LD I, 0x400
+        0xF055, // LD [I], V0
+        0xF029, // LD F, V0
+        0xD115, // DRW V1, V1, 5
+        0x6103, // LD V1, 3
+        0x1200, // JP 0x200
+    ])
+}
+
+// Runs `frames` frames, recording each one, and returns the state after every
+// frame that the buffer took a snapshot of
+fn play(chip8: &mut Chip8, rewind: &mut RewindBuffer, frames: u32, interval: u32) -> Vec<Vec<u8>> {
+    let mut snapshots = Vec::new();
+    for frame in 1..=frames {
+        chip8.run_frame(7).unwrap();
+        rewind.record(chip8);
+        if frame % interval == 0 {
+            snapshots.push(chip8.save_state());
+        }
+    }
+    snapshots
+}
+
+#[test]
+fn rewinding_restores_snapshots_newest_first() {
+    let mut chip8 = counting_machine();
+    let mut rewind = RewindBuffer::new(3, 10);
+    let snapshots = play(&mut chip8, &mut rewind, 30, 3);
+    assert_eq!(rewind.len(), 10);
+
+    for expected in snapshots.iter().rev() {
+        assert!(rewind.rewind(&mut chip8));
+        assert_eq!(&chip8.save_state(), expected);
+    }
+
+    // Holding rewind stays on the oldest snapshot
+    assert!(rewind.rewind(&mut chip8));
+    assert_eq!(chip8.save_state(), snapshots[0]);
+    assert_eq!(rewind.len(), 1);
+}
+
+#[test]
+fn recording_after_a_rewind_carries_on_from_there() {
+    let mut chip8 = counting_machine();
+    let mut rewind = RewindBuffer::new(1, 10);
+    let snapshots = play(&mut chip8, &mut rewind, 10, 1);
+
+    for _ in 0..4 {
+        rewind.rewind(&mut chip8);
+    }
+    assert_eq!(chip8.save_state(), snapshots[6]);
+
+    let later = play(&mut chip8, &mut rewind, 2, 1);
+    rewind.rewind(&mut chip8);
+    assert_eq!(chip8.save_state(), later[1]);
+    rewind.rewind(&mut chip8);
+    assert_eq!(chip8.save_state(), later[0]);
+    rewind.rewind(&mut chip8);
+    assert_eq!(chip8.save_state(), snapshots[5]);
+}
+
+#[test]
+fn only_the_last_seconds_are_kept() {
+    let mut chip8 = counting_machine();
+    // One second at a snapshot every 2 frames is 30 snapshots
+    let mut rewind = RewindBuffer::new(2, 1);
+    let snapshots = play(&mut chip8, &mut rewind, 100, 2);
+    assert_eq!(rewind.len(), 30);
+
+    for _ in 0..30 {
+        rewind.rewind(&mut chip8);
+    }
+    assert_eq!(chip8.save_state(), snapshots[snapshots.len() - 30]);
+}
+
+#[test]
+fn no_seconds_means_no_rewinding() {
+    let mut chip8 = counting_machine();
+    let mut rewind = RewindBuffer::new(1, 0);
+    play(&mut chip8, &mut rewind, 10, 1);
+    assert!(rewind.is_empty());
+    assert!(!rewind.rewind(&mut chip8));
+}
+
+#[test]
+fn idle_frames_cost_almost_nothing() {
+    let mut chip8 = machine(Quirks::modern(), &rom![0x1200]);
+    let mut rewind = RewindBuffer::new(1, 10);
+    play(&mut chip8, &mut rewind, 60, 1);
+
+    let full = chip8.save_state().len();
+    assert_eq!(rewind.len(), 60);
+    assert!(rewind.memory_usage() < full + 60 * 16, "{} bytes for 60 snapshots", rewind.memory_usage());
+}
+
+#[test]
+fn long_rewind_times_dont_overflow() {
+    let mut chip8 = counting_machine();
+    let mut rewind = RewindBuffer::new(1, u32::MAX);
+    let snapshots = play(&mut chip8, &mut rewind, 3, 1);
+    assert_eq!(rewind.len(), 3);
+
+    rewind.rewind(&mut chip8);
+    rewind.rewind(&mut chip8);
+    assert_eq!(chip8.save_state(), snapshots[1]);
+}
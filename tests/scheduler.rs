@@ -0,0 +1,74 @@
+// Frame scheduling: 60 Hz frames from wall-clock time and instructions spread over them
+use std::time::{Duration, Instant};
+
+use chip8::scheduler::{DEFAULT_MAX_CATCH_UP, TIMER_HZ};
+use chip8::FrameScheduler;
+
+#[test]
+fn the_first_call_starts_the_clock() {
+    let mut scheduler = FrameScheduler::new(600);
+    let start = Instant::now();
+    assert_eq!(scheduler.frames_due(start + Duration::from_secs(5)), 0);
+    assert_eq!(scheduler.frames_due(start + Duration::from_secs(5)), 0);
+}
+
+#[test]
+fn frames_follow_the_clock_whatever_the_refresh_rate() {
+    let mut scheduler = FrameScheduler::new(600);
+    let start = Instant::now();
+    scheduler.frames_due(start);
+
+    // 100 Hz and 144 Hz hosts both get exactly 60 frames a second
+    let frames: u32 = (1..=100).map(|i| scheduler.frames_due(start + Duration::from_millis(10 * i))).sum();
+    assert_eq!(frames, TIMER_HZ);
+
+    let start = start + Duration::from_secs(1);
+    let frames: u32 = (1..=144u32)
+        .map(|i| scheduler.frames_due(start + Duration::from_secs(1) * i / 144))
+        .sum();
+    assert_eq!(frames, TIMER_HZ);
+}
+
+#[test]
+fn stalls_only_catch_up_a_few_frames() {
+    let mut scheduler = FrameScheduler::new(600);
+    let start = Instant::now();
+    scheduler.frames_due(start);
+    assert_eq!(scheduler.frames_due(start + Duration::from_secs(2)), DEFAULT_MAX_CATCH_UP);
+
+    // The rest of the stall is forgotten, the next frame is due a frame later
+    let after = start + Duration::from_secs(2);
+    assert_eq!(scheduler.frames_due(after + Duration::from_millis(10)), 0);
+    assert_eq!(scheduler.frames_due(after + Duration::from_millis(17)), 1);
+
+    let mut scheduler = FrameScheduler::new(600).max_catch_up(2);
+    scheduler.frames_due(start);
+    assert_eq!(scheduler.frames_due(start + Duration::from_millis(500)), 2);
+}
+
+#[test]
+fn until_next_frame_counts_down() {
+    let mut scheduler = FrameScheduler::new(600);
+    let start = Instant::now();
+    scheduler.frames_due(start);
+    assert_eq!(scheduler.until_next_frame(), Duration::from_nanos(16_666_666));
+
+    scheduler.frames_due(start + Duration::from_millis(10));
+    assert_eq!(scheduler.until_next_frame(), Duration::from_nanos(6_666_666));
+}
+
+#[test]
+fn instructions_are_spread_evenly_over_frames() {
+    let mut scheduler = FrameScheduler::new(600);
+    assert!((0..60).all(|_| scheduler.cycles_for_frame() == 10));
+
+    // 1000 isn't a multiple of 60, so frames get 16 or 17 and the second adds up
+    let mut scheduler = FrameScheduler::new(1000);
+    let cycles: Vec<u32> = (0..60).map(|_| scheduler.cycles_for_frame()).collect();
+    assert!(cycles.iter().all(|count| *count == 16 || *count == 17));
+    assert_eq!(cycles.iter().sum::<u32>(), 1000);
+
+    let mut scheduler = FrameScheduler::new(30);
+    let cycles: Vec<u32> = (0..4).map(|_| scheduler.cycles_for_frame()).collect();
+    assert_eq!(cycles, [0, 1, 0, 1]);
+}
@@ -0,0 +1,66 @@
+// Screenshots: image encoding, scaling and file naming
+mod common;
+
+use std::env;
+use std::fs;
+use std::time::{Duration, UNIX_EPOCH};
+
+use chip8::image::{self, ImageFormat, DEFAULT_COLORS};
+use chip8::Quirks;
+use common::run_rom;
+
+#[test]
+fn ppm_screenshots() {
+    // Digit 0 at the top left corner
+    let chip8 = run_rom(Quirks::modern(), &rom![0xD005], 1);
+    let ppm = image::screenshot(&chip8.get_display(), &DEFAULT_COLORS, 1, ImageFormat::Ppm);
+
+    let header = b"P6\n64 32\n255\n";
+    assert_eq!(&ppm[..header.len()], header);
+    assert_eq!(ppm.len(), header.len() + 64 * 32 * 3);
+    // The top row of the 0 is four lit pixels
+    assert_eq!(&ppm[header.len()..header.len() + 15], &[255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 0, 0, 0]);
+}
+
+#[test]
+fn scaled_png_screenshots() {
+    let chip8 = run_rom(Quirks::modern(), &rom![0x00E0], 1);
+    let png = image::screenshot(&chip8.get_display(), &DEFAULT_COLORS, 3, ImageFormat::Png);
+
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    // IHDR width and height
+    assert_eq!(&png[16..24], &[0, 0, 0, 192, 0, 0, 0, 96]);
+}
+
+#[test]
+fn timestamps() {
+    assert_eq!(image::timestamp(UNIX_EPOCH), "19700101-000000");
+    assert_eq!(image::timestamp(UNIX_EPOCH + Duration::from_secs(951_827_696)), "20000229-123456");
+    assert_eq!(image::timestamp(UNIX_EPOCH + Duration::from_secs(1_792_367_999)), "20261018-235959");
+}
+
+#[test]
+fn screenshot_files_are_named_after_the_rom() {
+    let dir = env::temp_dir().join(format!("chip8-screenshots-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let rom = dir.join("pong.ch8");
+    let time = UNIX_EPOCH + Duration::from_secs(951_827_696);
+
+    let chip8 = run_rom(Quirks::modern(), &rom![0x00E0], 1);
+    let display = chip8.get_display();
+    let first = image::save_screenshot(&display, &DEFAULT_COLORS, 1, ImageFormat::Png, &rom, time).unwrap();
+    let second = image::save_screenshot(&display, &DEFAULT_COLORS, 1, ImageFormat::Png, &rom, time).unwrap();
+
+    assert_eq!(first, dir.join("pong-20000229-123456.png"));
+    assert_eq!(second, dir.join("pong-20000229-123456-2.png"));
+    assert!(fs::read(&second).unwrap().starts_with(b"\x89PNG"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn image_formats() {
+    assert_eq!("PPM".parse::<ImageFormat>(), Ok(ImageFormat::Ppm));
+    assert_eq!(ImageFormat::Png.extension(), "png");
+    assert!("gif".parse::<ImageFormat>().is_err());
+}
@@ -0,0 +1,121 @@
+// Save states: restoring a machine exactly, and refusing anything that isn't a state we wrote
+mod common;
+
+use chip8::state::STATE_VERSION;
+use chip8::{Chip8, Chip8Error, Quirks, HIRES_HEIGHT, HIRES_WIDTH, MEMORY_SIZE};
+use common::{machine, run, screen};
+
+// Leaves registers, memory, both timers, the stack and the screen all set
+fn busy_machine() -> Chip8 {
+    let mut chip8 = machine(Quirks::modern(), &rom![
+        0x6005, // LD V0, 5
+        0x610A, // LD V1, 10
+        0xA300, // LD I, 0x300
+        0xF155, // LD [I], V1
+        0xF015, // LD DT, V0
+        0xF118, // LD ST, V1
+        0x2210, // CALL 0x210
+        0x120E, // JP 0x20E
+        0xD015, // DRW V0, V1, 5
+        0x1212, // JP 0x212
+    ]);
+    run(&mut chip8, 9);
+    chip8.keypress(0xC, true);
+    chip8
+}
+
+// Where a few fields sit in the format: magic, version, opcode, opcode_pc, memory,
+// V registers, screen, hires and exited come before the plane mask
+const PLANES: usize = 4 + 2 + 2 + 2 + MEMORY_SIZE + 16 + HIRES_WIDTH * HIRES_HEIGHT + 1 + 1;
+// Then RPL flags, the audio pattern and pitch, I, PC and both timers
+const STACK_POINTER: usize = PLANES + 1 + 16 + 16 + 1 + 2 + 2 + 1 + 1;
+
+#[test]
+fn save_and_load_round_trip() {
+    let chip8 = busy_machine();
+    let state = chip8.save_state();
+
+    let mut restored = machine(Quirks::modern(), &rom![0x00E0]);
+    restored.load_state(&state).unwrap();
+
+    assert_eq!(restored.registers(), chip8.registers());
+    assert_eq!((restored.index_register(), restored.pc()), (0x300, 0x212));
+    assert_eq!(restored.stack(), &[0x20E]);
+    assert_eq!(&restored.memory()[0x300..0x302], &[5, 10]);
+    assert_eq!((restored.delay_timer(), restored.sound_timer()), (5, 10));
+    assert!(restored.keys()[0xC]);
+    assert_eq!(screen(&restored, 5, 10, 4, 5), screen(&chip8, 5, 10, 4, 5));
+    assert_eq!(restored.get_display().pixels, chip8.get_display().pixels);
+    assert_eq!(restored.save_state(), state);
+}
+
+#[test]
+fn states_from_another_version_are_refused() {
+    let mut state = busy_machine().save_state();
+    state[4..6].copy_from_slice(&(STATE_VERSION + 1).to_le_bytes());
+
+    let mut chip8 = machine(Quirks::modern(), &rom![0x00E0]);
+    let error = chip8.load_state(&state).unwrap_err();
+    assert!(matches!(error, Chip8Error::IncompatibleState { version, expected, pc: 0x200 }
+        if version == STATE_VERSION + 1 && expected == STATE_VERSION));
+}
+
+#[test]
+fn garbage_and_truncated_states_are_refused() {
+    let state = busy_machine().save_state();
+    let mut chip8 = machine(Quirks::modern(), &rom![0x00E0]);
+
+    let mut trailing = state.clone();
+    trailing.push(0);
+    for data in [&b"not a state at all"[..], &state[..6], &state[..state.len() - 1], &trailing] {
+        assert!(matches!(chip8.load_state(data), Err(Chip8Error::InvalidState { .. })));
+    }
+
+    // A failed load leaves the machine as it was
+    assert_eq!(chip8.pc(), 0x200);
+    assert_eq!(chip8.memory()[0x200], 0x00);
+}
+
+#[test]
+fn out_of_range_fields_are_refused() {
+    let state = busy_machine().save_state();
+    let mut chip8 = machine(Quirks::modern(), &rom![0x00E0]);
+
+    // The offsets are right, the untouched state has one call on the stack and plane 1
+    assert_eq!(state[STACK_POINTER..STACK_POINTER + 2], [1, 0]);
+    assert_eq!(state[PLANES], 1);
+
+    let mut stack_pointer = state.clone();
+    stack_pointer[STACK_POINTER..STACK_POINTER + 2].copy_from_slice(&17u16.to_le_bytes());
+    let mut planes = state.clone();
+    planes[PLANES] = 4;
+    let mut hires = state.clone();
+    hires[PLANES - 2] = 2;
+
+    for data in [stack_pointer, planes, hires] {
+        assert!(matches!(chip8.load_state(&data), Err(Chip8Error::InvalidState { .. })));
+    }
+}
+
+#[test]
+fn random_numbers_pick_up_from_a_save_state() {
+    let mut chip8 = machine(Quirks::modern(), &rom![0xC0FF, 0xC1FF, 0xC2FF, 0x1200]);
+    chip8.seed(1234);
+    run(&mut chip8, 8);
+    let state = chip8.save_state();
+
+    let mut later = Vec::new();
+    for _ in 0..4 {
+        run(&mut chip8, 4);
+        later.push(*chip8.registers());
+    }
+
+    // A reseeded generator would repeat from the start, a restored one carries on
+    chip8.seed(1234);
+    chip8.load_state(&state).unwrap();
+    for registers in &later {
+        run(&mut chip8, 4);
+        assert_eq!(chip8.registers(), registers);
+    }
+    assert_ne!(later[0], later[1]);
+}
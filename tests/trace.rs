@@ -0,0 +1,119 @@
+// The opt-in instruction tracer: records, filters, output formats and the history
+mod common;
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use chip8::trace::Change;
+use chip8::{OpcodeClass, Quirks, TraceFilter, TraceFormat, Tracer};
+use chip8::debugger::Register;
+use common::machine;
+
+// A Write that can still be read after the tracer owns it
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn lines(&self) -> Vec<String> {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap().lines().map(String::from).collect()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn records_register_and_index_changes() {
+    let mut chip8 = machine(Quirks::modern(), &rom![0x6005, 0xA300, 0xF033, 0x7001, 0x1208]);
+    chip8.set_tracer(Tracer::new().keep_last(16));
+    common::run(&mut chip8, 4);
+
+    let history: Vec<_> = chip8.tracer().unwrap().history().cloned().collect();
+    assert_eq!(history.len(), 4);
+    assert_eq!((history[0].pc, history[0].opcode), (0x200, 0x6005));
+    assert_eq!(history[0].changes, vec![Change { register: Register::V(0), old: 0, new: 5 }]);
+    assert_eq!(history[1].changes, vec![Change { register: Register::I, old: 0, new: 0x300 }]);
+    assert!(history[2].changes.is_empty());
+
+    assert_eq!(
+        chip8.tracer().unwrap().dump(),
+        "200: 6005  LD V0, 0x05        v0 0x00 -> 0x05\n\
+         202: A300  LD I, 0x300        i 0x000 -> 0x300\n\
+         204: F033  LD B, V0\n\
+         206: 7001  ADD V0, 0x01       v0 0x05 -> 0x06"
+    );
+}
+
+#[test]
+fn history_keeps_only_the_last_instructions() {
+    // Counts up in v0 forever
+    let mut chip8 = machine(Quirks::modern(), &rom![0x7001, 0x1200]);
+    chip8.set_tracer(Tracer::new().keep_last(3));
+    common::run(&mut chip8, 20);
+
+    let pcs: Vec<u16> = chip8.tracer().unwrap().history().map(|record| record.pc).collect();
+    assert_eq!(pcs, vec![0x202, 0x200, 0x202]);
+}
+
+#[test]
+fn failing_instructions_are_recorded() {
+    let mut chip8 = machine(Quirks::modern(), &rom![0x6001, 0x5121]);
+    chip8.set_tracer(Tracer::new().keep_last(8));
+    chip8.cycle().unwrap();
+    assert!(chip8.cycle().is_err());
+
+    let dump = chip8.tracer().unwrap().dump();
+    assert_eq!(dump.lines().last(), Some("202: 5121  ???"));
+}
+
+#[test]
+fn filters_by_address_and_class() {
+    let output = SharedBuffer::default();
+    let filter = TraceFilter { start: 0x202, end: 0x208, classes: vec![OpcodeClass::Arithmetic, OpcodeClass::Flow] };
+    let tracer = Tracer::new().filter(filter).write_to(Box::new(output.clone()), TraceFormat::Text).keep_last(10);
+
+    // 200: v0 := 1, 202: i := 0x300, 204: v1 := 2, 206: skip, 20A: jump to 0x20A
+    let mut chip8 = machine(Quirks::modern(), &rom![0x6001, 0xA300, 0x6102, 0x3102, 0x0000, 0x120A]);
+    chip8.set_tracer(tracer);
+    common::run(&mut chip8, 6);
+
+    let lines = output.lines();
+    assert_eq!(lines.len(), 2, "{:?}", lines);
+    assert!(lines[0].starts_with("204: 6102  LD V1, 0x02"));
+    assert!(lines[1].starts_with("206: 3102  SE V1, 0x02"));
+
+    // The history ignores the filter
+    assert_eq!(chip8.tracer().unwrap().history().count(), 6);
+}
+
+#[test]
+fn writes_json_lines() {
+    let output = SharedBuffer::default();
+    let mut chip8 = machine(Quirks::modern(), &rom![0x6005, 0x1202]);
+    chip8.set_tracer(Tracer::new().write_to(Box::new(output.clone()), TraceFormat::JsonLines));
+    common::run(&mut chip8, 2);
+    chip8.take_tracer().unwrap().flush().unwrap();
+
+    assert_eq!(
+        output.lines(),
+        vec![
+            r#"{"pc":512,"opcode":"6005","mnemonic":"LD V0, 0x05","changes":[{"register":"v0","old":0,"new":5}]}"#,
+            r#"{"pc":514,"opcode":"1202","mnemonic":"JP 0x202","changes":[]}"#,
+        ]
+    );
+}
+
+#[test]
+fn parses_classes_and_formats() {
+    assert_eq!("ALU".parse::<OpcodeClass>(), Ok(OpcodeClass::Arithmetic));
+    assert_eq!("json".parse::<TraceFormat>(), Ok(TraceFormat::JsonLines));
+    assert!("sound".parse::<OpcodeClass>().is_err());
+}